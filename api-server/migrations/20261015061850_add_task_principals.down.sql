@@ -0,0 +1,2 @@
+ALTER TABLE task DROP COLUMN IF EXISTS reprocessed_by;
+ALTER TABLE task DROP COLUMN IF EXISTS created_by;
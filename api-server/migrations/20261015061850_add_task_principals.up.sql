@@ -0,0 +1,2 @@
+ALTER TABLE task ADD COLUMN IF NOT EXISTS created_by text;
+ALTER TABLE task ADD COLUMN IF NOT EXISTS reprocessed_by text;
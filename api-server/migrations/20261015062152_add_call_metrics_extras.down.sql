@@ -0,0 +1 @@
+ALTER TABLE task_call_metrics DROP COLUMN IF EXISTS extras;
@@ -0,0 +1 @@
+ALTER TABLE task_call_metrics ADD COLUMN IF NOT EXISTS extras jsonb DEFAULT '{}' NOT NULL;
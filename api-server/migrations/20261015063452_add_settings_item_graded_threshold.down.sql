@@ -0,0 +1 @@
+ALTER TABLE settings_item DROP COLUMN IF EXISTS graded_threshold;
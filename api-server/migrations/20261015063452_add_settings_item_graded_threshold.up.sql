@@ -0,0 +1 @@
+ALTER TABLE settings_item ADD COLUMN IF NOT EXISTS graded_threshold real;
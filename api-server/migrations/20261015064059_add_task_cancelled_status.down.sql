@@ -0,0 +1,6 @@
+UPDATE task SET status = 'failed' WHERE status = 'cancelled';
+
+ALTER TYPE task_result_status RENAME TO task_result_status_old;
+CREATE TYPE task_result_status AS ENUM ('processing', 'ready', 'failed');
+ALTER TABLE task ALTER COLUMN status TYPE task_result_status USING status::text::task_result_status;
+DROP TYPE task_result_status_old;
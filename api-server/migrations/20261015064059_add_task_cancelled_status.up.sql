@@ -0,0 +1 @@
+ALTER TYPE task_result_status ADD VALUE IF NOT EXISTS 'cancelled';
@@ -0,0 +1,2 @@
+ALTER TABLE task DROP COLUMN IF EXISTS failure_class;
+DROP TYPE IF EXISTS task_failure_class;
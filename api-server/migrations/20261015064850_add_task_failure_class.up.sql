@@ -0,0 +1,7 @@
+DO $$ BEGIN
+    CREATE TYPE task_failure_class AS ENUM ('transient', 'permanent');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+ALTER TABLE task ADD COLUMN IF NOT EXISTS failure_class task_failure_class;
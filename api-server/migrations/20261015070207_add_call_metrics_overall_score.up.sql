@@ -0,0 +1 @@
+ALTER TABLE task_call_metrics ADD COLUMN IF NOT EXISTS overall_score integer NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE task_call_metrics DROP COLUMN IF EXISTS avg_employee_segment_len;
+ALTER TABLE task_call_metrics DROP COLUMN IF EXISTS avg_client_segment_len;
@@ -0,0 +1,2 @@
+ALTER TABLE task_call_metrics ADD COLUMN IF NOT EXISTS avg_employee_segment_len real NOT NULL DEFAULT 0;
+ALTER TABLE task_call_metrics ADD COLUMN IF NOT EXISTS avg_client_segment_len real NOT NULL DEFAULT 0;
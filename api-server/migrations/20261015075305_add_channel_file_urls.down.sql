@@ -0,0 +1,3 @@
+ALTER TABLE call_metadata
+    DROP COLUMN IF EXISTS left_channel_url,
+    DROP COLUMN IF EXISTS right_channel_url;
@@ -0,0 +1,3 @@
+ALTER TABLE call_metadata
+    ADD COLUMN IF NOT EXISTS left_channel_url TEXT,
+    ADD COLUMN IF NOT EXISTS right_channel_url TEXT;
@@ -0,0 +1,14 @@
+DO $$ BEGIN
+    CREATE TYPE task_failure_kind AS ENUM (
+        'asr_timeout',
+        'asr_rejected',
+        'no_speech',
+        'index_error',
+        'invalid_metadata',
+        'unknown'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+ALTER TABLE task ADD COLUMN IF NOT EXISTS failure_kind task_failure_kind;
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS call_metadata_project_id_file_hash_key;
+
+ALTER TABLE call_metadata ADD CONSTRAINT call_metadata_file_hash_key UNIQUE (file_hash);
+
+ALTER TABLE call_metadata DROP COLUMN IF EXISTS project_id;
@@ -0,0 +1,12 @@
+ALTER TABLE call_metadata
+    ADD COLUMN IF NOT EXISTS project_id UUID DEFAULT '00000000-0000-0000-0000-000000000000' NOT NULL;
+
+UPDATE call_metadata
+SET project_id = task.project_id
+FROM task
+WHERE task.call_metadata_id = call_metadata.id;
+
+ALTER TABLE call_metadata DROP CONSTRAINT IF EXISTS call_metadata_file_hash_key;
+
+CREATE UNIQUE INDEX IF NOT EXISTS call_metadata_project_id_file_hash_key
+    ON call_metadata (project_id, file_hash);
@@ -0,0 +1 @@
+ALTER TABLE dictionary DROP COLUMN IF EXISTS any_speaker;
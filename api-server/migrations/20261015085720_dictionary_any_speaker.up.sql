@@ -0,0 +1 @@
+ALTER TABLE dictionary ADD COLUMN IF NOT EXISTS any_speaker boolean NOT NULL DEFAULT false;
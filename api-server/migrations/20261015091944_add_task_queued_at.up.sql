@@ -0,0 +1,6 @@
+ALTER TABLE task ADD COLUMN IF NOT EXISTS queued_at timestamptz NOT NULL DEFAULT now();
+
+UPDATE task
+SET queued_at = call_metadata.uploaded_at
+FROM call_metadata
+WHERE call_metadata.id = task.call_metadata_id;
@@ -0,0 +1 @@
+-- the backfilled scores are kept, the column itself is dropped by its own migration
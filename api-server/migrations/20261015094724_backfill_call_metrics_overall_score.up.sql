@@ -0,0 +1,5 @@
+-- metrics stored before the overall score was introduced got the column default,
+-- they are scored with the default weights of 0.5 for the script and the quality scores
+UPDATE task_call_metrics
+SET overall_score = round((script_score + employee_quality_score) / 2.0)
+WHERE overall_score = 0;
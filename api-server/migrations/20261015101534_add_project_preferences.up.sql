@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS project_preferences (
+    project_id uuid PRIMARY KEY,
+    dictionary_import_participant participant_type
+);
@@ -0,0 +1 @@
+ALTER TABLE project_preferences DROP COLUMN IF EXISTS emotion_recognition;
@@ -0,0 +1,6 @@
+ALTER TABLE project_preferences ADD COLUMN IF NOT EXISTS emotion_recognition bool NOT NULL DEFAULT false;
+
+-- projects set up so far keep having their emotions recognized
+INSERT INTO project_preferences (project_id, emotion_recognition)
+SELECT DISTINCT project_id, true FROM settings
+ON CONFLICT (project_id) DO UPDATE SET emotion_recognition = true;
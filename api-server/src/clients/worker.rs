@@ -1,11 +1,17 @@
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 #[cfg(test)]
 use mockall::{automock, predicate::*};
-use protocol::entity::speech_recog::RecognitionData;
+use protocol::entity::{
+    dictionary_test::{DictionaryTestRequest, PhraseMatch},
+    speech_recog::MetricsIntervals,
+};
+use serde::Deserialize;
 use thiserror::Error;
 use tracing::error;
 use url::Url;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::config::HttpClientConfig;
@@ -24,15 +30,45 @@ pub enum WorkerClientError {
     ReqwestError(#[source] reqwest::Error),
 }
 
+/// Body of a transcript rendered by the worker, read as it arrives
+pub type RenderedTranscript = BoxStream<'static, Result<Bytes, WorkerClientError>>;
+
+/// Transcript rendered by the worker
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptFormat {
+    #[default]
+    Text,
+    Srt,
+}
+
+impl TranscriptFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TranscriptFormat::Text => "text",
+            TranscriptFormat::Srt => "srt",
+        }
+    }
+}
+
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait WorkerClient {
     async fn raw_transcript_by_id(&self, task_id: Uuid) -> Result<Bytes, WorkerClientError>;
-    async fn transcript_by_id(&self, task_id: Uuid) -> Result<RecognitionData, WorkerClientError> {
-        let bytes_res = self.raw_transcript_by_id(task_id).await?;
-        let recog_data = serde_json::from_slice(&bytes_res).map_err(WorkerClientError::De)?;
-        Ok(recog_data)
-    }
+    async fn rendered_transcript_by_id(
+        &self,
+        task_id: Uuid,
+        format: TranscriptFormat,
+    ) -> Result<RenderedTranscript, WorkerClientError>;
+    async fn metrics_intervals_by_id(
+        &self,
+        task_id: Uuid,
+    ) -> Result<MetricsIntervals, WorkerClientError>;
+    async fn test_dictionary(
+        &self,
+        dict_id: i32,
+        request: DictionaryTestRequest,
+    ) -> Result<Vec<PhraseMatch>, WorkerClientError>;
 }
 
 #[derive(Clone)]
@@ -48,15 +84,19 @@ impl HttpWorkerClient {
         if let Some(timeout) = config.timeout {
             builder = builder.timeout(timeout);
         }
+        if let Some(max_idle) = config.pool.max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = config.pool.idle_timeout {
+            builder = builder.pool_idle_timeout(idle_timeout);
+        }
+        builder = builder.tcp_keepalive(config.pool.tcp_keepalive);
 
-        builder.build().map_err(WorkerClientError::Channel)?;
+        let client = builder.build().map_err(WorkerClientError::Channel)?;
 
         let base_url = Url::parse(&config.url).map_err(WorkerClientError::BaseUrl)?;
 
-        Ok(Self {
-            client: reqwest::Client::new(),
-            base_url,
-        })
+        Ok(Self { client, base_url })
     }
 }
 
@@ -78,4 +118,79 @@ impl WorkerClient for HttpWorkerClient {
             otherwise => Err(WorkerClientError::ResponseStatus(otherwise)),
         }
     }
+
+    async fn rendered_transcript_by_id(
+        &self,
+        task_id: Uuid,
+        format: TranscriptFormat,
+    ) -> Result<RenderedTranscript, WorkerClientError> {
+        let mut req_url = self.base_url.clone();
+        req_url.set_path(&format!("api/v1/transcript/{task_id}"));
+        req_url
+            .query_pairs_mut()
+            .append_pair("format", format.as_str());
+
+        let res = self
+            .client
+            .get(req_url)
+            .send()
+            .await
+            .map_err(WorkerClientError::Channel)?;
+
+        match res.status() {
+            reqwest::StatusCode::OK => Ok(res
+                .bytes_stream()
+                .map_err(WorkerClientError::ReqwestError)
+                .boxed()),
+            otherwise => Err(WorkerClientError::ResponseStatus(otherwise)),
+        }
+    }
+
+    async fn metrics_intervals_by_id(
+        &self,
+        task_id: Uuid,
+    ) -> Result<MetricsIntervals, WorkerClientError> {
+        let mut req_url = self.base_url.clone();
+        req_url.set_path(&format!("api/v1/transcript/{task_id}/intervals"));
+
+        let res = self
+            .client
+            .get(req_url)
+            .send()
+            .await
+            .map_err(WorkerClientError::Channel)?;
+
+        match res.status() {
+            reqwest::StatusCode::OK => {
+                let bytes_res = res.bytes().await.map_err(WorkerClientError::ReqwestError)?;
+                serde_json::from_slice(&bytes_res).map_err(WorkerClientError::De)
+            }
+            otherwise => Err(WorkerClientError::ResponseStatus(otherwise)),
+        }
+    }
+
+    async fn test_dictionary(
+        &self,
+        dict_id: i32,
+        request: DictionaryTestRequest,
+    ) -> Result<Vec<PhraseMatch>, WorkerClientError> {
+        let mut req_url = self.base_url.clone();
+        req_url.set_path(&format!("api/v1/dictionaries/{dict_id}/test"));
+
+        let res = self
+            .client
+            .post(req_url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(WorkerClientError::Channel)?;
+
+        match res.status() {
+            reqwest::StatusCode::OK => {
+                let bytes_res = res.bytes().await.map_err(WorkerClientError::ReqwestError)?;
+                serde_json::from_slice(&bytes_res).map_err(WorkerClientError::De)
+            }
+            otherwise => Err(WorkerClientError::ResponseStatus(otherwise)),
+        }
+    }
 }
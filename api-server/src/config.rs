@@ -3,10 +3,138 @@ use std::{net::SocketAddr, time::Duration};
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug, Clone)]
-pub(crate) struct Config {
+pub struct Config {
     pub db: DbConnectionConfig,
     pub http: HttpConfig,
     pub worker_app: HttpClientConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default = "default_list_default_limit")]
+    pub list_default_limit: i64,
+    #[serde(default = "default_list_max_limit")]
+    pub list_max_limit: i64,
+    /// Allowed `file_url` locations, a bare scheme (`s3://`) or a scheme with a host/bucket and
+    /// optionally a path, hosts and path segments are matched as a whole
+    #[serde(default = "default_file_url_allowlist")]
+    pub file_url_allowlist: Vec<String>,
+    /// Number of manifest lines inserted per transaction during bulk import
+    #[serde(default = "default_import_batch_size")]
+    pub import_batch_size: usize,
+    #[serde(default)]
+    pub stuck_tasks: StuckTasksConfig,
+    #[serde(default)]
+    pub request_log: RequestLogConfig,
+    /// Tasks exported by a single detailed export request at most
+    #[serde(default = "default_detailed_export_max_tasks")]
+    pub detailed_export_max_tasks: usize,
+    /// Decimals kept in metric floats of responses, stored metrics keep full precision
+    #[serde(default = "default_metrics_precision")]
+    pub metrics_precision: u32,
+}
+
+fn default_list_default_limit() -> i64 {
+    20
+}
+
+fn default_list_max_limit() -> i64 {
+    100
+}
+
+fn default_detailed_export_max_tasks() -> usize {
+    1000
+}
+
+fn default_metrics_precision() -> u32 {
+    2
+}
+
+fn default_import_batch_size() -> usize {
+    100
+}
+
+fn default_file_url_allowlist() -> Vec<String> {
+    vec!["s3://".to_string()]
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CorsConfig {
+    /// Response headers readable by browser clients in addition to `Content-Disposition`
+    #[serde(default)]
+    pub expose_headers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RequestLogConfig {
+    /// Logs the request body of 4xx/5xx responses, the head of every request body is
+    /// buffered when enabled
+    #[serde(default)]
+    pub capture_error_bodies: bool,
+    /// At most this many bytes of a body are buffered, longer bodies aren't logged
+    #[serde(default = "default_max_logged_body_bytes")]
+    pub max_body_bytes: usize,
+    /// JSON fields whose values are replaced before logging
+    #[serde(default = "default_redacted_fields")]
+    pub redacted_fields: Vec<String>,
+}
+
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        Self {
+            capture_error_bodies: false,
+            max_body_bytes: default_max_logged_body_bytes(),
+            redacted_fields: default_redacted_fields(),
+        }
+    }
+}
+
+fn default_max_logged_body_bytes() -> usize {
+    1024
+}
+
+fn default_redacted_fields() -> Vec<String> {
+    ["password", "token", "api_key", "secret"]
+        .map(String::from)
+        .to_vec()
+}
+
+/// What to do with a task left `processing` by a crashed worker
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StuckTaskAction {
+    #[default]
+    Requeue,
+    Fail,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StuckTasksConfig {
+    /// Time without progress after which a task is considered stuck, a task never picked up
+    /// is timed out since it was queued. Workers refresh running tasks every minute, so it has
+    /// to be longer than that
+    #[serde(with = "humantime_serde", default = "default_stuck_timeout")]
+    pub timeout: Duration,
+    #[serde(with = "humantime_serde", default = "default_stuck_sweep_interval")]
+    pub sweep_interval: Duration,
+    #[serde(default)]
+    pub action: StuckTaskAction,
+}
+
+impl Default for StuckTasksConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_stuck_timeout(),
+            sweep_interval: default_stuck_sweep_interval(),
+            action: StuckTaskAction::default(),
+        }
+    }
+}
+
+fn default_stuck_timeout() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_stuck_sweep_interval() -> Duration {
+    Duration::from_secs(5 * 60)
 }
 
 #[derive(Clone, Debug, serde::Deserialize)]
@@ -17,6 +145,20 @@ pub struct DbConnectionConfig {
     pub timeout: Duration,
     #[serde(with = "humantime_serde")]
     pub max_lifetime: Duration,
+    /// Extra acquire attempts when the pool is exhausted, each waits up to `timeout`
+    #[serde(default = "default_acquire_retries")]
+    pub acquire_retries: u32,
+    /// Delay before the first retry, doubled on every next one
+    #[serde(with = "humantime_serde", default = "default_acquire_retry_backoff")]
+    pub acquire_retry_backoff: Duration,
+}
+
+fn default_acquire_retries() -> u32 {
+    2
+}
+
+fn default_acquire_retry_backoff() -> Duration {
+    Duration::from_millis(50)
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -29,6 +171,21 @@ pub struct HttpClientConfig {
     pub url: String,
     #[serde(with = "humantime_serde")]
     pub timeout: Option<Duration>,
+    #[serde(default)]
+    pub pool: HttpPoolConfig,
+}
+
+/// Connection reuse of an HTTP client, reqwest defaults apply to unset values
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HttpPoolConfig {
+    /// Idle connections kept open per host
+    pub max_idle_per_host: Option<usize>,
+    /// Idle connections are closed after this long
+    #[serde(with = "humantime_serde", default)]
+    pub idle_timeout: Option<Duration>,
+    /// TCP keep-alive interval, disabled when unset
+    #[serde(with = "humantime_serde", default)]
+    pub tcp_keepalive: Option<Duration>,
 }
 
 pub fn load() -> Result<Config, config::ConfigError> {
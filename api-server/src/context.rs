@@ -5,12 +5,35 @@ use sqlx::pool::PoolConnection;
 use sqlx::{PgPool, Postgres};
 
 use crate::clients::worker::{HttpWorkerClient, WorkerClient};
-use crate::config::Config;
+use crate::config::{Config, DbConnectionConfig};
 use crate::error::{Error, ErrorExt, ErrorKind};
 
+/// Positions of the payloads of a batch which didn't reach the broker
+#[derive(Debug)]
+pub struct BatchPublishError {
+    pub unpublished: Vec<usize>,
+    pub error: Error,
+}
+
+impl BatchPublishError {
+    /// Picks the unpublished payloads out of the published batch
+    pub fn unpublished<T: Clone>(&self, payloads: &[T]) -> Vec<T> {
+        self.unpublished
+            .iter()
+            .map(|idx| payloads[*idx].clone())
+            .collect()
+    }
+}
+
 #[async_trait]
 pub trait TaskPublisher {
     async fn publish<T: serde::Serialize + Sync>(&self, payload: &T) -> Result<(), Error>;
+    /// Publishes all payloads before waiting for the broker confirms once,
+    /// a failure reports which payloads weren't published
+    async fn publish_batch<T: serde::Serialize + Sync>(
+        &self,
+        payloads: &[T],
+    ) -> Result<(), BatchPublishError>;
 }
 
 #[async_trait]
@@ -28,6 +51,64 @@ impl TaskPublisher for Channel {
 
         Ok(())
     }
+
+    async fn publish_batch<T: serde::Serialize + Sync>(
+        &self,
+        payloads: &[T],
+    ) -> Result<(), BatchPublishError> {
+        let mut confirms = Vec::with_capacity(payloads.len());
+        let mut publish_error = None;
+        for payload in payloads {
+            let published = async {
+                let payload = serde_json::to_vec(payload).error(ErrorKind::SerializationFailed)?;
+                self.basic_publish(
+                    "task_exchanger",
+                    "task",
+                    BasicPublishOptions::default(),
+                    &payload,
+                    BasicProperties::default(),
+                )
+                .await
+                .error(ErrorKind::AMQPError)
+            }
+            .await;
+            match published {
+                Ok(confirm) => confirms.push(confirm),
+                // the rest of the batch is not published after a failure
+                Err(err) => {
+                    publish_error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        // confirms are awaited for the published prefix of the batch only
+        let published = confirms.len();
+        let mut unpublished: Vec<usize> = futures::future::join_all(confirms)
+            .await
+            .into_iter()
+            .enumerate()
+            .filter(|(_, confirmation)| !matches!(confirmation, Ok(c) if !c.is_nack()))
+            .map(|(idx, _)| idx)
+            .collect();
+        unpublished.extend(published..payloads.len());
+
+        if unpublished.is_empty() {
+            return Ok(());
+        }
+
+        let error = publish_error.unwrap_or_else(|| {
+            Error::new(
+                ErrorKind::AMQPError,
+                anyhow::anyhow!(
+                    "{} of {} published tasks were rejected",
+                    unpublished.len(),
+                    payloads.len()
+                ),
+            )
+        });
+        Err(BatchPublishError { unpublished, error })
+    }
 }
 
 #[async_trait]
@@ -39,6 +120,8 @@ pub trait Context {
 
     fn worker_client(&self) -> &Self::WorkerClient;
 
+    fn config(&self) -> &Config;
+
     async fn get_db_conn(&self) -> Result<PoolConnection<Postgres>, Error>;
 }
 
@@ -47,6 +130,7 @@ pub struct AppContext {
     db: PgPool,
     channel: Channel,
     worker_client: HttpWorkerClient,
+    config: Config,
 }
 
 impl AppContext {
@@ -55,6 +139,7 @@ impl AppContext {
             db: pool,
             channel,
             worker_client: HttpWorkerClient::new(&config.worker_app)?,
+            config,
         })
     }
 }
@@ -72,8 +157,87 @@ impl Context for AppContext {
         &self.worker_client
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
     async fn get_db_conn(&self) -> Result<PoolConnection<Postgres>, Error> {
-        let conn = self.db.acquire().await?;
-        Ok(conn)
+        acquire_with_retry(&self.db, &self.config.db).await
+    }
+}
+
+/// Retries acquiring on pool timeouts only, so momentary pool exhaustion doesn't fail the request
+async fn acquire_with_retry(
+    pool: &PgPool,
+    config: &DbConnectionConfig,
+) -> Result<PoolConnection<Postgres>, Error> {
+    let mut backoff = config.acquire_retry_backoff;
+    let mut retries_left = config.acquire_retries;
+    loop {
+        match pool.acquire().await {
+            Err(sqlx::Error::PoolTimedOut) if retries_left > 0 => {
+                retries_left -= 1;
+                tracing::warn!("db pool exhausted, retrying acquire in {backoff:?}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+
+    fn tiny_pool(pool: &PgPool) -> PgPool {
+        PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(50))
+            .connect_lazy_with((*pool.connect_options()).clone())
+    }
+
+    fn db_config(acquire_retries: u32) -> DbConnectionConfig {
+        DbConnectionConfig {
+            size: 1,
+            idle_size: None,
+            timeout: Duration::from_millis(50),
+            max_lifetime: Duration::from_secs(60),
+            acquire_retries,
+            acquire_retry_backoff: Duration::from_millis(20),
+        }
+    }
+
+    #[sqlx::test]
+    async fn acquire_retries_under_contention(pool: PgPool) {
+        let pool = tiny_pool(&pool);
+        let config = db_config(5);
+
+        let requests = (0..4).map(|_| {
+            let pool = pool.clone();
+            let config = config.clone();
+            tokio::spawn(async move {
+                let mut conn = acquire_with_retry(&pool, &config).await?;
+                sqlx::query("SELECT pg_sleep(0.03)")
+                    .execute(&mut *conn)
+                    .await?;
+                Ok::<_, Error>(())
+            })
+        });
+        for result in futures::future::join_all(requests).await {
+            result
+                .unwrap()
+                .expect("request failed despite acquire retries");
+        }
+
+        let _held = pool.acquire().await.unwrap();
+        let err = acquire_with_retry(&pool, &db_config(0))
+            .await
+            .expect_err("unexpected acquire from exhausted pool");
+        assert_eq!(err.kind, ErrorKind::DbQueryFailed);
     }
 }
@@ -1,5 +1,5 @@
 use protocol::db::{metadata::CallMetadata, metrics::CallMetrics};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
@@ -11,6 +11,118 @@ pub struct MetricsWithMetadata {
     pub metrics: CallMetrics,
 }
 
+/// Columns the metrics list may be ordered by, the only ones interpolated into its query
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsColumn {
+    PerformedAt,
+    UploadedAt,
+    Duration,
+    CallId,
+    FileName,
+    EmployeeName,
+    ClientName,
+    CallDuration,
+    TimeToAnswer,
+    TotalEmployeeSpeech,
+    TotalClientSpeech,
+    EmployeeClientSpeechRatio,
+    EmployeeSpeechRatio,
+    ClientSpeechRatio,
+    CallHoldsCount,
+    SilencePauseCount,
+    TotalEmployeeSilence,
+    ClientInterruptionsCount,
+    TotalClientInterruptionsDuration,
+    AvgEmployeeWordsPerMin,
+    AvgClientWordsPerMin,
+    AvgEmployeeSegmentLen,
+    AvgClientSegmentLen,
+    ScriptScore,
+    EmployeeQualityScore,
+    OverallScore,
+}
+
+impl MetricsColumn {
+    pub const ALL: [MetricsColumn; 26] = [
+        MetricsColumn::PerformedAt,
+        MetricsColumn::UploadedAt,
+        MetricsColumn::Duration,
+        MetricsColumn::CallId,
+        MetricsColumn::FileName,
+        MetricsColumn::EmployeeName,
+        MetricsColumn::ClientName,
+        MetricsColumn::CallDuration,
+        MetricsColumn::TimeToAnswer,
+        MetricsColumn::TotalEmployeeSpeech,
+        MetricsColumn::TotalClientSpeech,
+        MetricsColumn::EmployeeClientSpeechRatio,
+        MetricsColumn::EmployeeSpeechRatio,
+        MetricsColumn::ClientSpeechRatio,
+        MetricsColumn::CallHoldsCount,
+        MetricsColumn::SilencePauseCount,
+        MetricsColumn::TotalEmployeeSilence,
+        MetricsColumn::ClientInterruptionsCount,
+        MetricsColumn::TotalClientInterruptionsDuration,
+        MetricsColumn::AvgEmployeeWordsPerMin,
+        MetricsColumn::AvgClientWordsPerMin,
+        MetricsColumn::AvgEmployeeSegmentLen,
+        MetricsColumn::AvgClientSegmentLen,
+        MetricsColumn::ScriptScore,
+        MetricsColumn::EmployeeQualityScore,
+        MetricsColumn::OverallScore,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|column| column.column() == name)
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            MetricsColumn::PerformedAt => "performed_at",
+            MetricsColumn::UploadedAt => "uploaded_at",
+            MetricsColumn::Duration => "duration",
+            MetricsColumn::CallId => "call_id",
+            MetricsColumn::FileName => "file_name",
+            MetricsColumn::EmployeeName => "employee_name",
+            MetricsColumn::ClientName => "client_name",
+            MetricsColumn::CallDuration => "call_duration",
+            MetricsColumn::TimeToAnswer => "time_to_answer",
+            MetricsColumn::TotalEmployeeSpeech => "total_employee_speech",
+            MetricsColumn::TotalClientSpeech => "total_client_speech",
+            MetricsColumn::EmployeeClientSpeechRatio => "employee_client_speech_ratio",
+            MetricsColumn::EmployeeSpeechRatio => "employee_speech_ratio",
+            MetricsColumn::ClientSpeechRatio => "client_speech_ratio",
+            MetricsColumn::CallHoldsCount => "call_holds_count",
+            MetricsColumn::SilencePauseCount => "silence_pause_count",
+            MetricsColumn::TotalEmployeeSilence => "total_employee_silence",
+            MetricsColumn::ClientInterruptionsCount => "client_interruptions_count",
+            MetricsColumn::TotalClientInterruptionsDuration => {
+                "total_client_interruptions_duration"
+            }
+            MetricsColumn::AvgEmployeeWordsPerMin => "avg_employee_words_per_min",
+            MetricsColumn::AvgClientWordsPerMin => "avg_client_words_per_min",
+            MetricsColumn::AvgEmployeeSegmentLen => "avg_employee_segment_len",
+            MetricsColumn::AvgClientSegmentLen => "avg_client_segment_len",
+            MetricsColumn::ScriptScore => "script_score",
+            MetricsColumn::EmployeeQualityScore => "employee_quality_score",
+            MetricsColumn::OverallScore => "overall_score",
+        }
+    }
+
+    fn is_score(&self) -> bool {
+        matches!(
+            self,
+            MetricsColumn::ScriptScore
+                | MetricsColumn::EmployeeQualityScore
+                | MetricsColumn::OverallScore
+        )
+    }
+}
+
+/// Calls with too little speech are stored with zero scores and flagged in the extras
+const UNSCORED: &str = "COALESCE((extras->>'unscored')::boolean, false)";
+
 impl MetricsWithMetadata {
     pub async fn total_count(project_id: Uuid, conn: &mut sqlx::PgConnection) -> sqlx::Result<i64> {
         sqlx::query!(
@@ -30,11 +142,18 @@ impl MetricsWithMetadata {
     pub async fn metrics_list(
         offset: i64,
         limit: i64,
-        order_by: &str, // TODO: possible SQL injection, fix it
+        order_by: MetricsColumn,
         desc: bool,
         conn: &mut sqlx::PgConnection,
     ) -> sqlx::Result<Vec<MetricsWithMetadata>> {
         let desc = if desc { "DESC" } else { "ASC" };
+        let column = order_by.column();
+        // unscored calls keep zero scores, they go last instead of ranking as the worst
+        let order_by = if order_by.is_score() {
+            format!("CASE WHEN {UNSCORED} THEN NULL ELSE {column} END {desc} NULLS LAST")
+        } else {
+            format!("{column} {desc}")
+        };
 
         let query = format!(
             r#"
@@ -53,6 +172,8 @@ impl MetricsWithMetadata {
                client_name,
                employee_name,
                inbound,
+               left_channel_url,
+               right_channel_url,
                call_duration,
                time_to_answer,
                total_employee_speech,
@@ -67,15 +188,19 @@ impl MetricsWithMetadata {
                total_client_interruptions_duration,
                avg_employee_words_per_min,
                avg_client_words_per_min,
+               avg_employee_segment_len,
+               avg_client_segment_len,
                script_score,
                employee_quality_score,
+               overall_score,
                emotion_mode,
                emotion_start_mode,
-               emotion_end_mode
+               emotion_end_mode,
+               extras
             FROM call_metadata
             JOIN task ON task.call_metadata_id = call_metadata.id
             JOIN task_call_metrics ON task.id = task_call_metrics.task_id
-            ORDER BY {order_by} {desc}
+            ORDER BY {order_by}
             OFFSET {offset}
             LIMIT {limit}
             "#
@@ -105,6 +230,8 @@ impl MetricsWithMetadata {
                client_name,
                employee_name,
                inbound,
+               left_channel_url,
+               right_channel_url,
                call_duration,
                time_to_answer,
                total_employee_speech,
@@ -119,11 +246,15 @@ impl MetricsWithMetadata {
                total_client_interruptions_duration,
                avg_employee_words_per_min,
                avg_client_words_per_min,
+               avg_employee_segment_len,
+               avg_client_segment_len,
                script_score,
                employee_quality_score,
+               overall_score,
                emotion_mode,
                emotion_start_mode,
-               emotion_end_mode
+               emotion_end_mode,
+               extras
             FROM call_metadata
             JOIN task ON task.call_metadata_id = call_metadata.id
             JOIN task_call_metrics ON task.id = task_call_metrics.task_id
@@ -135,66 +266,117 @@ impl MetricsWithMetadata {
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateGroup {
+    Employee,
+    Client,
+}
+
+impl AggregateGroup {
+    fn column(&self) -> &'static str {
+        match self {
+            AggregateGroup::Employee => "employee_name",
+            AggregateGroup::Client => "client_name",
+        }
+    }
+}
+
+/// Average scores of the calls of one employee or client
+#[derive(Debug, PartialEq, Serialize, sqlx::FromRow, ToSchema)]
+pub struct MetricsAggregate {
+    pub name: String,
+    pub calls_count: i64,
+    pub avg_overall_score: f64,
+    pub avg_script_score: f64,
+    pub avg_employee_quality_score: f64,
+}
+
+impl MetricsAggregate {
+    /// Only the latest task of every call counts and unscored calls are left out,
+    /// groups with fewer than `min_calls` calls too, best overall scores go first
+    pub async fn list(
+        project_id: Uuid,
+        group: AggregateGroup,
+        min_calls: i64,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<MetricsAggregate>> {
+        let column = group.column();
+        let query = format!(
+            r#"
+            WITH latest_metrics AS (
+                SELECT DISTINCT ON (task.call_metadata_id)
+                    {column} as name,
+                    overall_score,
+                    script_score,
+                    employee_quality_score,
+                    {UNSCORED} as unscored
+                FROM call_metadata
+                JOIN task ON task.call_metadata_id = call_metadata.id
+                JOIN task_call_metrics ON task.id = task_call_metrics.task_id
+                WHERE task.project_id = $1
+                ORDER BY task.call_metadata_id, task.queued_at DESC
+            )
+            SELECT
+                name,
+                COUNT(1) as calls_count,
+                AVG(overall_score)::float8 as avg_overall_score,
+                AVG(script_score)::float8 as avg_script_score,
+                AVG(employee_quality_score)::float8 as avg_employee_quality_score
+            FROM latest_metrics
+            WHERE NOT unscored
+            GROUP BY name
+            HAVING COUNT(1) >= $2
+            ORDER BY avg_overall_score DESC, name
+            "#
+        );
+
+        sqlx::query_as(&query)
+            .bind(project_id)
+            .bind(min_calls)
+            .fetch_all(conn)
+            .await
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use chrono::Utc;
     use protocol::{
-        db::{
-            metadata::CallMetadata,
-            metrics::CallMetrics,
-            task::{Task, TaskResultKind},
-        },
-        entity::{speech_recog::EmotionKind, ParticipantKind},
+        db::{metrics::CallMetrics, task::TaskResultKind},
+        entity::speech_recog::EmotionKind,
     };
     use uuid::Uuid;
 
     use super::*;
+    use crate::test_helpers::insert_task;
+
+    #[test]
+    fn metrics_columns_named_as_published() {
+        for column in MetricsColumn::ALL {
+            assert_eq!(serde_json::to_value(column).unwrap(), column.column());
+            assert_eq!(MetricsColumn::parse(column.column()), Some(column));
+        }
+    }
 
     #[sqlx::test]
     async fn test_metrics_fetch(pool: sqlx::PgPool) {
         let mut conn = pool.acquire().await.unwrap();
-        let metadata = CallMetadata {
-            metadata_id: Uuid::default(),
-            call_id: 11i64,
-            performed_at: Utc::now(),
-            uploaded_at: Utc::now(),
-            file_hash: Uuid::new_v4().hyphenated().to_string(),
-            file_url: "s3://test_bucket/test.mp3".to_string(),
-            file_name: "test.mp3".to_string(),
-            duration: 15.0,
-            left_channel: ParticipantKind::Client,
-            right_channel: ParticipantKind::Employee,
-            client_name: "test_client".to_string(),
-            employee_name: "test_agent".to_string(),
-            inbound: true,
-        };
-        let metadata_id = metadata
-            .insert(&mut conn)
-            .await
-            .expect("failed to insert metadata")
-            .metadata_id;
-
-        let task = Task {
-            id: Uuid::default(),
-            call_metadata_id: metadata_id,
-            failed_reason: None,
-            project_id: Uuid::default(),
-            status: TaskResultKind::Ready,
-        };
-        let task_id = task
-            .insert(&mut conn)
-            .await
-            .expect("failed to insert task")
-            .id;
+        let mut task = insert_task(Uuid::default(), &mut conn).await;
+        task.status = TaskResultKind::Ready;
+        task.update(&mut conn).await.expect("failed to update task");
+        let task_id = task.id;
 
-        let mut metrics = CallMetrics::default();
-        metrics.task_id = task_id;
-        metrics.emotion_mode = Some(EmotionKind::Sad);
-        let _ = CallMetrics::insert(metrics, &mut conn).await.unwrap();
+        let metrics = CallMetrics {
+            task_id,
+            emotion_mode: Some(EmotionKind::Sad),
+            ..Default::default()
+        };
+        CallMetrics::insert(metrics, &mut conn).await.unwrap();
 
-        let metrics = MetricsWithMetadata::metrics_list(0, 10, "file_name", false, &mut conn)
-            .await
-            .expect("failed to retrieve tasks list");
+        let metrics =
+            MetricsWithMetadata::metrics_list(0, 10, MetricsColumn::FileName, false, &mut conn)
+                .await
+                .expect("failed to retrieve tasks list");
         let count = MetricsWithMetadata::total_count(Uuid::default(), &mut conn)
             .await
             .expect("failed to retrieve total count");
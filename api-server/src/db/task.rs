@@ -1,4 +1,8 @@
-use protocol::db::{metadata::CallMetadata, task::Task};
+use chrono::{DateTime, Utc};
+use protocol::db::{
+    metadata::CallMetadata,
+    task::{Task, TaskFailureKind},
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -12,14 +16,21 @@ pub struct TaskWithMetadata {
 }
 
 impl TaskWithMetadata {
-    pub async fn total_count(project_id: Uuid, conn: &mut sqlx::PgConnection) -> sqlx::Result<i64> {
+    pub async fn total_count(
+        project_id: Uuid,
+        has_metrics: Option<bool>,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<i64> {
         sqlx::query!(
             r#"
                 SELECT COUNT(1) as total
                 FROM task
+                LEFT JOIN task_call_metrics ON task_call_metrics.task_id = task.id
                 WHERE project_id = $1
+                    AND ($2::bool IS NULL OR (task_call_metrics.task_id IS NOT NULL) = $2)
             "#,
-            project_id
+            project_id,
+            has_metrics
         )
         .fetch_one(conn)
         .await
@@ -31,19 +42,30 @@ impl TaskWithMetadata {
         limit: i64,
         order_by: &str, // TODO: possible SQL injection, fix it
         desc: bool,
+        has_metrics: Option<bool>,
         conn: &mut sqlx::PgConnection,
     ) -> sqlx::Result<Vec<TaskWithMetadata>> {
         let desc = if desc { "DESC" } else { "ASC" };
+        let metrics_filter = match has_metrics {
+            Some(true) => "WHERE task_call_metrics.task_id IS NOT NULL",
+            Some(false) => "WHERE task_call_metrics.task_id IS NULL",
+            None => "",
+        };
 
         let query = format!(
             r#"
             SELECT
                 call_metadata.id as metadata_id,
                 task.id as id,
-                call_metadata_id,
+                task.call_metadata_id,
                 status,
                 failed_reason,
-                project_id,
+                failure_class,
+                failure_kind,
+                task.project_id,
+                created_by,
+                reprocessed_by,
+                attempts,
                 call_id,
                 performed_at,
                 uploaded_at,
@@ -55,9 +77,13 @@ impl TaskWithMetadata {
                 right_channel,
                 client_name,
                 employee_name,
-                inbound
+                inbound,
+                left_channel_url,
+                right_channel_url
             FROM task
             JOIN call_metadata ON task.call_metadata_id = call_metadata.id
+            LEFT JOIN task_call_metrics ON task_call_metrics.task_id = task.id
+            {metrics_filter}
             ORDER BY {order_by} {desc}
             OFFSET {offset}
             LIMIT {limit}
@@ -68,52 +94,117 @@ impl TaskWithMetadata {
     }
 }
 
+/// Failed tasks of one failure kind, tasks failed before kinds were recorded count as unknown
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct FailureCategory {
+    pub failure_kind: TaskFailureKind,
+    pub count: i64,
+    /// Latest failure reasons first
+    pub recent_reasons: Vec<String>,
+}
+
+impl FailureCategory {
+    /// Categories of failed tasks of calls uploaded within the given bounds, most frequent first
+    pub async fn summary(
+        project_id: Uuid,
+        uploaded_from: Option<DateTime<Utc>>,
+        uploaded_to: Option<DateTime<Utc>>,
+        reasons_limit: i32,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<FailureCategory>> {
+        sqlx::query_as!(
+            FailureCategory,
+            r#"
+            SELECT
+                COALESCE(failure_kind, 'unknown') as "failure_kind!: TaskFailureKind",
+                COUNT(1) as "count!",
+                COALESCE(
+                    (ARRAY_AGG(failed_reason ORDER BY started_at DESC NULLS LAST, uploaded_at DESC)
+                        FILTER (WHERE failed_reason IS NOT NULL))[1:$4],
+                    '{}'
+                ) as "recent_reasons!"
+            FROM task
+            JOIN call_metadata ON task.call_metadata_id = call_metadata.id
+            WHERE task.project_id = $1
+                AND status = 'failed'
+                AND ($2::timestamptz IS NULL OR uploaded_at >= $2)
+                AND ($3::timestamptz IS NULL OR uploaded_at < $3)
+            GROUP BY 1
+            ORDER BY 2 DESC, 1
+            "#,
+            project_id,
+            uploaded_from,
+            uploaded_to,
+            reasons_limit,
+        )
+        .fetch_all(conn)
+        .await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Utc;
-    use protocol::{db::task::TaskResultKind, entity::ParticipantKind};
+    use protocol::db::{metrics::CallMetrics, task::TaskResultKind};
     use uuid::Uuid;
 
+    use crate::test_helpers::insert_task;
+
     #[sqlx::test]
     async fn test_tasks_list(pool: sqlx::PgPool) {
         let mut conn = pool.acquire().await.unwrap();
-        let metadata = CallMetadata {
-            metadata_id: Uuid::default(),
-            call_id: 11i64,
-            performed_at: Utc::now(),
-            uploaded_at: Utc::now(),
-            file_hash: Uuid::new_v4().hyphenated().to_string(),
-            file_url: "s3://test_bucket/test.mp3".to_string(),
-            file_name: "test.mp3".to_string(),
-            duration: 15.0,
-            left_channel: ParticipantKind::Client,
-            right_channel: ParticipantKind::Employee,
-            client_name: "test_client".to_string(),
-            employee_name: "test_agent".to_string(),
-            inbound: true,
-        };
-        let metadata_id = metadata
-            .insert(&mut conn)
+        insert_task(Uuid::default(), &mut conn).await;
+
+        let tasks = TaskWithMetadata::tasks_list(0, 10, "file_name", false, None, &mut conn)
             .await
-            .expect("failed to insert metadata")
-            .metadata_id;
+            .expect("failed to retrieve tasks list");
+        let count = TaskWithMetadata::total_count(Uuid::default(), None, &mut conn)
+            .await
+            .expect("failed to retrieve total count");
+        assert!(tasks.len() == count as usize);
+    }
 
-        let task = Task {
-            id: Uuid::default(),
-            call_metadata_id: metadata_id,
-            failed_reason: None,
-            project_id: Uuid::default(),
-            status: TaskResultKind::Processing,
+    #[sqlx::test]
+    async fn test_tasks_list_has_metrics(pool: sqlx::PgPool) {
+        let mut conn = pool.acquire().await.unwrap();
+        let mut task_ids = vec![];
+        for _ in 0..2 {
+            let mut task = insert_task(Uuid::default(), &mut conn).await;
+            task.status = TaskResultKind::Ready;
+            task.update(&mut conn).await.expect("failed to update task");
+            task_ids.push(task.id);
+        }
+        let metrics = CallMetrics {
+            task_id: task_ids[0],
+            ..Default::default()
         };
-        task.insert(&mut conn).await.expect("failed to insert task");
+        CallMetrics::insert(metrics, &mut conn).await.unwrap();
 
-        let tasks = TaskWithMetadata::tasks_list(0, 10, "file_name", false, &mut conn)
+        for (has_metrics, task_id) in [(true, task_ids[0]), (false, task_ids[1])] {
+            let tasks = TaskWithMetadata::tasks_list(
+                0,
+                10,
+                "file_name",
+                false,
+                Some(has_metrics),
+                &mut conn,
+            )
             .await
             .expect("failed to retrieve tasks list");
-        let count = TaskWithMetadata::total_count(Uuid::default(), &mut conn)
+            assert_eq!(
+                tasks.iter().map(|t| t.task.id).collect::<Vec<_>>(),
+                vec![task_id]
+            );
+            let count =
+                TaskWithMetadata::total_count(Uuid::default(), Some(has_metrics), &mut conn)
+                    .await
+                    .expect("failed to retrieve total count");
+            assert_eq!(count, 1);
+        }
+
+        let tasks = TaskWithMetadata::tasks_list(0, 10, "file_name", false, None, &mut conn)
             .await
-            .expect("failed to retrieve total count");
-        assert!(tasks.len() == count as usize);
+            .expect("failed to retrieve tasks list");
+        assert_eq!(tasks.len(), 2);
     }
 }
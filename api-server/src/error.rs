@@ -16,10 +16,16 @@ pub enum ErrorKind {
     EntityNotFound,
     SerializationFailed,
     TaskAlreadyProcessing,
+    TaskNotProcessing,
     FileAlredyExists,
+    InvalidMetadata,
     AMQPError,
     CalcMetricsFailed,
     InvalidSettingsRequest,
+    InvalidDictionaryRequest,
+    InvalidExportRequest,
+    InvalidOrderBy,
+    InvalidQueryParams,
     WorkerRequestFailed,
 }
 
@@ -34,9 +40,15 @@ impl From<ErrorKind> for StatusCode {
         match k {
             ErrorKind::DbQueryFailed => StatusCode::UNPROCESSABLE_ENTITY,
             ErrorKind::EntityNotFound => StatusCode::NOT_FOUND,
-            ErrorKind::TaskAlreadyProcessing => StatusCode::BAD_REQUEST,
-            ErrorKind::FileAlredyExists => StatusCode::BAD_REQUEST,
+            ErrorKind::TaskAlreadyProcessing => StatusCode::CONFLICT,
+            ErrorKind::TaskNotProcessing => StatusCode::CONFLICT,
+            ErrorKind::FileAlredyExists => StatusCode::CONFLICT,
             ErrorKind::InvalidSettingsRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::InvalidDictionaryRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::InvalidExportRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::InvalidOrderBy => StatusCode::BAD_REQUEST,
+            ErrorKind::InvalidQueryParams => StatusCode::BAD_REQUEST,
+            ErrorKind::InvalidMetadata => StatusCode::BAD_REQUEST,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -1,12 +1,16 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::Json;
 use http::StatusCode;
 use protocol::db::dictionary::{Dictionary, Phrase};
+use protocol::db::settings::ProjectPreferences;
+use protocol::entity::dictionary_test::{DictionaryTestRequest, PhraseMatch};
 use protocol::entity::ParticipantKind;
 use serde::Deserialize;
 use sqlx::Acquire;
-use utoipa::{OpenApi, ToSchema};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
 
+use crate::clients::worker::WorkerClient;
 use crate::context::{AppContext, Context};
 use crate::error::{Error, ErrorKind};
 
@@ -14,8 +18,15 @@ use super::utils::{AppResponse, RequestResult};
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(list_dicts, dict_by_id, create, update, delete),
-    components(schemas(Dictionary, Phrase, DictCreateRequest, DictUpdateRequest)),
+    paths(list_dicts, dict_by_id, create, import, test, update, delete),
+    components(schemas(
+        Dictionary,
+        Phrase,
+        DictCreateRequest,
+        DictUpdateRequest,
+        DictionaryTestRequest,
+        PhraseMatch
+    )),
     tags(
         (name = "Dictionaries", description = "API for handling dictionaries operations")
     )
@@ -46,7 +57,7 @@ async fn do_list_dicts<C: Context>(cx: C) -> RequestResult<Vec<Dictionary>> {
     get,
     path = "/{dict_id}",
     responses(
-        (status = OK, description = "Get a dictionary", body = Vec<Phrase>),
+        (status = OK, description = "Phrases of the dictionary, empty when it has none", body = Vec<Phrase>),
         (status = NOT_FOUND, description = "Dictionary not found"),
         (status = INTERNAL_SERVER_ERROR, description = "Failed to retrieve dictionary phrases")
     ),
@@ -64,11 +75,13 @@ pub async fn dict_by_id(
 
 async fn do_dict_by_id<C: Context>(cx: C, dict_id: i32) -> RequestResult<Vec<Phrase>> {
     let mut conn = cx.get_db_conn().await?;
-    let dict = Dictionary::fetch_by_id(dict_id, &mut conn).await?;
-    dict.ok_or(Error::new(
-        ErrorKind::EntityNotFound,
-        anyhow::anyhow!("dictionary by {dict_id} not found"),
-    ))?;
+    // an existing dictionary without phrases is an empty list, not a missing one
+    if Dictionary::fetch_by_id(dict_id, &mut conn).await?.is_none() {
+        return Err(Error::new(
+            ErrorKind::EntityNotFound,
+            anyhow::anyhow!("dictionary by {dict_id} not found"),
+        ));
+    }
 
     let phrases = Phrase::list_by_dict_id(dict_id, &mut conn).await?;
 
@@ -79,6 +92,9 @@ async fn do_dict_by_id<C: Context>(cx: C, dict_id: i32) -> RequestResult<Vec<Phr
 pub struct DictCreateRequest {
     name: String,
     participant: ParticipantKind,
+    /// Match the phrases against both participants rather than `participant` only
+    #[serde(default)]
+    any_speaker: bool,
     phrases: Vec<String>,
 }
 
@@ -104,7 +120,12 @@ async fn do_create<C: Context>(cx: C, request: DictCreateRequest) -> RequestResu
     let mut conn = cx.get_db_conn().await?;
     let mut txn = conn.begin().await?;
 
-    let dict = Dictionary::insert(request.name, request.participant, &mut txn).await?;
+    let mut dict = Dictionary::insert(request.name, request.participant, &mut txn).await?;
+    if request.any_speaker {
+        dict = Dictionary::set_any_speaker(dict.id, true, &mut txn)
+            .await?
+            .unwrap_or(dict);
+    }
     let phrases = request
         .phrases
         .into_iter()
@@ -121,10 +142,135 @@ async fn do_create<C: Context>(cx: C, request: DictCreateRequest) -> RequestResu
     Ok(AppResponse::new(StatusCode::CREATED, dict))
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DictImportRequest {
+    name: String,
+    /// Falls back to the participant set in the project preferences when omitted
+    participant: Option<ParticipantKind>,
+}
+
+#[utoipa::path(
+    post,
+    operation_id = "dict_import",
+    path = "/import",
+    params(DictImportRequest),
+    request_body(
+        content = String,
+        content_type = "text/plain",
+        description = "Phrases of the dictionary, one per line"
+    ),
+    responses(
+        (status = CREATED, description = "Dictionary imported", body = Dictionary),
+        (status = BAD_REQUEST, description = "Participant is neither given nor set in the project preferences"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to import dictionary")
+    ),
+    tags = ["Dictionaries"]
+)]
+pub async fn import(
+    State(cx): State<AppContext>,
+    Query(request): Query<DictImportRequest>,
+    phrases: String,
+) -> RequestResult<Dictionary> {
+    do_import(cx, Uuid::default(), request, &phrases).await
+}
+
+async fn do_import<C: Context>(
+    cx: C,
+    project_id: Uuid,
+    request: DictImportRequest,
+    phrases: &str,
+) -> RequestResult<Dictionary> {
+    let default_participant = {
+        let mut conn = cx.get_db_conn().await?;
+        ProjectPreferences::fetch_by_project_id(project_id, &mut conn)
+            .await?
+            .and_then(|preferences| preferences.dictionary_import_participant)
+    };
+    let participant = request.participant.or(default_participant).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidDictionaryRequest,
+            anyhow::anyhow!(
+                "participant of imported dictionary {} is unknown",
+                request.name
+            ),
+        )
+    })?;
+
+    let phrases = phrases
+        .lines()
+        .map(str::trim)
+        .filter(|phrase| !phrase.is_empty())
+        .map(String::from)
+        .collect();
+
+    do_create(
+        cx,
+        DictCreateRequest {
+            name: request.name,
+            participant,
+            any_speaker: false,
+            phrases,
+        },
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    operation_id = "dict_test",
+    path = "/{dict_id}/test",
+    request_body = DictionaryTestRequest,
+    responses(
+        (status = OK, description = "Phrases of the dictionary with whether each matched the sample", body = Vec<PhraseMatch>),
+        (status = NOT_FOUND, description = "Dictionary not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to match the sample")
+    ),
+    params(
+        ("dict_id" = i32, Path, description = "Dictionary ID to test")
+    ),
+    tags = ["Dictionaries"]
+)]
+pub async fn test(
+    State(cx): State<AppContext>,
+    Path(dict_id): Path<i32>,
+    Json(request): Json<DictionaryTestRequest>,
+) -> RequestResult<Vec<PhraseMatch>> {
+    do_test(cx, dict_id, request).await
+}
+
+async fn do_test<C: Context>(
+    cx: C,
+    dict_id: i32,
+    request: DictionaryTestRequest,
+) -> RequestResult<Vec<PhraseMatch>> {
+    {
+        let mut conn = cx.get_db_conn().await?;
+        if Dictionary::fetch_by_id(dict_id, &mut conn).await?.is_none() {
+            return Err(Error::new(
+                ErrorKind::EntityNotFound,
+                anyhow::anyhow!("dictionary by {dict_id} not found"),
+            ));
+        }
+    }
+
+    // phrases are matched by the worker with the same tokenizer as indexed transcripts
+    let matches = cx
+        .worker_client()
+        .test_dictionary(dict_id, request)
+        .await
+        .map_err(|err| Error::new(ErrorKind::WorkerRequestFailed, anyhow::anyhow!(err)))?;
+
+    Ok(AppResponse::new(StatusCode::OK, matches))
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct DictUpdateRequest {
     delete_phrases: Vec<i64>,
     create_phrases: Vec<String>,
+    /// Switches matching against both participants, kept as is when omitted
+    #[serde(default)]
+    any_speaker: Option<bool>,
 }
 
 #[utoipa::path(
@@ -175,6 +321,9 @@ async fn do_update<C: Context>(
         .collect();
     Phrase::bulk_delete(request.delete_phrases, &mut txn).await?;
     Phrase::bulk_insert(create_phrases, &mut txn).await?;
+    if let Some(any_speaker) = request.any_speaker {
+        Dictionary::set_any_speaker(dict_id, any_speaker, &mut txn).await?;
+    }
 
     txn.commit().await?;
 
@@ -276,12 +425,36 @@ mod tests {
         assert_eq!(phrases.text, "test_phrase");
     }
 
+    #[sqlx::test]
+    async fn fetch_empty_and_missing_dict(pool: sqlx::PgPool) {
+        let empty_dict = {
+            let mut conn = pool.acquire().await.unwrap();
+
+            Dictionary::insert("empty_dict".to_owned(), ParticipantKind::Client, &mut conn)
+                .await
+                .unwrap()
+        };
+
+        let cx = TestContext::new(pool).await;
+        let dicts_resp = do_dict_by_id(cx.clone(), empty_dict.id)
+            .await
+            .expect("failed to retrieve empty dict");
+        assert_eq!(dicts_resp.status(), StatusCode::OK);
+        assert!(dicts_resp.payload().is_empty());
+
+        let missing_err = do_dict_by_id(cx, empty_dict.id + 1000)
+            .await
+            .expect_err("unexpected success while retrieving missing dict");
+        assert_eq!(missing_err.kind, ErrorKind::EntityNotFound);
+    }
+
     #[sqlx::test]
     async fn create_dict(pool: sqlx::PgPool) {
         let cx = TestContext::new(pool.clone()).await;
         let create_request = DictCreateRequest {
             name: "test_dict".to_string(),
             participant: ParticipantKind::Employee,
+            any_speaker: true,
             phrases: vec!["test_phrase".to_string()],
         };
 
@@ -290,6 +463,7 @@ mod tests {
             .expect("failed to create dict");
 
         assert_eq!(dicts_resp.status(), StatusCode::CREATED);
+        assert_eq!(dicts_resp.payload().searched_speaker(), None);
         let mut conn = pool.acquire().await.unwrap();
         let mut phrases = Phrase::list_by_dict_id(dicts_resp.payload().id, &mut conn)
             .await
@@ -297,6 +471,55 @@ mod tests {
         assert_eq!(phrases.pop().unwrap().text, "test_phrase");
     }
 
+    #[sqlx::test]
+    async fn import_dict(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let request = |participant| DictImportRequest {
+            name: "imported_dict".to_string(),
+            participant,
+        };
+
+        let err = do_import(cx.clone(), Uuid::default(), request(None), "phrase")
+            .await
+            .expect_err("participant is neither given nor configured");
+        assert_eq!(err.kind, ErrorKind::InvalidDictionaryRequest);
+
+        let resp = do_import(
+            cx.clone(),
+            Uuid::default(),
+            request(Some(ParticipantKind::Client)),
+            "first phrase\n\n  second phrase \n",
+        )
+        .await
+        .expect("failed to import dict");
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.payload().participant, ParticipantKind::Client);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let phrases = Phrase::list_by_dict_id(resp.payload().id, &mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|phrase| phrase.text)
+            .collect::<Vec<_>>();
+        assert_eq!(phrases, vec!["first phrase", "second phrase"]);
+
+        ProjectPreferences::upsert(
+            ProjectPreferences {
+                project_id: Uuid::default(),
+                dictionary_import_participant: Some(ParticipantKind::Employee),
+                ..Default::default()
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        let resp = do_import(cx, Uuid::default(), request(None), "phrase")
+            .await
+            .expect("failed to import dict with default participant");
+        assert_eq!(resp.payload().participant, ParticipantKind::Employee);
+    }
+
     #[sqlx::test]
     async fn update_dict(pool: sqlx::PgPool) {
         let mut conn = pool.acquire().await.unwrap();
@@ -324,6 +547,7 @@ mod tests {
         let update_request = DictUpdateRequest {
             create_phrases: vec!["test_phrase".to_string()],
             delete_phrases: vec![phrase_to_delete.id],
+            any_speaker: None,
         };
 
         let dicts_resp = do_update(cx, dict.id, update_request)
@@ -366,4 +590,44 @@ mod tests {
             .expect_err("unexpected success while retrieving dict");
         assert_eq!(dict_resp.kind, ErrorKind::EntityNotFound);
     }
+
+    #[sqlx::test]
+    async fn test_dict(pool: sqlx::PgPool) {
+        let dict = {
+            let mut conn = pool.acquire().await.unwrap();
+            Dictionary::insert("test_dict".to_owned(), ParticipantKind::Employee, &mut conn)
+                .await
+                .unwrap()
+        };
+
+        let mut cx = TestContext::new(pool).await;
+        cx.worker_client_mock()
+            .expect_test_dictionary()
+            .withf(move |dict_id, request| *dict_id == dict.id && request.text == "hello there")
+            .times(1)
+            .returning(|dict_id, _| {
+                Ok(vec![PhraseMatch {
+                    phrase: Phrase {
+                        id: 1,
+                        dictionary_id: dict_id,
+                        text: "hello".to_owned(),
+                    },
+                    matched: true,
+                }])
+            });
+
+        let request = || DictionaryTestRequest {
+            text: "hello there".to_owned(),
+        };
+        let test_resp = do_test(cx.clone(), dict.id, request())
+            .await
+            .expect("failed to test dict");
+        assert_eq!(test_resp.status(), StatusCode::OK);
+        assert!(test_resp.payload()[0].matched);
+
+        let err = do_test(cx, dict.id + 1, request())
+            .await
+            .expect_err("unexpected success while testing missing dict");
+        assert_eq!(err.kind, ErrorKind::EntityNotFound);
+    }
 }
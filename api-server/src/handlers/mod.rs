@@ -6,7 +6,8 @@ use tower_http::cors::{Any, CorsLayer};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::context::AppContext;
+use crate::config::CorsConfig;
+use crate::context::{AppContext, Context};
 
 #[derive(OpenApi)]
 #[openapi(
@@ -24,6 +25,12 @@ use crate::context::AppContext;
 struct ApiDoc;
 
 pub fn api_router(cx: AppContext) -> Router {
+    let cors = cors_layer(&cx.config().cors);
+    let request_log = axum::middleware::from_fn_with_state(
+        cx.config().request_log.clone(),
+        request_log::log_requests,
+    );
+
     Router::new()
         .nest(
             "/api/v1",
@@ -34,24 +41,66 @@ pub fn api_router(cx: AppContext) -> Router {
         )
         .with_state(cx)
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .layer(CorsLayer::new().allow_origin(Any))
+        .layer(request_log)
+        .layer(cors)
+}
+
+/// Download endpoints pass the file name in `Content-Disposition`,
+/// browsers hide it from scripts unless it's exposed
+fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let mut expose_headers = vec![http::header::CONTENT_DISPOSITION];
+    for header in &config.expose_headers {
+        match http::HeaderName::try_from(header.as_str()) {
+            Ok(header) => expose_headers.push(header),
+            Err(err) => tracing::warn!("Skipping invalid CORS expose header {header}: {err}"),
+        }
+    }
+
+    CorsLayer::new()
+        .allow_origin(Any)
+        .expose_headers(expose_headers)
 }
 
 pub fn tasks_router() -> Router<AppContext> {
     Router::new()
         .route("/tasks", post(task::create).get(task::list))
+        .route("/tasks/import", post(task::import))
         .route("/tasks/:id", put(task::reprocess))
         .route("/tasks/:id/detailed_metrics", get(task::detailed_metrics))
+        .route("/tasks/:id/full", get(task::full))
+        .route("/tasks/:id/score_preview", post(task::score_preview))
+        .route("/tasks/:id/cancel", post(task::cancel))
         .route("/tasks/metrics", get(task::metrics_list))
+        .route("/tasks/metrics/schema", get(task::metrics_schema))
+        .route("/tasks/metrics/aggregates", get(task::metrics_aggregates))
+        .route("/tasks/failures/summary", get(task::failures_summary))
+        .route(
+            "/tasks/metrics/detailed_export",
+            post(task::detailed_export),
+        )
 }
 
 pub fn settings_router() -> Router<AppContext> {
     Router::new()
-        .route("/settings", get(settings::settings_list))
+        .route(
+            "/settings",
+            get(settings::settings_list).put(settings::settings_replace),
+        )
+        .route("/settings/kinds", get(settings::settings_kinds))
+        .route(
+            "/settings/preferences",
+            get(settings::preferences).put(settings::preferences_update),
+        )
         .route("/settings/item", post(settings::settings_item_create))
         .route(
             "/settings/item/:id",
-            put(settings::settings_item_update).delete(settings::settings_item_delete),
+            put(settings::settings_item_update)
+                .patch(settings::settings_item_patch)
+                .delete(settings::settings_item_delete),
+        )
+        .route(
+            "/settings/item/:id/dictionaries",
+            get(settings::settings_item_dictionaries),
         )
 }
 
@@ -76,10 +125,50 @@ pub fn dictionaries_router() -> Router<AppContext> {
             "/dictionaries",
             get(dictionary::list_dicts).post(dictionary::create),
         )
+        .route("/dictionaries/import", post(dictionary::import))
+        .route("/dictionaries/:id/test", post(dictionary::test))
 }
 
 mod dictionary;
+mod request_log;
 mod settings;
 mod task;
 mod transcript;
 mod utils;
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{header, Request};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cors_exposes_download_headers() {
+        let config = CorsConfig {
+            expose_headers: vec!["x-total-count".to_string(), "invalid header".to_string()],
+        };
+        let router = Router::new()
+            .route("/", get(|| async {}))
+            .layer(cors_layer(&config));
+
+        let response = router
+            .oneshot(
+                Request::get("/")
+                    .header(header::ORIGIN, "http://example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let exposed = response
+            .headers()
+            .get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .expect("expose headers are missing")
+            .to_str()
+            .unwrap();
+        assert_eq!(exposed, "content-disposition,x-total-count");
+    }
+}
@@ -0,0 +1,285 @@
+use std::time::Instant;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::BytesMut;
+use futures::{stream, StreamExt};
+use http::StatusCode;
+use serde_json::Value;
+
+use crate::config::RequestLogConfig;
+
+const REDACTED: &str = "***";
+
+/// Logs method, path, status and latency of every request. The request body of error
+/// responses is logged too when capturing is enabled, only its first `max_body_bytes` are
+/// buffered and the handler still receives the whole body as a stream.
+pub async fn log_requests(
+    State(config): State<RequestLogConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+
+    let (request, body) = if config.capture_error_bodies {
+        let (parts, body) = request.into_parts();
+        match capture_body(body, config.max_body_bytes).await {
+            Ok((body, captured)) => (Request::from_parts(parts, body), Some(captured)),
+            Err(err) => {
+                tracing::warn!(%method, path, "failed to read request body: {err}");
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to read request body: {err}"),
+                )
+                    .into_response();
+            }
+        }
+    } else {
+        (request, None)
+    };
+
+    let response = next.run(request).await;
+    let status = response.status().as_u16();
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    if response.status().is_client_error() || response.status().is_server_error() {
+        let body = body.map(|body| match body {
+            CapturedBody::Complete(body) => loggable_body(&body, &config),
+            // a partial body can't be redacted, so it isn't logged
+            CapturedBody::Exceeded => format!("<body exceeds {} bytes>", config.max_body_bytes),
+        });
+        tracing::warn!(%method, path, status, latency_ms, body, "request failed");
+    } else {
+        tracing::info!(%method, path, status, latency_ms, "request handled");
+    }
+
+    response
+}
+
+enum CapturedBody {
+    Complete(Bytes),
+    Exceeded,
+}
+
+/// Buffers the body until it turns out to be longer than `max_bytes`, the returned body
+/// replays the buffered chunks before streaming the rest
+async fn capture_body(body: Body, max_bytes: usize) -> Result<(Body, CapturedBody), axum::Error> {
+    let mut stream = body.into_data_stream();
+    let mut chunks = vec![];
+    let mut captured = BytesMut::new();
+    let complete = loop {
+        if captured.len() > max_bytes {
+            break false;
+        }
+        match stream.next().await {
+            Some(chunk) => {
+                let chunk = chunk?;
+                captured.extend_from_slice(&chunk);
+                chunks.push(chunk);
+            }
+            None => break true,
+        }
+    };
+
+    if complete {
+        let captured = captured.freeze();
+        return Ok((
+            Body::from(captured.clone()),
+            CapturedBody::Complete(captured),
+        ));
+    }
+
+    let replayed = stream::iter(chunks.into_iter().map(Ok::<_, axum::Error>));
+    Ok((
+        Body::from_stream(replayed.chain(stream)),
+        CapturedBody::Exceeded,
+    ))
+}
+
+fn loggable_body(body: &Bytes, config: &RequestLogConfig) -> String {
+    let body = match serde_json::from_slice::<Value>(body) {
+        Ok(mut json) => {
+            redact(&mut json, &config.redacted_fields);
+            json.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+
+    if body.len() <= config.max_body_bytes {
+        return body;
+    }
+    let mut end = config.max_body_bytes;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &body[..end])
+}
+
+fn redact(json: &mut Value, fields: &[String]) {
+    match json {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if fields.iter().any(|field| field.eq_ignore_ascii_case(key)) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, fields)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{middleware, routing::post, Json, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogBuffer {
+        fn capture() -> (Self, tracing::subscriber::DefaultGuard) {
+            let logs = LogBuffer::default();
+            let subscriber = tracing_subscriber::fmt()
+                .with_writer({
+                    let logs = logs.clone();
+                    move || logs.clone()
+                })
+                .with_ansi(false)
+                .finish();
+            (logs, tracing::subscriber::set_default(subscriber))
+        }
+
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn error_response_is_logged() {
+        let (logs, _guard) = LogBuffer::capture();
+
+        let config = RequestLogConfig {
+            capture_error_bodies: true,
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route(
+                "/items",
+                post(|Json(body): Json<Value>| async move {
+                    // the handler still receives the buffered body
+                    assert_eq!(body["name"], "test");
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }),
+            )
+            .layer(middleware::from_fn_with_state(config, log_requests));
+
+        let response = router
+            .oneshot(
+                Request::post("/items")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"name":"test","password":"hunter2"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let logs = logs.contents();
+        assert!(logs.contains("request failed"));
+        assert!(logs.contains("status=422"));
+        assert!(logs.contains("path=\"/items\""));
+        assert!(logs.contains(r#"\"name\":\"test\""#));
+        assert!(!logs.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn oversized_body_streamed_to_handler() {
+        let (logs, _guard) = LogBuffer::capture();
+
+        let config = RequestLogConfig {
+            capture_error_bodies: true,
+            max_body_bytes: 4,
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route(
+                "/items",
+                post(|body: String| async move {
+                    assert_eq!(body, "password=hunter2");
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }),
+            )
+            .layer(middleware::from_fn_with_state(config, log_requests));
+
+        let chunks = ["pass", "word=", "hunter2"].map(Ok::<_, std::io::Error>);
+        let response = router
+            .oneshot(
+                Request::post("/items")
+                    .body(Body::from_stream(stream::iter(chunks)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let logs = logs.contents();
+        assert!(logs.contains("<body exceeds 4 bytes>"));
+        assert!(!logs.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn unreadable_body_rejected() {
+        let config = RequestLogConfig {
+            capture_error_bodies: true,
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route("/items", post(|| async { StatusCode::OK }))
+            .layer(middleware::from_fn_with_state(config, log_requests));
+
+        let chunks = [Err::<Bytes, _>(std::io::Error::other("connection reset"))];
+        let response = router
+            .oneshot(
+                Request::post("/items")
+                    .body(Body::from_stream(stream::iter(chunks)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn loggable_body_truncated() {
+        let config = RequestLogConfig {
+            max_body_bytes: 5,
+            ..Default::default()
+        };
+        assert_eq!(loggable_body(&Bytes::from("привет"), &config), "пр...");
+        assert_eq!(loggable_body(&Bytes::from("hi"), &config), "hi");
+    }
+}
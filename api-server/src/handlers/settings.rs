@@ -1,37 +1,77 @@
-use axum::extract::Path;
+use std::collections::HashMap;
+
+use axum::extract::{Path, Query};
 use axum::{extract::State, Json};
 use http::StatusCode;
 use protocol::auxiliary;
 use protocol::db::settings::SettingsKind;
 use protocol::db::{
     dictionary::Dictionary,
-    settings::{Settings, SettingsDictItem, SettingsItem},
+    settings::{ProjectPreferences, Settings, SettingsDictItem, SettingsItem},
+};
+use protocol::entity::settings_metrics::{
+    SettingsItemKindDefinition, SettingsItemSource, SETTINGS_ITEM_KINDS,
 };
 use serde::{Deserialize, Serialize};
-use utoipa::{OpenApi, ToSchema};
+use sqlx::Acquire;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
 use crate::context::{AppContext, Context};
 use crate::error::{Error, ErrorKind};
-use crate::handlers::utils::{AppResponse, RequestResult};
+use crate::handlers::utils::{double_option, AppResponse, RequestResult};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct SettingsItemWithDicts {
     item: SettingsItem,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     dicts: Vec<Dictionary>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dict_items: Vec<SettingsDictItem>,
+}
+
+fn linked_dicts(dictionaries: &[Dictionary], dict_items: &[SettingsDictItem]) -> Vec<Dictionary> {
+    dict_items
+        .iter()
+        .flat_map(|dict_item| {
+            dictionaries
+                .iter()
+                .find(|dict| dict.id == dict_item.dictionary_id)
+                .cloned()
+        })
+        .collect()
+}
+
+/// A dictionary linked to a settings item along with the link's `contains` flag
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LinkedDictionary {
+    #[serde(flatten)]
+    dictionary: Dictionary,
+    contains: bool,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct SettingsResponse {
-    quality: Vec<SettingsItemWithDicts>,
-    script: Vec<SettingsItemWithDicts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<Vec<SettingsItemWithDicts>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<Vec<SettingsItemWithDicts>>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SettingsListRequest {
+    /// Return only the settings of the given kind, both kinds by default
+    kind: Option<SettingsKind>,
+    /// Return only items with the given immutability flag, `false` leaves just the
+    /// user-editable items, both by default
+    immutable: Option<bool>,
 }
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(settings_list, settings_item_create, settings_item_update, settings_item_delete),
-    components(schemas(SettingsItemCreateRequest, SettingsItemUpdateRequest, SettingsResponse, SettingsItemWithDicts, Dictionary)),
+    paths(settings_list, settings_replace, settings_kinds, preferences, preferences_update, settings_item_create, settings_item_update, settings_item_patch, settings_item_dictionaries, settings_item_delete),
+    components(schemas(SettingsKind, ProjectPreferences, SettingsItemKindDefinition, SettingsItemSource, SettingsItemCreateRequest, SettingsItemUpdateRequest, SettingsItemPatchRequest, SettingsReplaceRequest, SettingsItemReplacement, SettingsResponse, SettingsItemWithDicts, LinkedDictionary, Dictionary)),
     tags(
         (name = "Settings", description = "API for handle settings options")
     )
@@ -41,17 +81,25 @@ pub struct ApiSettings;
 #[utoipa::path(
     get,
     path = "",
+    params(SettingsListRequest),
     responses(
         (status = OK, description = "List Settings of Project", body = SettingsResponse),
         (status = INTERNAL_SERVER_ERROR, description = "Error while trying to handle list of settings")
     ),
     tags = ["Settings"]
 )]
-pub async fn settings_list(State(cx): State<AppContext>) -> RequestResult<SettingsResponse> {
-    do_settings_list(cx, Uuid::default()).await
+pub async fn settings_list(
+    State(cx): State<AppContext>,
+    Query(request): Query<SettingsListRequest>,
+) -> RequestResult<SettingsResponse> {
+    do_settings_list(cx, Uuid::default(), request).await
 }
 
-async fn do_settings_list<C: Context>(cx: C, project_id: Uuid) -> RequestResult<SettingsResponse> {
+async fn do_settings_list<C: Context>(
+    cx: C,
+    project_id: Uuid,
+    request: SettingsListRequest,
+) -> RequestResult<SettingsResponse> {
     let mut conn = cx.get_db_conn().await?;
     let settings = Settings::list_by_project_id(project_id, &mut conn).await?;
     let dictionaries = Dictionary::list(&mut conn).await?;
@@ -65,26 +113,24 @@ async fn do_settings_list<C: Context>(cx: C, project_id: Uuid) -> RequestResult<
     drop(conn);
 
     let mut items_with_dicts = vec![];
-    for item in settings_items.into_iter() {
+    let settings_items = settings_items.into_iter().filter(|item| {
+        request
+            .immutable
+            .is_none_or(|immutable| item.settings_immutable == immutable)
+    });
+    for item in settings_items {
         let dict_items = settings_dict_items.remove(&item.id).unwrap_or(vec![]);
-        let dict_items = dict_items.into_iter().flat_map(|dict_item| {
-            dictionaries
-                .iter()
-                .find(|dict| dict.id == dict_item.dictionary_id)
-                .cloned()
-                .into_iter()
-        });
-        items_with_dicts.push({
-            SettingsItemWithDicts {
-                item,
-                dicts: dict_items.collect(),
-            }
+        items_with_dicts.push(SettingsItemWithDicts {
+            dicts: linked_dicts(&dictionaries, &dict_items),
+            item,
+            dict_items,
         });
     }
 
     let mut items_with_dicts =
         auxiliary::group_by(items_with_dicts, |item| item.item.settings_id, |_| true);
-    let quality_settings = {
+    let include_kind = |kind: SettingsKind| request.kind.is_none_or(|filter| filter == kind);
+    let quality_settings = if include_kind(SettingsKind::Quality) {
         let id = settings
             .iter()
             .find(|settings| settings.r#type == SettingsKind::Quality)
@@ -93,9 +139,11 @@ async fn do_settings_list<C: Context>(cx: C, project_id: Uuid) -> RequestResult<
                 ErrorKind::EntityNotFound,
                 anyhow::anyhow!("related quality settings id not found"),
             ))?;
-        items_with_dicts.remove(&id).unwrap_or(vec![])
+        Some(items_with_dicts.remove(&id).unwrap_or(vec![]))
+    } else {
+        None
     };
-    let script_settings = {
+    let script_settings = if include_kind(SettingsKind::Script) {
         let id = settings
             .iter()
             .find(|settings| settings.r#type == SettingsKind::Script)
@@ -104,7 +152,9 @@ async fn do_settings_list<C: Context>(cx: C, project_id: Uuid) -> RequestResult<
                 ErrorKind::EntityNotFound,
                 anyhow::anyhow!("related script settings id not found"),
             ))?;
-        items_with_dicts.remove(&id).unwrap_or(vec![])
+        Some(items_with_dicts.remove(&id).unwrap_or(vec![]))
+    } else {
+        None
     };
 
     Ok(AppResponse::new(
@@ -116,18 +166,224 @@ async fn do_settings_list<C: Context>(cx: C, project_id: Uuid) -> RequestResult<
     ))
 }
 
+/// Desired state of a settings item, linked `dicts` are ignored so listed items can be sent back
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SettingsItemReplacement {
+    item: SettingsItem,
+    #[serde(default)]
+    dict_items: Vec<SettingsDictItem>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SettingsReplaceRequest {
+    /// Settings of an omitted kind are left untouched
+    quality: Option<Vec<SettingsItemReplacement>>,
+    script: Option<Vec<SettingsItemReplacement>>,
+}
+
+#[utoipa::path(
+    put,
+    path = "",
+    request_body = SettingsReplaceRequest,
+    responses(
+        (status = OK, description = "Settings of the project after the replacement", body = SettingsResponse),
+        (status = BAD_REQUEST, description = "Trying to create or delete non-script items or to change immutable ones"),
+        (status = NOT_FOUND, description = "Related settings not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Server error when replacing settings")
+    ),
+    tags = ["Settings"]
+)]
+pub async fn settings_replace(
+    State(cx): State<AppContext>,
+    Json(request): Json<SettingsReplaceRequest>,
+) -> RequestResult<SettingsResponse> {
+    do_settings_replace(cx, Uuid::default(), request).await
+}
+
+/// Items are matched by id: known ones are updated, unknown ones are created and the ones
+/// missing from the request are deleted, all in one transaction
+async fn do_settings_replace<C: Context>(
+    cx: C,
+    project_id: Uuid,
+    request: SettingsReplaceRequest,
+) -> RequestResult<SettingsResponse> {
+    let mut conn = cx.get_db_conn().await?;
+    let mut txn = conn.begin().await?;
+    let settings = Settings::list_by_project_id(project_id, &mut txn).await?;
+    let settings_items = SettingsItem::list_by_project_id(project_id, &mut txn).await?;
+
+    for (kind, replacements) in [
+        (SettingsKind::Quality, request.quality),
+        (SettingsKind::Script, request.script),
+    ] {
+        let Some(replacements) = replacements else {
+            continue;
+        };
+        let settings_id = settings
+            .iter()
+            .find(|settings| settings.r#type == kind)
+            .map(|settings| settings.id)
+            .ok_or(Error::new(
+                ErrorKind::EntityNotFound,
+                anyhow::anyhow!("related {kind:?} settings id not found"),
+            ))?;
+        let mut current: HashMap<Uuid, &SettingsItem> = settings_items
+            .iter()
+            .filter(|item| item.settings_id == settings_id)
+            .map(|item| (item.id, item))
+            .collect();
+
+        for SettingsItemReplacement { item, dict_items } in replacements {
+            match current.remove(&item.id) {
+                Some(stored) => {
+                    ensure_immutable_kept(stored, Some(&item.name), Some(&dict_items), &mut txn)
+                        .await?;
+                    SettingsItem::update_by_id(
+                        stored.id,
+                        item.name,
+                        item.score_weight,
+                        item.graded_threshold,
+                        &mut txn,
+                    )
+                    .await?;
+                    SettingsDictItem::reconcile_by_item_id(stored.id, dict_items, &mut txn).await?;
+                }
+                None if kind == SettingsKind::Script => {
+                    let item = SettingsItem {
+                        settings_id,
+                        settings_immutable: false,
+                        ..item
+                    };
+                    let inserted_item = SettingsItem::insert(item, &mut txn).await?;
+                    let dict_items = dict_items
+                        .into_iter()
+                        .map(|mut dict_item| {
+                            dict_item.settings_item_id = inserted_item.id;
+                            dict_item
+                        })
+                        .collect();
+                    SettingsDictItem::bulk_insert(dict_items, &mut txn).await?;
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidSettingsRequest,
+                        anyhow::anyhow!("attempted to create non-script settings item"),
+                    ))
+                }
+            }
+        }
+
+        for stored in current.into_values() {
+            if stored.settings_immutable {
+                return Err(Error::new(
+                    ErrorKind::InvalidSettingsRequest,
+                    anyhow::anyhow!("attempted to delete immutable settings item"),
+                ));
+            }
+            if kind != SettingsKind::Script {
+                return Err(Error::new(
+                    ErrorKind::InvalidSettingsRequest,
+                    anyhow::anyhow!("attempted to delete non-script settings item"),
+                ));
+            }
+            SettingsDictItem::delete_by_item_id(stored.id, &mut txn).await?;
+            SettingsItem::delete_by_id(stored.id, &mut txn).await?;
+        }
+    }
+
+    txn.commit().await?;
+    drop(conn);
+
+    do_settings_list(cx, project_id, SettingsListRequest::default()).await
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct SettingsItemCreateRequest {
     item: SettingsItem,
     dict_items: Vec<SettingsDictItem>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/kinds",
+    responses(
+        (status = OK, description = "Settings item kinds with their scoring rules", body = Vec<SettingsItemKindDefinition>)
+    ),
+    tags = ["Settings"]
+)]
+pub async fn settings_kinds() -> RequestResult<Vec<SettingsItemKindDefinition>> {
+    Ok(AppResponse::new(
+        StatusCode::OK,
+        SETTINGS_ITEM_KINDS.to_vec(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/preferences",
+    responses(
+        (status = OK, description = "Preferences of the project, empty when never set", body = ProjectPreferences),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch the preferences")
+    ),
+    tags = ["Settings"]
+)]
+pub async fn preferences(State(cx): State<AppContext>) -> RequestResult<ProjectPreferences> {
+    do_preferences(cx, Uuid::default()).await
+}
+
+async fn do_preferences<C: Context>(cx: C, project_id: Uuid) -> RequestResult<ProjectPreferences> {
+    let mut conn = cx.get_db_conn().await?;
+    let preferences = ProjectPreferences::fetch_by_project_id(project_id, &mut conn)
+        .await?
+        .unwrap_or(ProjectPreferences {
+            project_id,
+            ..Default::default()
+        });
+
+    Ok(AppResponse::new(StatusCode::OK, preferences))
+}
+
+#[utoipa::path(
+    put,
+    path = "/preferences",
+    request_body = ProjectPreferences,
+    responses(
+        (status = OK, description = "Preferences of the project after the update", body = ProjectPreferences),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to update the preferences")
+    ),
+    tags = ["Settings"]
+)]
+pub async fn preferences_update(
+    State(cx): State<AppContext>,
+    Json(request): Json<ProjectPreferences>,
+) -> RequestResult<ProjectPreferences> {
+    do_preferences_update(cx, Uuid::default(), request).await
+}
+
+async fn do_preferences_update<C: Context>(
+    cx: C,
+    project_id: Uuid,
+    request: ProjectPreferences,
+) -> RequestResult<ProjectPreferences> {
+    let mut conn = cx.get_db_conn().await?;
+    let preferences = ProjectPreferences::upsert(
+        ProjectPreferences {
+            project_id,
+            ..request
+        },
+        &mut conn,
+    )
+    .await?;
+
+    Ok(AppResponse::new(StatusCode::OK, preferences))
+}
+
 #[utoipa::path(
     post,
     path = "/item",
     request_body = SettingsItemCreateRequest,
     responses(
-        (status = CREATED, description = "Create Settings", body = SettingsItem),
+        (status = CREATED, description = "Create Settings", body = SettingsItemWithDicts),
         (status = BAD_REQUEST, description = "Trying to create non-script settings item"),
         (status = NOT_FOUND, description = "Related settings not found"),
         (status = INTERNAL_SERVER_ERROR, description = "Server error when creating a settings item")
@@ -137,7 +393,7 @@ pub struct SettingsItemCreateRequest {
 pub async fn settings_item_create(
     State(cx): State<AppContext>,
     Json(request): Json<SettingsItemCreateRequest>,
-) -> RequestResult<SettingsItem> {
+) -> RequestResult<SettingsItemWithDicts> {
     do_settings_item_create(cx, Uuid::default(), request).await
 }
 
@@ -145,7 +401,7 @@ async fn do_settings_item_create<C: Context>(
     cx: C,
     project_id: Uuid,
     request: SettingsItemCreateRequest,
-) -> RequestResult<SettingsItem> {
+) -> RequestResult<SettingsItemWithDicts> {
     let mut conn = cx.get_db_conn().await?;
     let settings = Settings::list_by_project_id(project_id, &mut conn).await?;
     let related_settings = settings
@@ -174,15 +430,25 @@ async fn do_settings_item_create<C: Context>(
             dict_item
         })
         .collect();
-    SettingsDictItem::bulk_insert(dict_items, &mut conn).await?;
+    let dict_items = SettingsDictItem::bulk_insert(dict_items, &mut conn).await?;
+    let dictionaries = Dictionary::list(&mut conn).await?;
 
-    Ok(AppResponse::new(StatusCode::CREATED, inserted_item))
+    Ok(AppResponse::new(
+        StatusCode::CREATED,
+        SettingsItemWithDicts {
+            dicts: linked_dicts(&dictionaries, &dict_items),
+            item: inserted_item,
+            dict_items,
+        },
+    ))
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct SettingsItemUpdateRequest {
     item_name: String,
     item_score_weight: i32,
+    #[serde(default)]
+    item_graded_threshold: Option<f32>,
     dict_items: Vec<SettingsDictItem>,
 }
 
@@ -192,6 +458,7 @@ pub struct SettingsItemUpdateRequest {
     request_body = SettingsItemUpdateRequest,
     responses(
         (status = OK, description = "Updates the setting item"),
+        (status = BAD_REQUEST, description = "Trying to rename or relink an immutable settings item"),
         (status = NOT_FOUND, description = "Setting item not found"),
         (status = INTERNAL_SERVER_ERROR, description = "Server error when updating a settings item")
     ),
@@ -210,39 +477,173 @@ pub async fn settings_item_update(
 
 async fn do_settings_item_update<C: Context>(
     cx: C,
-    _project_id: Uuid,
+    project_id: Uuid,
     item_id: Uuid,
     request: SettingsItemUpdateRequest,
+) -> RequestResult<()> {
+    let request = SettingsItemPatchRequest {
+        item_name: Some(request.item_name),
+        item_score_weight: Some(request.item_score_weight),
+        item_graded_threshold: Some(request.item_graded_threshold),
+        dict_items: Some(request.dict_items),
+    };
+
+    do_settings_item_patch(cx, project_id, item_id, request).await
+}
+
+/// Built-in items can only be re-weighted, their name and dict links are fixed
+async fn ensure_immutable_kept(
+    item: &SettingsItem,
+    name: Option<&str>,
+    dict_items: Option<&[SettingsDictItem]>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), Error> {
+    if item.settings_immutable {
+        if name.is_some_and(|name| name != item.name) {
+            return Err(Error::new(
+                ErrorKind::InvalidSettingsRequest,
+                anyhow::anyhow!("attempted to rename immutable settings item"),
+            ));
+        }
+        if let Some(dict_items) = dict_items {
+            let linked = |dict_items: &[SettingsDictItem]| {
+                let mut linked: Vec<_> = dict_items
+                    .iter()
+                    .map(|dict_item| (dict_item.dictionary_id, dict_item.contains))
+                    .collect();
+                linked.sort();
+                linked
+            };
+            let stored = SettingsDictItem::list_by_item_id(item.id, conn).await?;
+            if linked(dict_items) != linked(&stored) {
+                return Err(Error::new(
+                    ErrorKind::InvalidSettingsRequest,
+                    anyhow::anyhow!("attempted to change dicts of immutable settings item"),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SettingsItemPatchRequest {
+    item_name: Option<String>,
+    item_score_weight: Option<i32>,
+    /// `null` switches the item back to binary scoring
+    #[serde(default, deserialize_with = "double_option")]
+    #[schema(value_type = Option<f32>)]
+    item_graded_threshold: Option<Option<f32>>,
+    dict_items: Option<Vec<SettingsDictItem>>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/item/{item_id}",
+    request_body = SettingsItemPatchRequest,
+    responses(
+        (status = OK, description = "Updates provided fields of the setting item"),
+        (status = BAD_REQUEST, description = "Trying to rename or relink an immutable settings item"),
+        (status = NOT_FOUND, description = "Setting item not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Server error when updating a settings item")
+    ),
+    params(
+        ("item_id" = Uuid, Path, description = "Unique identifier of the setting item")
+    ),
+    tags = ["Settings"]
+)]
+pub async fn settings_item_patch(
+    State(cx): State<AppContext>,
+    Path(item_id): Path<Uuid>,
+    Json(request): Json<SettingsItemPatchRequest>,
+) -> RequestResult<()> {
+    do_settings_item_patch(cx, Uuid::default(), item_id, request).await
+}
+
+async fn do_settings_item_patch<C: Context>(
+    cx: C,
+    _project_id: Uuid,
+    item_id: Uuid,
+    request: SettingsItemPatchRequest,
 ) -> RequestResult<()> {
     let mut conn = cx.get_db_conn().await?;
-    let _ = SettingsItem::fetch_by_id(item_id, &mut conn)
+    let item = SettingsItem::fetch_by_id(item_id, &mut conn)
         .await?
         .ok_or(Error::new(
             ErrorKind::EntityNotFound,
             anyhow::anyhow!("settings item by {item_id} not found"),
         ))?;
+
+    ensure_immutable_kept(
+        &item,
+        request.item_name.as_deref(),
+        request.dict_items.as_deref(),
+        &mut conn,
+    )
+    .await?;
+
     SettingsItem::update_by_id(
         item_id,
-        request.item_name,
-        request.item_score_weight,
+        request.item_name.unwrap_or(item.name),
+        request.item_score_weight.unwrap_or(item.score_weight),
+        request
+            .item_graded_threshold
+            .unwrap_or(item.graded_threshold),
         &mut conn,
     )
     .await?;
 
-    SettingsDictItem::delete_by_item_id(item_id, &mut conn).await?;
+    // dict links are left untouched when omitted
+    if let Some(dict_items) = request.dict_items {
+        SettingsDictItem::reconcile_by_item_id(item_id, dict_items, &mut conn).await?;
+    }
 
-    let dict_items = request
-        .dict_items
+    Ok(AppResponse::new(StatusCode::OK, ()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/item/{item_id}/dictionaries",
+    responses(
+        (status = OK, description = "Dictionaries linked to the settings item", body = Vec<LinkedDictionary>),
+        (status = NOT_FOUND, description = "Setting item not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Server error when listing linked dictionaries")
+    ),
+    params(
+        ("item_id" = Uuid, Path, description = "Unique identifier of the setting item")
+    ),
+    tags = ["Settings"]
+)]
+pub async fn settings_item_dictionaries(
+    State(cx): State<AppContext>,
+    Path(item_id): Path<Uuid>,
+) -> RequestResult<Vec<LinkedDictionary>> {
+    do_settings_item_dictionaries(cx, item_id).await
+}
+
+async fn do_settings_item_dictionaries<C: Context>(
+    cx: C,
+    item_id: Uuid,
+) -> RequestResult<Vec<LinkedDictionary>> {
+    let mut conn = cx.get_db_conn().await?;
+    SettingsItem::fetch_by_id(item_id, &mut conn)
+        .await?
+        .ok_or(Error::new(
+            ErrorKind::EntityNotFound,
+            anyhow::anyhow!("settings item by {item_id} not found"),
+        ))?;
+
+    let linked = Dictionary::list_by_settings_item_id(item_id, &mut conn)
+        .await?
         .into_iter()
-        .map(|dict_item| {
-            let mut dict_item = dict_item.clone();
-            dict_item.settings_item_id = item_id;
-            dict_item
+        .map(|(dictionary, contains)| LinkedDictionary {
+            dictionary,
+            contains,
         })
         .collect();
-    SettingsDictItem::bulk_insert(dict_items, &mut conn).await?;
 
-    Ok(AppResponse::new(StatusCode::OK, ()))
+    Ok(AppResponse::new(StatusCode::OK, linked))
 }
 
 #[utoipa::path(
@@ -251,7 +652,7 @@ async fn do_settings_item_update<C: Context>(
     responses(
         (status = OK, description = "Deletes a settings item"),
         (status = NOT_FOUND, description = "Setting item not found"),
-        (status = BAD_REQUEST, description = "Trying to delete non-script settings item"),
+        (status = BAD_REQUEST, description = "Trying to delete non-script or immutable settings item"),
         (status = INTERNAL_SERVER_ERROR, description = "Server error when deleting a settings item")
     ),
     params(
@@ -287,6 +688,12 @@ async fn do_settings_item_delete<C: Context>(
             anyhow::anyhow!("settings item by {item_id} not found"),
         ))?;
 
+    if item.settings_immutable {
+        return Err(Error::new(
+            ErrorKind::InvalidSettingsRequest,
+            anyhow::anyhow!("attempted to delete immutable settings item"),
+        ));
+    }
     if related_settings.id != item.settings_id {
         return Err(Error::new(
             ErrorKind::InvalidSettingsRequest,
@@ -299,3 +706,471 @@ async fn do_settings_item_delete<C: Context>(
 
     Ok(AppResponse::new(StatusCode::OK, ()))
 }
+
+#[cfg(test)]
+mod tests {
+    use protocol::db::settings::SettingsItemKind;
+    use protocol::entity::ParticipantKind;
+
+    use crate::test_helpers::context::TestContext;
+
+    use super::*;
+
+    async fn create_script_item(cx: &TestContext, project_id: Uuid, dictionary_id: i32) -> Uuid {
+        let settings_id = {
+            let mut conn = cx.get_db_conn().await.unwrap();
+            Settings::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|settings| settings.r#type == SettingsKind::Script)
+                .unwrap()
+                .id
+        };
+
+        let request = SettingsItemCreateRequest {
+            item: SettingsItem {
+                id: Uuid::default(),
+                settings_id,
+                settings_immutable: false,
+                r#type: SettingsItemKind::Dictionary,
+                name: "test_item".to_string(),
+                score_weight: 10,
+                graded_threshold: None,
+            },
+            dict_items: vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: Uuid::default(),
+                dictionary_id,
+                contains: true,
+            }],
+        };
+
+        do_settings_item_create(cx.clone(), project_id, request)
+            .await
+            .expect("failed to create settings item")
+            .payload()
+            .item
+            .id
+    }
+
+    #[tokio::test]
+    async fn settings_kinds_definitions() {
+        let resp = settings_kinds()
+            .await
+            .expect("failed to list settings kinds");
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let kinds = resp.payload();
+        assert_eq!(kinds.len(), SETTINGS_ITEM_KINDS.len());
+        let filler_words = kinds
+            .iter()
+            .find(|def| def.kind == SettingsItemKind::FillerWordsDict)
+            .expect("filler words kind is missing");
+        assert_eq!(filler_words.description, "Filler words in employee speech");
+        assert_eq!(filler_words.source, SettingsItemSource::Dictionary);
+    }
+
+    #[sqlx::test]
+    async fn settings_list_filters(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let project_id = Uuid::default();
+
+        let resp = do_settings_list(cx.clone(), project_id, SettingsListRequest::default())
+            .await
+            .expect("failed to list settings");
+        let settings = resp.payload();
+        assert!(!settings.quality.as_ref().unwrap().is_empty());
+        assert!(!settings.script.as_ref().unwrap().is_empty());
+
+        let request = SettingsListRequest {
+            kind: Some(SettingsKind::Script),
+            immutable: None,
+        };
+        let resp = do_settings_list(cx.clone(), project_id, request)
+            .await
+            .expect("failed to list script settings");
+        let settings = resp.payload();
+        assert!(settings.quality.is_none());
+        assert_eq!(settings.script.as_ref().map(|items| items.len()), Some(4));
+
+        let request = SettingsListRequest {
+            kind: Some(SettingsKind::Script),
+            immutable: Some(true),
+        };
+        let resp = do_settings_list(cx.clone(), project_id, request)
+            .await
+            .expect("failed to list immutable script settings");
+        assert_eq!(
+            resp.payload().script.as_ref().map(|items| items.len()),
+            Some(0)
+        );
+
+        let request = SettingsListRequest {
+            kind: None,
+            immutable: Some(false),
+        };
+        let resp = do_settings_list(cx.clone(), project_id, request)
+            .await
+            .expect("failed to list editable settings");
+        let settings = resp.payload();
+        let items = || {
+            settings
+                .quality
+                .iter()
+                .chain(&settings.script)
+                .flatten()
+                .map(|item| &item.item)
+        };
+        assert!(items().next().is_some());
+        assert!(items().all(|item| !item.settings_immutable));
+    }
+
+    #[sqlx::test]
+    async fn settings_replace(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let project_id = Uuid::default();
+        let list = || do_settings_list(cx.clone(), project_id, SettingsListRequest::default());
+        let replacements = |items: &[SettingsItemWithDicts]| -> Vec<SettingsItemReplacement> {
+            items
+                .iter()
+                .map(|item| SettingsItemReplacement {
+                    item: item.item.clone(),
+                    dict_items: item.dict_items.clone(),
+                })
+                .collect()
+        };
+
+        let resp = list().await.expect("failed to list settings");
+        let before = resp.payload();
+        let mut quality = replacements(before.quality.as_ref().unwrap());
+        quality[0].item.score_weight += 5;
+        let reweighted_id = quality[0].item.id;
+        let reweighted_weight = quality[0].item.score_weight;
+        let mut script = replacements(&before.script.as_ref().unwrap()[..1]);
+        script[0].item.name = "renamed_item".to_string();
+        script.push(SettingsItemReplacement {
+            item: SettingsItem {
+                id: Uuid::default(),
+                settings_id: Uuid::default(),
+                settings_immutable: false,
+                r#type: SettingsItemKind::Dictionary,
+                name: "new_item".to_string(),
+                score_weight: 3,
+                graded_threshold: None,
+            },
+            dict_items: vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: Uuid::default(),
+                dictionary_id: 6,
+                contains: true,
+            }],
+        });
+        let request = SettingsReplaceRequest {
+            quality: Some(quality),
+            script: Some(script),
+        };
+
+        let resp = do_settings_replace(cx.clone(), project_id, request)
+            .await
+            .expect("failed to replace settings");
+        assert_eq!(resp.status(), StatusCode::OK);
+        let after = resp.payload();
+        let reweighted = after
+            .quality
+            .iter()
+            .flatten()
+            .find(|item| item.item.id == reweighted_id)
+            .unwrap();
+        assert_eq!(reweighted.item.score_weight, reweighted_weight);
+        let script = after.script.as_ref().unwrap();
+        let mut names: Vec<_> = script.iter().map(|item| item.item.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["new_item", "renamed_item"]);
+        let created = script
+            .iter()
+            .find(|item| item.item.name == "new_item")
+            .unwrap();
+        assert_eq!(created.dict_items.len(), 1);
+        assert_eq!(created.dicts[0].id, 6);
+
+        // dropping built-in quality items fails the whole replacement
+        let request = SettingsReplaceRequest {
+            quality: Some(vec![]),
+            script: Some(vec![]),
+        };
+        let err = do_settings_replace(cx.clone(), project_id, request)
+            .await
+            .expect_err("built-in items were deleted");
+        assert_eq!(err.kind, ErrorKind::InvalidSettingsRequest);
+        let resp = list().await.expect("failed to list settings");
+        assert_eq!(resp.payload().script.as_ref().unwrap().len(), 2);
+    }
+
+    #[sqlx::test]
+    async fn settings_item_create_returns_dict_items(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::default();
+        let mut conn = pool.acquire().await.unwrap();
+        let settings_id = Settings::list_by_project_id(project_id, &mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|settings| settings.r#type == SettingsKind::Script)
+            .unwrap()
+            .id;
+
+        let dict_item = |dictionary_id, contains| SettingsDictItem {
+            id: Uuid::default(),
+            settings_item_id: Uuid::default(),
+            dictionary_id,
+            contains,
+        };
+        let request = SettingsItemCreateRequest {
+            item: SettingsItem {
+                id: Uuid::default(),
+                settings_id,
+                settings_immutable: false,
+                r#type: SettingsItemKind::Dictionary,
+                name: "test_item".to_string(),
+                score_weight: 10,
+                graded_threshold: None,
+            },
+            dict_items: vec![dict_item(6, true), dict_item(7, false)],
+        };
+
+        let resp = do_settings_item_create(cx, project_id, request)
+            .await
+            .expect("failed to create settings item");
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created = resp.payload();
+
+        let stored_dict_items = SettingsDictItem::list_by_item_id(created.item.id, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(created.dict_items.len(), 2);
+        assert!(created
+            .dict_items
+            .iter()
+            .all(|dict_item| dict_item.id != Uuid::default()
+                && dict_item.settings_item_id == created.item.id));
+        for dict_item in stored_dict_items {
+            assert!(created.dict_items.contains(&dict_item));
+        }
+
+        let mut dict_ids: Vec<_> = created.dicts.iter().map(|dict| dict.id).collect();
+        dict_ids.sort();
+        assert_eq!(dict_ids, vec![6, 7]);
+    }
+
+    #[sqlx::test]
+    async fn settings_item_linked_dictionaries(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let item_id = create_script_item(&cx, Uuid::default(), 6).await;
+
+        let resp = do_settings_item_dictionaries(cx.clone(), item_id)
+            .await
+            .expect("failed to list linked dictionaries");
+        assert_eq!(resp.status(), StatusCode::OK);
+        let linked = resp.payload();
+        assert_eq!(linked.len(), 1);
+        assert_eq!(linked[0].dictionary.id, 6);
+        assert!(linked[0].contains);
+
+        let err = do_settings_item_dictionaries(cx, Uuid::new_v4())
+            .await
+            .expect_err("missing settings item has no dictionaries");
+        assert_eq!(err.kind, ErrorKind::EntityNotFound);
+    }
+
+    #[sqlx::test]
+    async fn settings_item_patch_name_only(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::default();
+        let item_id = create_script_item(&cx, project_id, 6).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        let dict_items_before: Vec<_> = SettingsDictItem::list_by_project_id(project_id, &mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|dict_item| dict_item.settings_item_id == item_id)
+            .collect();
+        assert_eq!(dict_items_before.len(), 1);
+
+        let patch_request = SettingsItemPatchRequest {
+            item_name: Some("renamed_item".to_string()),
+            item_score_weight: None,
+            item_graded_threshold: None,
+            dict_items: None,
+        };
+        let patch_resp = do_settings_item_patch(cx, project_id, item_id, patch_request)
+            .await
+            .expect("failed to patch settings item");
+        assert_eq!(patch_resp.status(), StatusCode::OK);
+
+        let item = SettingsItem::fetch_by_id(item_id, &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.name, "renamed_item");
+        assert_eq!(item.score_weight, 10);
+
+        let dict_items_after: Vec<_> = SettingsDictItem::list_by_project_id(project_id, &mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|dict_item| dict_item.settings_item_id == item_id)
+            .collect();
+        assert_eq!(dict_items_after.len(), 1);
+        assert_eq!(dict_items_after[0].id, dict_items_before[0].id);
+        assert_eq!(dict_items_after[0].dictionary_id, 6);
+    }
+
+    #[sqlx::test]
+    async fn settings_item_update_keeps_dict_item_ids(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::default();
+        let item_id = create_script_item(&cx, project_id, 6).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        let dict_items_before = SettingsDictItem::list_by_item_id(item_id, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(dict_items_before.len(), 1);
+
+        let update_request = SettingsItemUpdateRequest {
+            item_name: "test_item".to_string(),
+            item_score_weight: 10,
+            item_graded_threshold: None,
+            dict_items: vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: Uuid::default(),
+                dictionary_id: 6,
+                contains: true,
+            }],
+        };
+        do_settings_item_update(cx.clone(), project_id, item_id, update_request)
+            .await
+            .expect("failed to update settings item");
+
+        let dict_items_after = SettingsDictItem::list_by_item_id(item_id, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(dict_items_after, dict_items_before);
+
+        let update_request = SettingsItemUpdateRequest {
+            item_name: "test_item".to_string(),
+            item_score_weight: 10,
+            item_graded_threshold: None,
+            dict_items: vec![
+                SettingsDictItem {
+                    id: Uuid::default(),
+                    settings_item_id: Uuid::default(),
+                    dictionary_id: 6,
+                    contains: false,
+                },
+                SettingsDictItem {
+                    id: Uuid::default(),
+                    settings_item_id: Uuid::default(),
+                    dictionary_id: 7,
+                    contains: true,
+                },
+            ],
+        };
+        do_settings_item_update(cx, project_id, item_id, update_request)
+            .await
+            .expect("failed to update settings item");
+
+        let dict_items_after = SettingsDictItem::list_by_item_id(item_id, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(dict_items_after.len(), 2);
+        let unchanged = dict_items_after
+            .iter()
+            .find(|dict_item| dict_item.dictionary_id == 6)
+            .unwrap();
+        assert_eq!(unchanged.id, dict_items_before[0].id);
+        assert!(!unchanged.contains);
+    }
+
+    #[sqlx::test]
+    async fn immutable_settings_item_restrictions(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::default();
+        let mut conn = pool.acquire().await.unwrap();
+        let item = SettingsItem::list_by_project_id(project_id, &mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|item| item.settings_immutable)
+            .expect("no immutable settings item");
+
+        let delete_err = do_settings_item_delete(cx.clone(), project_id, item.id)
+            .await
+            .expect_err("unexpected success while deleting immutable item");
+        assert_eq!(delete_err.kind, ErrorKind::InvalidSettingsRequest);
+
+        let rename_request = SettingsItemPatchRequest {
+            item_name: Some("renamed_item".to_string()),
+            item_score_weight: None,
+            item_graded_threshold: None,
+            dict_items: None,
+        };
+        let rename_err = do_settings_item_patch(cx.clone(), project_id, item.id, rename_request)
+            .await
+            .expect_err("unexpected success while renaming immutable item");
+        assert_eq!(rename_err.kind, ErrorKind::InvalidSettingsRequest);
+
+        let reweight_request = SettingsItemPatchRequest {
+            item_name: Some(item.name.clone()),
+            item_score_weight: Some(item.score_weight + 1),
+            item_graded_threshold: None,
+            dict_items: None,
+        };
+        do_settings_item_patch(cx, project_id, item.id, reweight_request)
+            .await
+            .expect("failed to re-weight immutable item");
+
+        let stored = SettingsItem::fetch_by_id(item.id, &mut conn)
+            .await
+            .unwrap()
+            .expect("immutable item was removed");
+        assert_eq!(stored.name, item.name);
+        assert_eq!(stored.score_weight, item.score_weight + 1);
+    }
+
+    #[sqlx::test]
+    async fn preferences_update(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let project_id = Uuid::default();
+
+        let resp = do_preferences(cx.clone(), project_id)
+            .await
+            .expect("failed to fetch preferences");
+        assert_eq!(resp.payload().dictionary_import_participant, None);
+        // projects set up before the preferences keep their emotions recognized
+        assert!(resp.payload().emotion_recognition);
+
+        for participant in [ParticipantKind::Client, ParticipantKind::Employee] {
+            let request = ProjectPreferences {
+                project_id: Uuid::new_v4(),
+                dictionary_import_participant: Some(participant),
+                emotion_recognition: false,
+            };
+            let resp = do_preferences_update(cx.clone(), project_id, request)
+                .await
+                .expect("failed to update preferences");
+            assert_eq!(resp.payload().project_id, project_id);
+        }
+
+        let resp = do_preferences(cx, project_id)
+            .await
+            .expect("failed to fetch preferences");
+        assert_eq!(
+            resp.payload().dictionary_import_participant,
+            Some(ParticipantKind::Employee)
+        );
+        assert!(!resp.payload().emotion_recognition);
+    }
+}
@@ -1,20 +1,42 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::body::Body;
 use axum::extract::{Path, Query};
+use axum::response::Response;
 use axum::{extract::State, Json};
+use chrono::serde::ts_milliseconds_option;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
 use http::StatusCode;
 use protocol::db::{
     metadata::CallMetadata,
+    metrics::{CallMetrics, CallMetricsExtras, TalkBalance},
     settings::{Settings, SettingsDictItem, SettingsItem},
     task::{Task, TaskResultKind, TaskToDict},
 };
-use protocol::entity::settings_metrics::{self, TaskSettingsMetrics};
+use protocol::entity::settings_metrics::{
+    self, ScoringRules, TaskSettingsItemMetric, TaskSettingsMetrics,
+};
+use protocol::entity::speech_recog::{MetricsIntervals, RecognitionData};
+use protocol::entity::ParticipantKind;
 use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio_util::io::StreamReader;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
+use crate::clients::worker::{WorkerClient, WorkerClientError};
+use crate::config::Config;
 use crate::context::{AppContext, Context, TaskPublisher};
-use crate::db::{metrics::MetricsWithMetadata, task::TaskWithMetadata};
+use crate::db::{
+    metrics::{AggregateGroup, MetricsAggregate, MetricsColumn, MetricsWithMetadata},
+    task::{FailureCategory, TaskWithMetadata},
+};
 use crate::error::{Error, ErrorExt, ErrorKind};
-use crate::handlers::utils::{AppResponse, RequestResult};
+use crate::handlers::utils::{round_floats, AppResponse, Principal, RequestResult};
 
 #[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct TaskCreateRequest {
@@ -25,8 +47,8 @@ pub struct TaskCreateRequest {
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(create, reprocess, list, metrics_list, detailed_metrics),
-    components(schemas(TaskCreateRequest, TaskListResponse, MetricsListResponse, TaskDetailedMetrics)),
+    paths(create, import, reprocess, cancel, list, metrics_list, metrics_schema, metrics_aggregates, failures_summary, detailed_metrics, detailed_export, full, score_preview),
+    components(schemas(TaskCreateRequest, TaskImportResponse, TaskImportError, TaskImportPublishError, TaskListResponse, MetricsListResponse, MetricsSchema, AggregateGroup, MetricsAggregate, MetricsAggregatesResponse, FailureCategory, FailuresSummaryResponse, TaskDetailedMetrics, DetailedExportRequest, ExportFormat, TaskFull, MetricsShape, ScorePreviewRequest)),
     tags(
         (name = "Tasks", description = "API to handle tasks and metrics")
     )
@@ -40,51 +62,140 @@ pub(super) struct ApiTasks;
     request_body = TaskCreateRequest,
     responses(
         (status = CREATED, description = "Task created successfully", body = Task),
-        (status = BAD_REQUEST, description = "File with the same hash already exists"),
+        (status = BAD_REQUEST, description = "File url is not allowed"),
+        (status = CONFLICT, description = "File with the same hash already exists"),
         (status = INTERNAL_SERVER_ERROR, description = "Failed to create task")
     ),
     tags = ["Tasks"]
 )]
 pub async fn create(
     State(cx): State<AppContext>,
+    Principal(principal): Principal,
     Json(request): Json<TaskCreateRequest>,
 ) -> RequestResult<Task> {
-    do_create(cx, request).await
+    do_create(cx, request, principal).await
 }
 
-async fn do_create<C: Context>(cx: C, request: TaskCreateRequest) -> RequestResult<Task> {
-    let stored_metadata = {
-        let mut conn = cx.get_db_conn().await?;
-        let res = request.metadata.insert(&mut conn).await;
-        match res {
-            Err(sqlx::Error::Database(db_err))
-                if db_err.kind() == sqlx::error::ErrorKind::UniqueViolation =>
-            {
-                return Err(Error::new(
-                    ErrorKind::FileAlredyExists,
-                    anyhow::anyhow!(
-                        "file {} with hash {} already exists",
-                        request.metadata.file_name,
-                        request.metadata.file_hash
-                    ),
-                ))
-            }
-            Err(err) => return Err(err.into()),
-            Ok(res) => res,
+fn normalize_file_url(file_url: &str, allowlist: &[String]) -> Result<String, Error> {
+    let file_url = file_url.trim();
+    let invalid = |reason: &str| {
+        Error::new(
+            ErrorKind::InvalidMetadata,
+            anyhow::anyhow!("file url {file_url} {reason}"),
+        )
+    };
+
+    // the scheme is lowercased by the parser, hosts of non-special schemes like s3 are not
+    let mut url = url::Url::parse(file_url).map_err(|_| invalid("is not a valid url"))?;
+    if let Some(host) = url.host_str().map(str::to_lowercase) {
+        url.set_host(Some(&host))
+            .map_err(|_| invalid("has an invalid host"))?;
+    }
+
+    if !allowlist.iter().any(|entry| file_url_allowed(&url, entry)) {
+        return Err(invalid("is not allowed"));
+    }
+
+    Ok(url.to_string())
+}
+
+/// Allowlist entries are a bare scheme (`s3://`), a scheme with a host (`s3://bucket`) or with
+/// a path (`https://host/calls`), hosts and path segments have to match as a whole
+fn file_url_allowed(url: &url::Url, entry: &str) -> bool {
+    let Some((scheme, rest)) = entry.split_once("://") else {
+        return false;
+    };
+    if !url.scheme().eq_ignore_ascii_case(scheme) {
+        return false;
+    }
+
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if host.is_empty() {
+        return true;
+    }
+    if !url
+        .host_str()
+        .is_some_and(|url_host| url_host.eq_ignore_ascii_case(host))
+    {
+        return false;
+    }
+
+    let mut url_segments = url.path_segments().into_iter().flatten();
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .all(|segment| url_segments.next() == Some(segment))
+}
+
+async fn insert_task(
+    mut metadata: CallMetadata,
+    project_id: Uuid,
+    principal: Option<String>,
+    allowlist: &[String],
+    conn: &mut sqlx::PgConnection,
+) -> Result<Task, Error> {
+    metadata.file_url = normalize_file_url(&metadata.file_url, allowlist)?;
+    for channel_url in [
+        &mut metadata.left_channel_url,
+        &mut metadata.right_channel_url,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *channel_url = normalize_file_url(channel_url, allowlist)?;
+    }
+
+    let stored_metadata = match metadata.insert(project_id, conn).await {
+        Err(sqlx::Error::Database(db_err))
+            if db_err.kind() == sqlx::error::ErrorKind::UniqueViolation =>
+        {
+            return Err(Error::new(
+                ErrorKind::FileAlredyExists,
+                anyhow::anyhow!(
+                    "file {} with hash {} already exists",
+                    metadata.file_name,
+                    metadata.file_hash
+                ),
+            ))
         }
+        Err(err) => return Err(err.into()),
+        Ok(res) => res,
+    };
+
+    let task = Task {
+        id: Uuid::default(),
+        call_metadata_id: stored_metadata.metadata_id,
+        status: TaskResultKind::Processing,
+        failed_reason: None,
+        failure_class: None,
+        failure_kind: None,
+        project_id,
+        created_by: principal,
+        reprocessed_by: None,
+        attempts: 0,
     };
 
+    Ok(task.insert(conn).await?)
+}
+
+async fn do_create<C: Context>(
+    cx: C,
+    request: TaskCreateRequest,
+    principal: Option<String>,
+) -> RequestResult<Task> {
     let stored_task = {
         let mut conn = cx.get_db_conn().await?;
-        let task = Task {
-            id: Uuid::default(),
-            call_metadata_id: stored_metadata.metadata_id,
-            status: TaskResultKind::Processing,
-            failed_reason: None,
-            project_id: request._project_id,
-        };
+        let mut txn = conn.begin().await?;
+        let task = insert_task(
+            request.metadata,
+            request._project_id,
+            principal,
+            &cx.config().file_url_allowlist,
+            &mut txn,
+        )
+        .await?;
+        txn.commit().await?;
 
-        task.insert(&mut conn).await?
+        task
     };
 
     cx.publisher().publish(&stored_task.id).await?;
@@ -92,6 +203,169 @@ async fn do_create<C: Context>(cx: C, request: TaskCreateRequest) -> RequestResu
     Ok(AppResponse::new(StatusCode::CREATED, stored_task))
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskImportError {
+    /// 1-based line number in the manifest
+    line: usize,
+    error: String,
+}
+
+/// Tasks of a batch which were stored but failed to be queued, the stuck tasks sweep
+/// re-queues them once they time out
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskImportPublishError {
+    task_ids: Vec<Uuid>,
+    error: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskImportResponse {
+    created: Vec<Uuid>,
+    errors: Vec<TaskImportError>,
+    publish_errors: Vec<TaskImportPublishError>,
+}
+
+#[utoipa::path(
+    post,
+    operation_id = "task_import",
+    path = "/import",
+    request_body(
+        content = String,
+        content_type = "application/x-ndjson",
+        description = "JSONL manifest, one `CallMetadata` per line, e.g. streamed from object storage"
+    ),
+    responses(
+        (status = OK, description = "Manifest imported, per-line failures are reported", body = TaskImportResponse),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to import manifest")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn import(
+    State(cx): State<AppContext>,
+    Principal(principal): Principal,
+    manifest: Body,
+) -> RequestResult<TaskImportResponse> {
+    // the manifest is read line by line as it arrives, so its size isn't bounded by memory
+    let manifest = StreamReader::new(
+        manifest
+            .into_data_stream()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err)),
+    );
+    do_import(cx, manifest, principal).await
+}
+
+async fn do_import<C: Context, R: AsyncBufRead + Unpin>(
+    cx: C,
+    manifest: R,
+    principal: Option<String>,
+) -> RequestResult<TaskImportResponse> {
+    let batch_size = cx.config().import_batch_size.max(1);
+    let mut lines = manifest.split(b'\n');
+    let mut line_number = 0;
+    let mut manifest_read = false;
+
+    let mut created = Vec::new();
+    let mut errors = Vec::new();
+    let mut publish_errors = Vec::new();
+
+    while !manifest_read {
+        let mut batch = Vec::with_capacity(batch_size);
+        while batch.len() < batch_size {
+            let Some(raw) = lines
+                .next_segment()
+                .await
+                .error(ErrorKind::DeserializationFailed)?
+            else {
+                manifest_read = true;
+                break;
+            };
+            line_number += 1;
+            if !raw.trim_ascii().is_empty() {
+                batch.push((line_number, raw));
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+
+        let mut batch_tasks = Vec::with_capacity(batch.len());
+        {
+            let mut conn = cx.get_db_conn().await?;
+            let mut txn = conn.begin().await?;
+
+            for (line, raw) in batch {
+                let metadata = match serde_json::from_slice::<CallMetadata>(raw.trim_ascii()) {
+                    Ok(metadata) => metadata,
+                    Err(err) => {
+                        errors.push(TaskImportError {
+                            line,
+                            error: format!("malformed metadata: {err}"),
+                        });
+                        continue;
+                    }
+                };
+
+                // A savepoint per line keeps a failed insert from aborting the whole batch
+                let mut savepoint = txn.begin().await?;
+                match insert_task(
+                    metadata,
+                    Uuid::default(),
+                    principal.clone(),
+                    &cx.config().file_url_allowlist,
+                    &mut savepoint,
+                )
+                .await
+                {
+                    Ok(task) => {
+                        savepoint.commit().await?;
+                        batch_tasks.push(task.id);
+                    }
+                    Err(err) => {
+                        savepoint.rollback().await?;
+                        errors.push(TaskImportError {
+                            line,
+                            error: err.to_string(),
+                        });
+                    }
+                }
+            }
+
+            txn.commit().await?;
+        }
+
+        // Publishing the batch before inserting the next one bounds the backlog of
+        // tasks that are stored but not yet queued
+        if let Err(err) = cx.publisher().publish_batch(&batch_tasks).await {
+            let task_ids = err.unpublished(&batch_tasks);
+            tracing::warn!(
+                "failed to queue {} imported tasks: {}",
+                task_ids.len(),
+                err.error
+            );
+            publish_errors.push(TaskImportPublishError {
+                task_ids,
+                error: err.error.to_string(),
+            });
+        }
+        created.extend(batch_tasks);
+
+        tracing::info!(
+            processed = created.len() + errors.len(),
+            failed = errors.len(),
+            "task import progress"
+        );
+    }
+
+    Ok(AppResponse::new(
+        StatusCode::OK,
+        TaskImportResponse {
+            created,
+            errors,
+            publish_errors,
+        },
+    ))
+}
+
 #[utoipa::path(
     put,
     operation_id = "task_recreate",
@@ -99,7 +373,7 @@ async fn do_create<C: Context>(cx: C, request: TaskCreateRequest) -> RequestResu
     responses(
         (status = OK, description = "Task reprocessed successfully", body = Task),
         (status = NOT_FOUND, description = "Task not found"),
-        (status = BAD_REQUEST, description = "Task is already processing")
+        (status = CONFLICT, description = "Task is already processing")
     ),
     params(
         ("task_id" = Uuid, Path, description = "Unique identifier for the task")
@@ -108,12 +382,17 @@ async fn do_create<C: Context>(cx: C, request: TaskCreateRequest) -> RequestResu
 )]
 pub async fn reprocess(
     State(cx): State<AppContext>,
+    Principal(principal): Principal,
     Path(task_id): Path<Uuid>,
 ) -> RequestResult<Task> {
-    do_reprocess(cx, task_id).await
+    do_reprocess(cx, task_id, principal).await
 }
 
-async fn do_reprocess<C: Context>(cx: C, task_id: Uuid) -> RequestResult<Task> {
+async fn do_reprocess<C: Context>(
+    cx: C,
+    task_id: Uuid,
+    principal: Option<String>,
+) -> RequestResult<Task> {
     let mut stored_task = {
         let mut conn = cx.get_db_conn().await?;
         Task::get(&task_id, &mut conn)
@@ -128,6 +407,7 @@ async fn do_reprocess<C: Context>(cx: C, task_id: Uuid) -> RequestResult<Task> {
     }
 
     stored_task.status = TaskResultKind::Processing;
+    stored_task.reprocessed_by = principal;
 
     let stored_task = {
         let mut conn = cx.get_db_conn().await?;
@@ -140,15 +420,77 @@ async fn do_reprocess<C: Context>(cx: C, task_id: Uuid) -> RequestResult<Task> {
     Ok(AppResponse::new(StatusCode::OK, stored_task))
 }
 
+#[utoipa::path(
+    post,
+    operation_id = "task_cancel",
+    path = "/{task_id}/cancel",
+    responses(
+        (status = OK, description = "Task cancelled successfully", body = Task),
+        (status = NOT_FOUND, description = "Task not found"),
+        (status = CONFLICT, description = "Task is not processing")
+    ),
+    params(
+        ("task_id" = Uuid, Path, description = "Unique identifier for the task")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn cancel(
+    State(cx): State<AppContext>,
+    Principal(principal): Principal,
+    Path(task_id): Path<Uuid>,
+) -> RequestResult<Task> {
+    do_cancel(cx, task_id, principal).await
+}
+
+async fn do_cancel<C: Context>(
+    cx: C,
+    task_id: Uuid,
+    principal: Option<String>,
+) -> RequestResult<Task> {
+    let failed_reason = match principal {
+        Some(principal) => format!("cancelled by {principal}"),
+        None => "cancelled".to_string(),
+    };
+    let mut conn = cx.get_db_conn().await?;
+    // the worker checks the status between processing steps and stops on cancellation,
+    // the conditional update keeps a task finished meanwhile from being cancelled
+    let Some(cancelled_task) =
+        Task::cancel_if_processing(&task_id, &failed_reason, &mut conn).await?
+    else {
+        Task::get(&task_id, &mut conn)
+            .await
+            .error(ErrorKind::EntityNotFound)?;
+        return Err(Error::new(
+            ErrorKind::TaskNotProcessing,
+            anyhow::anyhow!("task {task_id} is not processing"),
+        ));
+    };
+
+    Ok(AppResponse::new(StatusCode::OK, cancelled_task))
+}
+
 #[derive(Debug, Deserialize, IntoParams)]
 #[into_params(parameter_in = Query)]
 pub struct TaskListRequest {
     #[serde(skip_deserializing)]
     _project_id: Uuid,
     offset: i64,
-    limit: i64,
+    /// Page size, the deployment default when omitted and capped by the deployment maximum
+    limit: Option<i64>,
     order_by: String,
     desc: bool,
+    /// Only tasks with (`true`) or without (`false`) computed metrics
+    #[serde(default)]
+    has_metrics: Option<bool>,
+}
+
+impl TaskListRequest {
+    fn limit(&self, config: &Config) -> i64 {
+        self.limit
+            .unwrap_or(config.list_default_limit)
+            .min(config.list_max_limit)
+            .max(0)
+    }
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -181,13 +523,15 @@ async fn do_list<C: Context>(cx: C, request: TaskListRequest) -> RequestResult<T
     let mut conn = cx.get_db_conn().await?;
     let items = TaskWithMetadata::tasks_list(
         request.offset,
-        request.limit,
+        request.limit(cx.config()),
         &request.order_by,
         request.desc,
+        request.has_metrics,
         &mut conn,
     )
     .await?;
-    let total_count = TaskWithMetadata::total_count(Uuid::default(), &mut conn).await?;
+    let total_count =
+        TaskWithMetadata::total_count(Uuid::default(), request.has_metrics, &mut conn).await?;
 
     Ok(AppResponse::new(
         StatusCode::OK,
@@ -210,6 +554,7 @@ pub struct MetricsListResponse {
     ),
     responses(
         (status = OK, description = "List of metrics with metadata", body = MetricsListResponse),
+        (status = BAD_REQUEST, description = "Metrics can't be ordered by the given column"),
         (status = INTERNAL_SERVER_ERROR, description = "Failed to retrieve metrics list")
     ),
     tags = ["Tasks"]
@@ -217,19 +562,27 @@ pub struct MetricsListResponse {
 pub async fn metrics_list(
     State(cx): State<AppContext>,
     Query(request): Query<TaskListRequest>,
-) -> RequestResult<MetricsListResponse> {
-    do_metrics_list(cx, request).await
+) -> RequestResult<serde_json::Value> {
+    let precision = cx.config().metrics_precision;
+    do_metrics_list(cx, request)
+        .await?
+        .with_rounded_floats(precision)
 }
 
 async fn do_metrics_list<C: Context>(
     cx: C,
     request: TaskListRequest,
 ) -> RequestResult<MetricsListResponse> {
+    let order_by = MetricsColumn::parse(&request.order_by).ok_or(Error::new(
+        ErrorKind::InvalidOrderBy,
+        anyhow::anyhow!("metrics can't be ordered by {}", request.order_by),
+    ))?;
+
     let mut conn = cx.get_db_conn().await?;
     let items = MetricsWithMetadata::metrics_list(
         request.offset,
-        request.limit,
-        &request.order_by,
+        request.limit(cx.config()),
+        order_by,
         request.desc,
         &mut conn,
     )
@@ -242,31 +595,272 @@ async fn do_metrics_list<C: Context>(
     ))
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MetricsSchema {
+    /// Accepted values of `order_by` in the metrics list
+    sortable_columns: Vec<MetricsColumn>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics/schema",
+    responses(
+        (status = OK, description = "Metrics list capabilities", body = MetricsSchema)
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn metrics_schema() -> RequestResult<MetricsSchema> {
+    Ok(AppResponse::new(
+        StatusCode::OK,
+        MetricsSchema {
+            sortable_columns: MetricsColumn::ALL.to_vec(),
+        },
+    ))
+}
+
+/// How many of the latest failure reasons are returned per category
+const FAILURE_REASONS_SAMPLE: i32 = 5;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FailuresSummaryRequest {
+    /// Only calls uploaded at or after this time, in milliseconds since the epoch
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[param(value_type = Option<i64>)]
+    uploaded_from: Option<DateTime<Utc>>,
+    /// Only calls uploaded before this time, in milliseconds since the epoch
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[param(value_type = Option<i64>)]
+    uploaded_to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FailuresSummaryResponse {
+    categories: Vec<FailureCategory>,
+    total_count: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/failures/summary",
+    params(
+        FailuresSummaryRequest
+    ),
+    responses(
+        (status = OK, description = "Failed tasks grouped by failure kind with their latest reasons", body = FailuresSummaryResponse),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to summarize failures")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn failures_summary(
+    State(cx): State<AppContext>,
+    Query(request): Query<FailuresSummaryRequest>,
+) -> RequestResult<FailuresSummaryResponse> {
+    do_failures_summary(cx, request, Uuid::default()).await
+}
+
+async fn do_failures_summary<C: Context>(
+    cx: C,
+    request: FailuresSummaryRequest,
+    project_id: Uuid,
+) -> RequestResult<FailuresSummaryResponse> {
+    let mut conn = cx.get_db_conn().await?;
+    let categories = FailureCategory::summary(
+        project_id,
+        request.uploaded_from,
+        request.uploaded_to,
+        FAILURE_REASONS_SAMPLE,
+        &mut conn,
+    )
+    .await?;
+    let total_count = categories.iter().map(|category| category.count).sum();
+
+    Ok(AppResponse::new(
+        StatusCode::OK,
+        FailuresSummaryResponse {
+            categories,
+            total_count,
+        },
+    ))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MetricsAggregatesRequest {
+    group_by: AggregateGroup,
+    /// Groups with fewer scored calls are excluded, every group is kept by default
+    #[serde(default = "default_min_calls")]
+    min_calls: i64,
+}
+
+fn default_min_calls() -> i64 {
+    1
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MetricsAggregatesResponse {
+    items: Vec<MetricsAggregate>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics/aggregates",
+    params(
+        MetricsAggregatesRequest
+    ),
+    responses(
+        (status = OK, description = "Average scores per employee or client", body = MetricsAggregatesResponse),
+        (status = BAD_REQUEST, description = "min_calls is less than 1"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to aggregate metrics")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn metrics_aggregates(
+    State(cx): State<AppContext>,
+    Query(request): Query<MetricsAggregatesRequest>,
+) -> RequestResult<serde_json::Value> {
+    let precision = cx.config().metrics_precision;
+    do_metrics_aggregates(cx, request, Uuid::default())
+        .await?
+        .with_rounded_floats(precision)
+}
+
+async fn do_metrics_aggregates<C: Context>(
+    cx: C,
+    request: MetricsAggregatesRequest,
+    project_id: Uuid,
+) -> RequestResult<MetricsAggregatesResponse> {
+    if request.min_calls < 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidQueryParams,
+            anyhow::anyhow!("min_calls must be at least 1, got {}", request.min_calls),
+        ));
+    }
+
+    let mut conn = cx.get_db_conn().await?;
+    let items =
+        MetricsAggregate::list(project_id, request.group_by, request.min_calls, &mut conn).await?;
+
+    Ok(AppResponse::new(
+        StatusCode::OK,
+        MetricsAggregatesResponse { items },
+    ))
+}
+
 #[derive(Debug, PartialEq, Serialize, ToSchema)]
 pub struct TaskDetailedMetrics {
     #[serde(flatten)]
     nested: MetricsWithMetadata,
     efficiency_metrics: Vec<TaskSettingsMetrics>,
+    /// Absent when the worker has no transcript for the task or is unavailable
+    intervals: Option<MetricsIntervals>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsShape {
+    #[default]
+    Nested,
+    /// A single object with dotted keys, settings scores keyed by settings type and item id
+    Flat,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DetailedMetricsRequest {
+    #[serde(default)]
+    shape: MetricsShape,
 }
 
 #[utoipa::path(
     get,
     path = "/{task_id}/detailed_metrics",
     responses(
-        (status = OK, description = "Detailed metrics for the specified task", body = TaskDetailedMetrics),
+        (status = OK, description = "Detailed metrics for the specified task, flat objects for `shape=flat`", body = TaskDetailedMetrics),
         (status = NOT_FOUND, description = "Metrics not found"),
         (status = INTERNAL_SERVER_ERROR, description = "Failed to retrieve detailed metrics")
     ),
     params(
-        ("task_id" = Uuid, Path, description = "Unique identifier for the task")
+        ("task_id" = Uuid, Path, description = "Unique identifier for the task"),
+        DetailedMetricsRequest
     ),
     tags = ["Tasks"]
 )]
 pub async fn detailed_metrics(
     State(cx): State<AppContext>,
     Path(task_id): Path<Uuid>,
-) -> RequestResult<TaskDetailedMetrics> {
-    do_detailed_metrics(cx, task_id, Uuid::default()).await
+    Query(request): Query<DetailedMetricsRequest>,
+) -> RequestResult<serde_json::Value> {
+    let precision = cx.config().metrics_precision;
+    do_detailed_metrics(cx, task_id, Uuid::default())
+        .await?
+        .try_map(|metrics| match request.shape {
+            MetricsShape::Nested => serde_json::to_value(&metrics),
+            MetricsShape::Flat => flat_detailed_metrics(&metrics).map(serde_json::Value::Object),
+        })
+        .error(ErrorKind::SerializationFailed)?
+        .with_rounded_floats(precision)
+}
+
+/// Flattens nested objects into dotted keys, arrays of objects are keyed by index
+fn flatten_json(
+    prefix: &str,
+    value: serde_json::Value,
+    flat: &mut serde_json::Map<String, serde_json::Value>,
+) {
+    let key = |name: &str| match prefix {
+        "" => name.to_string(),
+        _ => format!("{prefix}.{name}"),
+    };
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields {
+                flatten_json(&key(&name), value, flat);
+            }
+        }
+        serde_json::Value::Array(items) if items.iter().any(|item| item.is_object()) => {
+            for (idx, item) in items.into_iter().enumerate() {
+                flatten_json(&key(&idx.to_string()), item, flat);
+            }
+        }
+        value => {
+            flat.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+fn flat_detailed_metrics(
+    metrics: &TaskDetailedMetrics,
+) -> serde_json::Result<serde_json::Map<String, serde_json::Value>> {
+    let mut flat = serde_json::Map::new();
+    flatten_json("", serde_json::to_value(&metrics.nested)?, &mut flat);
+    if let Some(intervals) = &metrics.intervals {
+        flatten_json("intervals", serde_json::to_value(intervals)?, &mut flat);
+    }
+
+    // settings are keyed by their kind and items by id, item names are neither unique nor
+    // free of dots, so they are values next to the scores
+    for settings_metrics in &metrics.efficiency_metrics {
+        let settings_key = serde_json::to_value(settings_metrics.settings.r#type)?;
+        let settings_key = settings_key.as_str().unwrap_or_default();
+        flat.insert(
+            format!("efficiency_metrics.{settings_key}.total_score"),
+            settings_metrics.total_score.into(),
+        );
+        for item in &settings_metrics.items {
+            let item_key = format!(
+                "efficiency_metrics.{settings_key}.{}",
+                item.settings_item.id
+            );
+            flat.insert(
+                format!("{item_key}.name"),
+                item.settings_item.name.clone().into(),
+            );
+            flat.insert(format!("{item_key}.score"), item.score.into());
+        }
+    }
+
+    Ok(flat)
 }
 
 async fn do_detailed_metrics<C: Context>(
@@ -274,6 +868,36 @@ async fn do_detailed_metrics<C: Context>(
     task_id: Uuid,
     project_id: Uuid,
 ) -> RequestResult<TaskDetailedMetrics> {
+    let mut conn = cx.get_db_conn().await?;
+    let project_settings = ProjectSettings::fetch(project_id, &mut conn).await?;
+    drop(conn);
+
+    let detailed_metrics = task_detailed_metrics(&cx, task_id, &project_settings).await?;
+    Ok(AppResponse::new(StatusCode::OK, detailed_metrics))
+}
+
+/// Settings of a project, fetched once to score any number of its tasks
+struct ProjectSettings {
+    settings: Vec<Settings>,
+    settings_items: Vec<SettingsItem>,
+    settings_dict_items: Vec<SettingsDictItem>,
+}
+
+impl ProjectSettings {
+    async fn fetch(project_id: Uuid, conn: &mut sqlx::PgConnection) -> Result<Self, Error> {
+        Ok(Self {
+            settings: Settings::list_by_project_id(project_id, conn).await?,
+            settings_items: SettingsItem::list_by_project_id(project_id, conn).await?,
+            settings_dict_items: SettingsDictItem::list_by_project_id(project_id, conn).await?,
+        })
+    }
+}
+
+async fn task_detailed_metrics<C: Context>(
+    cx: &C,
+    task_id: Uuid,
+    project_settings: &ProjectSettings,
+) -> Result<TaskDetailedMetrics, Error> {
     let mut conn = cx.get_db_conn().await?;
     let task_to_dicts = TaskToDict::list_by_task_id(task_id, &mut conn).await?;
     let mut call_metrics = MetricsWithMetadata::fetch_by_task_id(task_id, &mut conn)
@@ -282,106 +906,663 @@ async fn do_detailed_metrics<C: Context>(
             ErrorKind::EntityNotFound,
             anyhow::anyhow!("metrics by task id {task_id} not found"),
         ))?;
-    let settings = Settings::list_by_project_id(project_id, &mut conn).await?;
-    let settings_items = SettingsItem::list_by_project_id(project_id, &mut conn).await?;
-    let settings_dict_items = SettingsDictItem::list_by_project_id(project_id, &mut conn).await?;
+    drop(conn);
+
+    // rescored with the rules the worker scored the call with
+    let rules = call_metrics.metrics.extras.scoring.unwrap_or_default();
     let task_settings_metrics = settings_metrics::calculate_settings_metrics(
         task_to_dicts,
         &mut call_metrics.metrics,
-        settings,
-        settings_items,
-        settings_dict_items,
+        project_settings.settings.clone(),
+        project_settings.settings_items.clone(),
+        project_settings.settings_dict_items.clone(),
+        rules,
     )
     .error(ErrorKind::CalcMetricsFailed)?;
 
-    Ok(AppResponse::new(
-        StatusCode::OK,
-        TaskDetailedMetrics {
-            nested: call_metrics,
-            efficiency_metrics: task_settings_metrics,
-        },
-    ))
-}
-
-#[cfg(test)]
-mod tests {
-    use chrono::DateTime;
-    use protocol::{
-        db::{
-            dictionary::{Dictionary, Phrase},
-            metrics::CallMetrics,
-            settings::{SettingsItemKind, SettingsKind},
-        },
-        entity::ParticipantKind,
+    // the stored metrics are still meaningful without the transcript intervals
+    let intervals = match cx.worker_client().metrics_intervals_by_id(task_id).await {
+        Ok(intervals) => Some(intervals),
+        Err(err) => {
+            tracing::warn!(%task_id, "failed to fetch metrics intervals: {err}");
+            None
+        }
     };
-    use settings_metrics::TaskSettingsItemMetric;
 
-    use crate::test_helpers::context::TestContext;
+    Ok(TaskDetailedMetrics {
+        nested: call_metrics,
+        efficiency_metrics: task_settings_metrics,
+        intervals,
+    })
+}
 
-    use super::*;
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A nested detailed metrics object per line
+    #[default]
+    Jsonl,
+    /// A flat detailed metrics row per task, columns are every flat key a task of the project
+    /// can have and the `error` of a task which failed to export
+    Csv,
+}
 
-    #[sqlx::test]
-    async fn task_create(pool: sqlx::PgPool) {
-        let cx = TestContext::new(pool).await;
-        let request = TaskCreateRequest {
-            metadata: CallMetadata {
-                metadata_id: Uuid::default(),
-                call_id: 42,
-                performed_at: DateTime::default(),
-                uploaded_at: DateTime::default(),
-                file_hash: "test_hash".to_string(),
-                file_url: "s3://test.mp3".to_string(),
-                file_name: "test.mp3".to_string(),
-                duration: 100.0,
-                left_channel: ParticipantKind::Client,
-                right_channel: ParticipantKind::Employee,
-                client_name: "test_client".to_string(),
-                employee_name: "test_operator".to_string(),
-                inbound: true,
-            },
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DetailedExportRequest {
+    /// At most the deployment maximum of tasks
+    task_ids: Vec<Uuid>,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[utoipa::path(
+    post,
+    path = "/metrics/detailed_export",
+    request_body = DetailedExportRequest,
+    responses(
+        (status = OK, description = "Detailed metrics of the requested tasks as JSON lines or CSV, a task which failed to export gets a row with its error", content_type = "text/plain"),
+        (status = BAD_REQUEST, description = "Too many tasks requested"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to export detailed metrics")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn detailed_export(
+    State(cx): State<AppContext>,
+    Json(request): Json<DetailedExportRequest>,
+) -> Result<Response, Error> {
+    do_detailed_export(cx, request, Uuid::default()).await
+}
+
+async fn do_detailed_export<C>(
+    cx: C,
+    request: DetailedExportRequest,
+    project_id: Uuid,
+) -> Result<Response, Error>
+where
+    C: Context + Clone + Send + Sync + 'static,
+{
+    let max_tasks = cx.config().detailed_export_max_tasks;
+    if request.task_ids.len() > max_tasks {
+        return Err(Error::new(
+            ErrorKind::InvalidExportRequest,
+            anyhow::anyhow!(
+                "{} tasks requested, at most {max_tasks} can be exported at once",
+                request.task_ids.len()
+            ),
+        ));
+    }
+
+    let mut conn = cx.get_db_conn().await?;
+    let project_settings = Arc::new(ProjectSettings::fetch(project_id, &mut conn).await?);
+    drop(conn);
+
+    let format = request.format;
+    let (content_type, filename, header, columns) = match format {
+        ExportFormat::Jsonl => ("application/jsonl", "detailed_metrics.jsonl", None, None),
+        ExportFormat::Csv => {
+            let columns = csv_columns(&project_settings).error(ErrorKind::SerializationFailed)?;
+            let header = columns
+                .iter()
+                .map(|column| csv_field(column))
+                .collect::<Vec<_>>()
+                .join(",");
+            (
+                "text/csv",
+                "detailed_metrics.csv",
+                Some(header + "\n"),
+                Some(columns),
+            )
+        }
+    };
+
+    // rows are computed while the body is streamed, one at a time
+    let precision = cx.config().metrics_precision;
+    let rows = stream::iter(request.task_ids).then(move |task_id| {
+        let cx = cx.clone();
+        let project_settings = project_settings.clone();
+        async move { export_row(&cx, task_id, &project_settings, format, precision).await }
+    });
+    let lines = rows.map(move |row| match &columns {
+        Some(columns) => csv_row(columns, &row),
+        None => format!("{row}\n"),
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, content_type)
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .body(Body::from_stream(
+            stream::iter(header).chain(lines).map(Ok::<_, Infallible>),
+        ))
+        .map_err(|err| Error::new(ErrorKind::SerializationFailed, anyhow::anyhow!(err)))
+}
+
+/// Detailed metrics of a task in the export format, a task which fails to export
+/// is reported in its row instead of aborting the export
+async fn export_row<C: Context>(
+    cx: &C,
+    task_id: Uuid,
+    project_settings: &ProjectSettings,
+    format: ExportFormat,
+    precision: u32,
+) -> serde_json::Value {
+    let row = match task_detailed_metrics(cx, task_id, project_settings).await {
+        Ok(metrics) => match format {
+            ExportFormat::Jsonl => serde_json::to_value(&metrics),
+            ExportFormat::Csv => flat_detailed_metrics(&metrics).map(serde_json::Value::Object),
+        }
+        .error(ErrorKind::SerializationFailed),
+        Err(err) => Err(err),
+    };
+
+    match row {
+        Ok(mut row) => {
+            round_floats(&mut row, precision);
+            row
+        }
+        Err(err) => {
+            tracing::warn!(%task_id, "failed to export detailed metrics: {err}");
+            let task_id_key = match format {
+                ExportFormat::Jsonl => "task_id",
+                ExportFormat::Csv => "metrics.task_id",
+            };
+            let mut row = serde_json::Map::new();
+            row.insert(task_id_key.to_string(), task_id.to_string().into());
+            row.insert("error".to_string(), err.to_string().into());
+            serde_json::Value::Object(row)
+        }
+    }
+}
+
+/// Flat keys of a task scored with every settings item of the project, so the header
+/// is known before any row is computed
+fn csv_columns(project_settings: &ProjectSettings) -> serde_json::Result<Vec<String>> {
+    let metrics = CallMetrics {
+        extras: sqlx::types::Json(CallMetricsExtras {
+            talk_balance_by_thirds: vec![TalkBalance::default(); 3],
+            scoring: Some(ScoringRules::default()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let metadata = CallMetadata {
+        metadata_id: Uuid::default(),
+        call_id: 0,
+        performed_at: DateTime::default(),
+        uploaded_at: DateTime::default(),
+        file_hash: String::new(),
+        file_url: String::new(),
+        file_name: String::new(),
+        duration: 0.0,
+        left_channel: ParticipantKind::Client,
+        right_channel: ParticipantKind::Employee,
+        client_name: String::new(),
+        employee_name: String::new(),
+        inbound: false,
+        left_channel_url: None,
+        right_channel_url: None,
+    };
+    let efficiency_metrics = project_settings
+        .settings
+        .iter()
+        .map(|settings| TaskSettingsMetrics {
+            settings: settings.clone(),
+            total_score: 0,
+            items: project_settings
+                .settings_items
+                .iter()
+                .filter(|settings_item| settings_item.settings_id == settings.id)
+                .map(|settings_item| TaskSettingsItemMetric {
+                    settings_item: settings_item.clone(),
+                    score: 0,
+                })
+                .collect(),
+            unscored: false,
+        })
+        .collect();
+    let mut template = TaskDetailedMetrics {
+        nested: MetricsWithMetadata { metadata, metrics },
+        efficiency_metrics,
+        intervals: Some(MetricsIntervals::default()),
+    };
+
+    let mut columns: BTreeSet<String> = flat_detailed_metrics(&template)?
+        .into_iter()
+        .map(|(column, _)| column)
+        .collect();
+    // an empty talk balance, of a call without speech, is flattened into a key of its own
+    template
+        .nested
+        .metrics
+        .extras
+        .talk_balance_by_thirds
+        .clear();
+    columns.extend(
+        flat_detailed_metrics(&template)?
+            .into_iter()
+            .map(|(column, _)| column),
+    );
+    columns.insert("error".to_string());
+    Ok(columns.into_iter().collect())
+}
+
+/// A key absent from the row is left empty, keys absent from the columns are dropped
+fn csv_row(columns: &[String], row: &serde_json::Value) -> String {
+    let fields = columns
+        .iter()
+        .map(|column| match row.get(column) {
+            None | Some(serde_json::Value::Null) => String::new(),
+            Some(serde_json::Value::String(value)) => csv_field(value),
+            Some(value) => csv_field(&value.to_string()),
+        })
+        .collect::<Vec<_>>();
+    fields.join(",") + "\n"
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Transcript and detailed metrics of a task, as a single response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskFull {
+    transcript: RecognitionData,
+    #[serde(flatten)]
+    metrics: TaskDetailedMetrics,
+}
+
+#[utoipa::path(
+    get,
+    path = "/{task_id}/full",
+    responses(
+        (status = OK, description = "Transcript with detailed metrics for the specified task", body = TaskFull),
+        (status = NOT_FOUND, description = "Metrics or transcript not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to retrieve transcript or metrics")
+    ),
+    params(
+        ("task_id" = Uuid, Path, description = "Unique identifier for the task")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn full(
+    State(cx): State<AppContext>,
+    Path(task_id): Path<Uuid>,
+) -> RequestResult<serde_json::Value> {
+    let precision = cx.config().metrics_precision;
+    do_full(cx, task_id, Uuid::default())
+        .await?
+        .with_rounded_floats(precision)
+}
+
+async fn do_full<C: Context>(cx: C, task_id: Uuid, project_id: Uuid) -> RequestResult<TaskFull> {
+    // a task without metrics is reported as not found before the worker is asked for a transcript
+    let mut conn = cx.get_db_conn().await?;
+    let project_settings = ProjectSettings::fetch(project_id, &mut conn).await?;
+    drop(conn);
+    let metrics = task_detailed_metrics(&cx, task_id, &project_settings).await?;
+
+    let raw_transcript = cx
+        .worker_client()
+        .raw_transcript_by_id(task_id)
+        .await
+        .map_err(|err| match err {
+            WorkerClientError::ResponseStatus(StatusCode::NOT_FOUND) => Error::new(
+                ErrorKind::EntityNotFound,
+                anyhow::anyhow!("transcript by task id {task_id} not found"),
+            ),
+            err => Error::new(ErrorKind::WorkerRequestFailed, anyhow::anyhow!(err)),
+        })?;
+    let transcript = serde_json::from_slice::<RecognitionData>(&raw_transcript)
+        .error(ErrorKind::DeserializationFailed)?;
+
+    Ok(AppResponse::new(
+        StatusCode::OK,
+        TaskFull {
+            transcript,
+            metrics,
+        },
+    ))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ScorePreviewRequest {
+    settings: Vec<Settings>,
+    settings_items: Vec<SettingsItem>,
+    settings_dict_items: Vec<SettingsDictItem>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/{task_id}/score_preview",
+    request_body = ScorePreviewRequest,
+    responses(
+        (status = OK, description = "Settings metrics calculated with proposed settings", body = Vec<TaskSettingsMetrics>),
+        (status = NOT_FOUND, description = "Metrics not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to calculate settings metrics")
+    ),
+    params(
+        ("task_id" = Uuid, Path, description = "Unique identifier for the task")
+    ),
+    tags = ["Tasks"]
+)]
+pub async fn score_preview(
+    State(cx): State<AppContext>,
+    Path(task_id): Path<Uuid>,
+    Json(request): Json<ScorePreviewRequest>,
+) -> RequestResult<Vec<TaskSettingsMetrics>> {
+    do_score_preview(cx, task_id, request).await
+}
+
+async fn do_score_preview<C: Context>(
+    cx: C,
+    task_id: Uuid,
+    request: ScorePreviewRequest,
+) -> RequestResult<Vec<TaskSettingsMetrics>> {
+    let mut conn = cx.get_db_conn().await?;
+    let task_to_dicts = TaskToDict::list_by_task_id(task_id, &mut conn).await?;
+    let mut call_metrics = CallMetrics::fetch_by_task_id(task_id, &mut conn)
+        .await
+        .error(ErrorKind::EntityNotFound)?;
+    drop(conn);
+
+    // proposed settings are not persisted, scores are calculated in memory only
+    let rules = call_metrics.extras.scoring.unwrap_or_default();
+    let task_settings_metrics = settings_metrics::calculate_settings_metrics(
+        task_to_dicts,
+        &mut call_metrics,
+        request.settings,
+        request.settings_items,
+        request.settings_dict_items,
+        rules,
+    )
+    .error(ErrorKind::CalcMetricsFailed)?;
+
+    Ok(AppResponse::new(StatusCode::OK, task_settings_metrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use protocol::{
+        db::{
+            dictionary::{Dictionary, Phrase},
+            settings::{SettingsItemKind, SettingsKind},
+            task::TaskFailureKind,
+        },
+        entity::speech_recog::{Interval, SpeechRecognition},
+    };
+
+    use crate::test_helpers::{call_metadata, context::TestContext};
+
+    use super::*;
+
+    #[sqlx::test]
+    async fn task_create(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let request = TaskCreateRequest {
+            metadata: call_metadata(42),
             _project_id: Uuid::default(),
         };
 
-        let task_resp = do_create(cx.clone(), request.clone())
+        let task_resp = do_create(cx.clone(), request.clone(), None)
             .await
             .expect("failed to create task");
         assert_eq!(task_resp.status(), StatusCode::CREATED);
         let task = task_resp.payload();
         assert_eq!(task.status, TaskResultKind::Processing);
 
-        let task_resp = do_create(cx.clone(), request.clone())
+        let task_resp = do_create(cx.clone(), request.clone(), None)
             .await
             .expect_err("unexpected success while creating task");
         assert_eq!(task_resp.kind, ErrorKind::FileAlredyExists);
+        assert_eq!(StatusCode::from(task_resp.kind), StatusCode::CONFLICT);
+
+        let reprocess_resp = do_reprocess(cx.clone(), task.id, None)
+            .await
+            .expect_err("unexpected success while reprocessing task");
+        assert_eq!(reprocess_resp.kind, ErrorKind::TaskAlreadyProcessing);
+        assert_eq!(StatusCode::from(reprocess_resp.kind), StatusCode::CONFLICT);
 
-        let published = cx.test_publisher().flush().await;
-        assert_eq!(published, vec![serde_json::json!(task.id)]);
+        assert_eq!(
+            cx.test_publisher().published_task_ids().await,
+            vec![task.id]
+        );
     }
 
     #[sqlx::test]
-    async fn task_list(pool: sqlx::PgPool) {
+    async fn task_create_same_hash_in_projects(pool: sqlx::PgPool) {
         let cx = TestContext::new(pool).await;
-        let mut metadata = CallMetadata {
-            metadata_id: Uuid::default(),
-            call_id: 42,
-            performed_at: DateTime::default(),
-            uploaded_at: DateTime::default(),
-            file_hash: "test_hash".to_string(),
-            file_url: "s3://test.mp3".to_string(),
-            file_name: "test.mp3".to_string(),
-            duration: 100.0,
-            left_channel: ParticipantKind::Client,
-            right_channel: ParticipantKind::Employee,
-            client_name: "test_client".to_string(),
-            employee_name: "test_operator".to_string(),
-            inbound: true,
+        let metadata = call_metadata(42);
+
+        for project_id in [Uuid::default(), Uuid::new_v4()] {
+            let request = TaskCreateRequest {
+                metadata: metadata.clone(),
+                _project_id: project_id,
+            };
+            let task_resp = do_create(cx.clone(), request.clone(), None)
+                .await
+                .expect("failed to create task");
+            assert_eq!(task_resp.status(), StatusCode::CREATED);
+            assert_eq!(task_resp.payload().project_id, project_id);
+
+            let task_resp = do_create(cx.clone(), request, None)
+                .await
+                .expect_err("unexpected success while creating task");
+            assert_eq!(task_resp.kind, ErrorKind::FileAlredyExists);
+        }
+    }
+
+    #[sqlx::test]
+    async fn task_import(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool).await;
+        cx.config_mut().import_batch_size = 2;
+
+        let line = |hash: &str| {
+            serde_json::json!({
+                "call_id": 42,
+                "performed_at": 0,
+                "uploaded_at": 0,
+                "file_hash": hash,
+                "file_url": "s3://test.mp3",
+                "file_name": "test.mp3",
+                "duration": 100.0,
+                "left_channel": "client",
+                "right_channel": "employee",
+                "client_name": "test_client",
+                "employee_name": "test_operator",
+                "inbound": true
+            })
+            .to_string()
         };
+        let manifest = [
+            line("hash_1"),
+            "{\"call_id\": 42,".to_string(),
+            String::new(),
+            line("hash_2"),
+            line("hash_1"),
+        ]
+        .join("\n");
+
+        let resp = do_import(cx.clone(), manifest.as_bytes(), None)
+            .await
+            .expect("failed to import tasks");
+        let report = resp.payload();
+        assert_eq!(report.created.len(), 2);
+        assert_eq!(
+            report.errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![2, 5]
+        );
+        assert!(report.errors[0].error.starts_with("malformed metadata"));
+
+        assert_eq!(
+            cx.test_publisher().published_task_ids().await,
+            report.created
+        );
+        assert!(report.publish_errors.is_empty());
+
+        // stored tasks which failed to be queued are reported per batch
+        cx.test_publisher().set_unavailable(true);
+        let manifest = [line("hash_3"), line("hash_4"), line("hash_5")].join("\n");
+        let resp = do_import(cx.clone(), manifest.as_bytes(), None)
+            .await
+            .expect("failed to import tasks");
+        let report = resp.payload();
+        assert_eq!(report.created.len(), 3);
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            report
+                .publish_errors
+                .iter()
+                .map(|e| e.task_ids.clone())
+                .collect::<Vec<_>>(),
+            vec![report.created[..2].to_vec(), report.created[2..].to_vec()]
+        );
+        assert!(cx.test_publisher().flush().await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn task_principals(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let request = TaskCreateRequest {
+            metadata: call_metadata(42),
+            _project_id: Uuid::default(),
+        };
+
+        let task_resp = do_create(cx.clone(), request, Some("creator_key".to_string()))
+            .await
+            .expect("failed to create task");
+        let mut task = task_resp.payload().clone();
+        assert_eq!(task.created_by.as_deref(), Some("creator_key"));
+        assert_eq!(task.reprocessed_by, None);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let stored_task = Task::get(&task.id, &mut conn).await.unwrap();
+        assert_eq!(stored_task.created_by.as_deref(), Some("creator_key"));
+
+        task.status = TaskResultKind::Ready;
+        task.update(&mut conn).await.unwrap();
+
+        let reprocess_resp = do_reprocess(cx, task.id, Some("reviewer_key".to_string()))
+            .await
+            .expect("failed to reprocess task");
+        let reprocessed_task = reprocess_resp.payload();
+        assert_eq!(reprocessed_task.created_by.as_deref(), Some("creator_key"));
+        assert_eq!(
+            reprocessed_task.reprocessed_by.as_deref(),
+            Some("reviewer_key")
+        );
+    }
+
+    #[sqlx::test]
+    async fn task_create_file_url(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let request = |file_url: &str| TaskCreateRequest {
+            metadata: CallMetadata {
+                file_url: file_url.to_string(),
+                ..call_metadata(42)
+            },
+            _project_id: Uuid::default(),
+        };
+
+        let err = do_create(cx.clone(), request("file:///etc/passwd"), None)
+            .await
+            .expect_err("unexpected success for file url");
+        assert_eq!(err.kind, ErrorKind::InvalidMetadata);
+        assert_eq!(StatusCode::from(err.kind), StatusCode::BAD_REQUEST);
+
+        let task_resp = do_create(cx.clone(), request(" S3://bucket/test.mp3"), None)
+            .await
+            .expect("failed to create task");
+        assert_eq!(task_resp.status(), StatusCode::CREATED);
+
+        let mut conn = cx.get_db_conn().await.unwrap();
+        let metadata = CallMetadata::get_by_task_id(task_resp.payload().id, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(metadata.file_url, "s3://bucket/test.mp3");
+    }
+
+    #[test]
+    fn file_url_allowlist_entries() {
+        let allowlist = [
+            "s3://bucket".to_string(),
+            "https://Storage.example.com/calls/".to_string(),
+        ];
+        let normalize = |file_url| normalize_file_url(file_url, &allowlist);
+
+        assert_eq!(
+            normalize("S3://BUCKET/test.mp3").unwrap(),
+            "s3://bucket/test.mp3"
+        );
+        assert_eq!(
+            normalize("https://storage.EXAMPLE.com/calls/2024/test.mp3").unwrap(),
+            "https://storage.example.com/calls/2024/test.mp3"
+        );
+        for file_url in [
+            "s3://bucket-evil/test.mp3",
+            "s3://bucket.evil/test.mp3",
+            "s3://other/bucket/test.mp3",
+            "https://storage.example.com/calls-evil/test.mp3",
+            "https://storage.example.com.evil/calls/test.mp3",
+            "http://storage.example.com/calls/test.mp3",
+            "bucket/test.mp3",
+        ] {
+            let err = normalize(file_url).expect_err(file_url);
+            assert_eq!(err.kind, ErrorKind::InvalidMetadata);
+        }
+    }
+
+    #[sqlx::test]
+    async fn task_cancel(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let request = TaskCreateRequest {
+            metadata: call_metadata(42),
+            _project_id: Uuid::default(),
+        };
+        let task_resp = do_create(cx.clone(), request, None)
+            .await
+            .expect("failed to create task");
+        let task = task_resp.payload();
+
+        let cancel_resp = do_cancel(cx.clone(), task.id, Some("reviewer_key".to_string()))
+            .await
+            .expect("failed to cancel task");
+        let cancelled_task = cancel_resp.payload();
+        assert_eq!(cancelled_task.status, TaskResultKind::Cancelled);
+        assert_eq!(
+            cancelled_task.failed_reason.as_deref(),
+            Some("cancelled by reviewer_key")
+        );
+
+        let cancel_err = do_cancel(cx.clone(), task.id, None)
+            .await
+            .expect_err("unexpected success while cancelling cancelled task");
+        assert_eq!(cancel_err.kind, ErrorKind::TaskNotProcessing);
+
+        let cancel_err = do_cancel(cx, Uuid::new_v4(), None)
+            .await
+            .expect_err("unexpected success while cancelling missing task");
+        assert_eq!(cancel_err.kind, ErrorKind::EntityNotFound);
+    }
+
+    #[sqlx::test]
+    async fn task_list(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let mut metadata = call_metadata(42);
         let request = TaskCreateRequest {
             metadata: metadata.clone(),
             _project_id: Uuid::default(),
         };
 
-        let task_resp = do_create(cx.clone(), request.clone())
+        let task_resp = do_create(cx.clone(), request.clone(), None)
             .await
             .expect("failed to create task");
         assert_eq!(task_resp.status(), StatusCode::CREATED);
@@ -393,9 +1574,10 @@ mod tests {
             TaskListRequest {
                 _project_id: Uuid::default(),
                 offset: 0,
-                limit: 10,
+                limit: Some(10),
                 order_by: "file_name".to_string(),
                 desc: true,
+                has_metrics: None,
             },
         )
         .await
@@ -414,30 +1596,305 @@ mod tests {
     }
 
     #[sqlx::test]
-    async fn detailed_metrics(pool: sqlx::PgPool) {
+    async fn task_list_limits(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool).await;
+        cx.config_mut().list_default_limit = 1;
+        cx.config_mut().list_max_limit = 2;
+
+        for idx in 0..3 {
+            let request = TaskCreateRequest {
+                metadata: CallMetadata {
+                    file_name: format!("test_{idx}.mp3"),
+                    ..call_metadata(idx)
+                },
+                _project_id: Uuid::default(),
+            };
+            do_create(cx.clone(), request, None)
+                .await
+                .expect("failed to create task");
+        }
+
+        for (limit, expected) in [(None, 1), (Some(1000), 2)] {
+            let list_response = do_list(
+                cx.clone(),
+                TaskListRequest {
+                    _project_id: Uuid::default(),
+                    offset: 0,
+                    limit,
+                    order_by: "file_name".to_string(),
+                    desc: true,
+                    has_metrics: None,
+                },
+            )
+            .await
+            .expect("failed to retrieve tasks list");
+
+            assert_eq!(list_response.payload().total_count, 3);
+            assert_eq!(list_response.payload().items.len(), expected);
+        }
+    }
+
+    #[sqlx::test]
+    async fn metrics_list_order_by(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let schema = metrics_schema()
+            .await
+            .expect("failed to retrieve metrics schema");
+        let sortable_columns = &schema.payload().sortable_columns;
+        assert!(sortable_columns.contains(&MetricsColumn::OverallScore));
+        assert_eq!(
+            serde_json::to_value(sortable_columns).unwrap()[4],
+            "file_name"
+        );
+
+        let request = |order_by: &str| TaskListRequest {
+            _project_id: Uuid::default(),
+            offset: 0,
+            limit: None,
+            order_by: order_by.to_string(),
+            desc: true,
+            has_metrics: None,
+        };
+        let mut conn = cx.get_db_conn().await.unwrap();
+        let mut task_ids = vec![];
+        for (call_id, unscored) in [(1, true), (2, false)] {
+            let request = TaskCreateRequest {
+                metadata: call_metadata(call_id),
+                _project_id: Uuid::default(),
+            };
+            let task_id = do_create(cx.clone(), request, None)
+                .await
+                .expect("failed to create task")
+                .payload()
+                .id;
+            let mut metrics = CallMetrics {
+                task_id,
+                overall_score: if unscored { 0 } else { 40 },
+                ..Default::default()
+            };
+            metrics.extras.unscored = unscored;
+            CallMetrics::insert(metrics, &mut conn).await.unwrap();
+            task_ids.push(task_id);
+        }
+
+        // unscored calls go last in both directions instead of ranking as the worst
+        for desc in [false, true] {
+            let items = do_metrics_list(
+                cx.clone(),
+                TaskListRequest {
+                    desc,
+                    ..request("overall_score")
+                },
+            )
+            .await
+            .expect("failed to order metrics by a sortable column")
+            .payload()
+            .items
+            .iter()
+            .map(|item| item.metrics.task_id)
+            .collect::<Vec<_>>();
+            assert_eq!(items, vec![task_ids[1], task_ids[0]]);
+        }
+
+        for order_by in ["file_name", "call_id", "employee_name", "client_name"] {
+            do_metrics_list(cx.clone(), request(order_by))
+                .await
+                .expect("failed to order metrics by a call metadata column");
+        }
+
+        for order_by in ["file_hash", "overall_score; DROP TABLE task"] {
+            let err = do_metrics_list(cx.clone(), request(order_by))
+                .await
+                .expect_err("metrics ordered by a column absent from the schema");
+            assert_eq!(err.kind, ErrorKind::InvalidOrderBy);
+        }
+    }
+
+    #[sqlx::test]
+    async fn metrics_aggregates_min_calls(pool: sqlx::PgPool) {
         let cx = TestContext::new(pool.clone()).await;
         let project_id = Uuid::new_v4();
-        let mut metadata = CallMetadata {
-            metadata_id: Uuid::default(),
-            call_id: 42,
-            performed_at: DateTime::default(),
-            uploaded_at: DateTime::default(),
-            file_hash: "test_hash".to_string(),
-            file_url: "s3://test.mp3".to_string(),
-            file_name: "test.mp3".to_string(),
-            duration: 100.0,
-            left_channel: ParticipantKind::Client,
-            right_channel: ParticipantKind::Employee,
-            client_name: "test_client".to_string(),
-            employee_name: "test_operator".to_string(),
-            inbound: true,
+        let mut conn = pool.acquire().await.unwrap();
+        for (call_id, employee_name, overall_score, unscored) in [
+            (1, "busy_operator", 60, false),
+            (2, "busy_operator", 80, false),
+            (3, "new_operator", 100, false),
+            (4, "new_operator", 0, true),
+        ] {
+            let request = TaskCreateRequest {
+                metadata: CallMetadata {
+                    employee_name: employee_name.to_string(),
+                    ..call_metadata(call_id)
+                },
+                _project_id: project_id,
+            };
+            let task_resp = do_create(cx.clone(), request, None)
+                .await
+                .expect("failed to create task");
+            let mut metrics = CallMetrics {
+                task_id: task_resp.payload().id,
+                overall_score,
+                ..Default::default()
+            };
+            metrics.extras.unscored = unscored;
+            CallMetrics::insert(metrics, &mut conn).await.unwrap();
+
+            // only the latest processing of a call counts
+            if call_id == 2 {
+                let reprocessed = Task {
+                    status: TaskResultKind::Ready,
+                    ..task_resp.payload().clone()
+                }
+                .insert(&mut conn)
+                .await
+                .unwrap();
+                CallMetrics::insert(
+                    CallMetrics {
+                        task_id: reprocessed.id,
+                        overall_score: 40,
+                        ..Default::default()
+                    },
+                    &mut conn,
+                )
+                .await
+                .unwrap();
+            }
+        }
+
+        let aggregates = |min_calls| {
+            do_metrics_aggregates(
+                cx.clone(),
+                MetricsAggregatesRequest {
+                    group_by: AggregateGroup::Employee,
+                    min_calls,
+                },
+                project_id,
+            )
         };
+        let response = aggregates(default_min_calls())
+            .await
+            .expect("failed to aggregate metrics");
+        let names = response
+            .payload()
+            .items
+            .iter()
+            .map(|item| (item.name.as_str(), item.calls_count, item.avg_overall_score))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("new_operator", 1, 100.0), ("busy_operator", 2, 50.0)]
+        );
+
+        let response = aggregates(2).await.expect("failed to aggregate metrics");
+        let names = response
+            .payload()
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["busy_operator"]);
+
+        let err = aggregates(0)
+            .await
+            .expect_err("aggregated metrics with a non-positive min_calls");
+        assert_eq!(err.kind, ErrorKind::InvalidQueryParams);
+    }
+
+    #[sqlx::test]
+    async fn failures_summary(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::new_v4();
+        let mut conn = pool.acquire().await.unwrap();
+        let failures = [
+            (Some(TaskFailureKind::AsrTimeout), "asr timed out"),
+            (Some(TaskFailureKind::NoSpeech), "no speech"),
+            (Some(TaskFailureKind::AsrTimeout), "asr timed out again"),
+            (None, "processed"),
+        ];
+        for (call_id, (failure_kind, reason)) in failures.into_iter().enumerate() {
+            let request = TaskCreateRequest {
+                metadata: CallMetadata {
+                    uploaded_at: DateTime::default() + chrono::Duration::days(call_id as i64),
+                    ..call_metadata(call_id as i64)
+                },
+                _project_id: project_id,
+            };
+            let mut task = do_create(cx.clone(), request, None)
+                .await
+                .expect("failed to create task")
+                .payload()
+                .clone();
+            task.status = match failure_kind {
+                Some(_) => TaskResultKind::Failed,
+                None => TaskResultKind::Ready,
+            };
+            task.failure_kind = failure_kind;
+            task.failed_reason = failure_kind.map(|_| reason.to_string());
+            task.update(&mut conn).await.unwrap();
+        }
+
+        let response = do_failures_summary(cx.clone(), Default::default(), project_id)
+            .await
+            .expect("failed to summarize failures");
+        let summary = response.payload();
+        assert_eq!(summary.total_count, 3);
+        assert_eq!(
+            summary.categories,
+            vec![
+                FailureCategory {
+                    failure_kind: TaskFailureKind::AsrTimeout,
+                    count: 2,
+                    recent_reasons: vec![
+                        "asr timed out again".to_string(),
+                        "asr timed out".to_string()
+                    ],
+                },
+                FailureCategory {
+                    failure_kind: TaskFailureKind::NoSpeech,
+                    count: 1,
+                    recent_reasons: vec!["no speech".to_string()],
+                },
+            ]
+        );
+
+        let day_ms = chrono::Duration::days(1).num_milliseconds();
+        let uri = format!("/?uploaded_from={day_ms}&uploaded_to={}", 2 * day_ms)
+            .parse()
+            .unwrap();
+        let Query(request) = Query::<FailuresSummaryRequest>::try_from_uri(&uri).unwrap();
+        let response = do_failures_summary(cx, request, project_id)
+            .await
+            .expect("failed to summarize failures");
+        let summary = response.payload();
+        assert_eq!(summary.total_count, 1);
+        assert_eq!(
+            summary.categories[0].failure_kind,
+            TaskFailureKind::NoSpeech
+        );
+    }
+
+    #[sqlx::test]
+    async fn detailed_metrics(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        cx.worker_client_mock()
+            .expect_metrics_intervals_by_id()
+            .returning(|_| {
+                Ok(MetricsIntervals {
+                    client_interruptions: vec![Interval {
+                        start: 9.0,
+                        end: 12.0,
+                    }],
+                    silence_pauses: vec![],
+                })
+            });
+        let project_id = Uuid::new_v4();
+        let mut metadata = call_metadata(42);
         let request = TaskCreateRequest {
             metadata: metadata.clone(),
             _project_id: project_id,
         };
 
-        let task_resp = do_create(cx.clone(), request.clone())
+        let task_resp = do_create(cx.clone(), request.clone(), None)
             .await
             .expect("failed to create task");
         assert_eq!(task_resp.status(), StatusCode::CREATED);
@@ -490,12 +1947,13 @@ mod tests {
                 name: "filler_words_test".to_string(),
                 r#type: SettingsItemKind::FillerWordsDict,
                 score_weight: 1,
+                graded_threshold: None,
             },
             &mut conn,
         )
         .await
         .unwrap();
-        let _ = SettingsDictItem::bulk_insert(
+        SettingsDictItem::bulk_insert(
             vec![SettingsDictItem {
                 id: Uuid::default(),
                 settings_item_id: settings_item.id,
@@ -508,8 +1966,10 @@ mod tests {
         .unwrap();
 
         let mut metrics = {
-            let mut metrics = CallMetrics::default();
-            metrics.task_id = task.id;
+            let metrics = CallMetrics {
+                task_id: task.id,
+                ..Default::default()
+            };
             CallMetrics::insert(metrics.clone(), &mut conn)
                 .await
                 .unwrap();
@@ -524,6 +1984,7 @@ mod tests {
         let detailed_metrics = response.payload();
 
         metrics.script_score = 100;
+        metrics.overall_score = 50;
 
         assert_eq!(
             detailed_metrics,
@@ -535,9 +1996,580 @@ mod tests {
                     items: vec![TaskSettingsItemMetric {
                         settings_item,
                         score: 100
-                    }]
-                }]
+                    }],
+                    unscored: false,
+                }],
+                intervals: Some(MetricsIntervals {
+                    client_interruptions: vec![Interval {
+                        start: 9.0,
+                        end: 12.0,
+                    }],
+                    silence_pauses: vec![],
+                }),
             }
+        );
+    }
+
+    #[test]
+    fn flat_detailed_metrics_keys() {
+        let settings = Settings {
+            id: Uuid::new_v4(),
+            project_id: Uuid::default(),
+            r#type: SettingsKind::Script,
+        };
+        // names are free text, duplicated and dotted ones must not collide
+        let settings_items = ["greeting", "greeting", "greeting.score"].map(|name| SettingsItem {
+            id: Uuid::new_v4(),
+            settings_id: settings.id,
+            settings_immutable: false,
+            name: name.to_string(),
+            r#type: SettingsItemKind::Dictionary,
+            score_weight: 1,
+            graded_threshold: None,
+        });
+        let metrics = TaskDetailedMetrics {
+            nested: MetricsWithMetadata {
+                metadata: call_metadata(42),
+                metrics: CallMetrics {
+                    script_score: 100,
+                    ..Default::default()
+                },
+            },
+            efficiency_metrics: vec![TaskSettingsMetrics {
+                settings,
+                total_score: 50,
+                items: settings_items
+                    .iter()
+                    .zip([100, 0, 50])
+                    .map(|(settings_item, score)| TaskSettingsItemMetric {
+                        settings_item: settings_item.clone(),
+                        score,
+                    })
+                    .collect(),
+                unscored: false,
+            }],
+            intervals: Some(MetricsIntervals {
+                client_interruptions: vec![Interval {
+                    start: 9.0,
+                    end: 12.0,
+                }],
+                silence_pauses: vec![],
+            }),
+        };
+
+        let flat = flat_detailed_metrics(&metrics).expect("failed to flatten metrics");
+        assert_eq!(flat["metrics.script_score"], 100);
+        assert_eq!(flat["metadata.call_id"], 42);
+        assert_eq!(flat["metrics.extras.active_call_duration"], 0.0);
+        assert!(flat.contains_key("intervals.client_interruptions"));
+        assert_eq!(flat["efficiency_metrics.script.total_score"], 50);
+        for (settings_item, score) in settings_items.iter().zip([100, 0, 50]) {
+            let item_key = format!("efficiency_metrics.script.{}", settings_item.id);
+            assert_eq!(flat[&format!("{item_key}.name")], settings_item.name);
+            assert_eq!(flat[&format!("{item_key}.score")], score);
+        }
+        assert!(!flat.contains_key("efficiency_metrics.script.greeting.score"));
+        assert!(flat.values().all(|value| !value.is_object()));
+    }
+
+    #[sqlx::test]
+    async fn detailed_metrics_without_intervals(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        cx.worker_client_mock()
+            .expect_metrics_intervals_by_id()
+            .returning(|_| Err(WorkerClientError::ResponseStatus(StatusCode::NOT_FOUND)));
+        let request = TaskCreateRequest {
+            metadata: call_metadata(42),
+            _project_id: Uuid::default(),
+        };
+        let task = do_create(cx.clone(), request, None)
+            .await
+            .expect("failed to create task")
+            .payload()
+            .clone();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let metrics = CallMetrics {
+            task_id: task.id,
+            call_duration: 60.0,
+            ..Default::default()
+        };
+        CallMetrics::insert(metrics, &mut conn).await.unwrap();
+
+        let response = do_detailed_metrics(cx, task.id, Uuid::default())
+            .await
+            .expect("metrics are served without the transcript");
+        assert_eq!(response.status(), StatusCode::OK);
+        let detailed_metrics = response.payload();
+        assert_eq!(detailed_metrics.intervals, None);
+        assert_eq!(detailed_metrics.nested.metrics.call_duration, 60.0);
+
+        let flat = flat_detailed_metrics(detailed_metrics).expect("failed to flatten metrics");
+        assert!(!flat.keys().any(|key| key.starts_with("intervals")));
+    }
+
+    #[sqlx::test]
+    async fn detailed_export(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        cx.worker_client_mock()
+            .expect_metrics_intervals_by_id()
+            .returning(|_| {
+                Ok(MetricsIntervals {
+                    client_interruptions: vec![],
+                    silence_pauses: vec![],
+                })
+            });
+        let project_id = Uuid::new_v4();
+        let mut conn = pool.acquire().await.unwrap();
+
+        let dict = Dictionary::insert("test_dict".to_owned(), ParticipantKind::Employee, &mut conn)
+            .await
+            .unwrap();
+        let settings = Settings::insert(
+            Settings {
+                id: Uuid::default(),
+                project_id,
+                r#type: SettingsKind::Script,
+            },
+            &mut conn,
         )
+        .await
+        .unwrap();
+        let settings_item = SettingsItem::insert(
+            SettingsItem {
+                id: Uuid::default(),
+                settings_id: settings.id,
+                settings_immutable: false,
+                name: "greeting".to_string(),
+                r#type: SettingsItemKind::Dictionary,
+                score_weight: 1,
+                graded_threshold: None,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        SettingsDictItem::bulk_insert(
+            vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: settings_item.id,
+                dictionary_id: dict.id,
+                contains: true,
+            }],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        let mut task_ids = vec![];
+        for call_id in [1, 2] {
+            let request = TaskCreateRequest {
+                metadata: call_metadata(call_id),
+                _project_id: project_id,
+            };
+            let task = do_create(cx.clone(), request, None)
+                .await
+                .expect("failed to create task")
+                .payload()
+                .clone();
+            // only the first call contains the greeting
+            TaskToDict::insert(
+                TaskToDict {
+                    task_id: task.id,
+                    dictionary_id: dict.id,
+                    contains: call_id == 1,
+                },
+                &mut conn,
+            )
+            .await
+            .unwrap();
+            CallMetrics::insert(
+                CallMetrics {
+                    task_id: task.id,
+                    ..Default::default()
+                },
+                &mut conn,
+            )
+            .await
+            .unwrap();
+            task_ids.push(task.id);
+        }
+
+        let response = do_detailed_export(
+            cx.clone(),
+            DetailedExportRequest {
+                task_ids: task_ids.clone(),
+                format: ExportFormat::Jsonl,
+            },
+            project_id,
+        )
+        .await
+        .expect("failed to export detailed metrics");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let rows = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2);
+        for (row, (task_id, score)) in rows.iter().zip(task_ids.iter().zip([100, 0])) {
+            assert_eq!(row["metrics"]["task_id"], task_id.to_string());
+            assert_eq!(row["efficiency_metrics"][0]["items"][0]["score"], score);
+        }
+
+        let response = do_detailed_export(
+            cx.clone(),
+            DetailedExportRequest {
+                task_ids: task_ids.clone(),
+                format: ExportFormat::Csv,
+            },
+            project_id,
+        )
+        .await
+        .expect("failed to export detailed metrics");
+        assert_eq!(response.headers()[http::header::CONTENT_TYPE], "text/csv");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        let header = lines[0].split(',').collect::<Vec<_>>();
+        let score_column = header
+            .iter()
+            .position(|column| {
+                *column == format!("efficiency_metrics.script.{}.score", settings_item.id)
+            })
+            .expect("missing settings item score column");
+        for (line, (task_id, score)) in lines[1..].iter().zip(task_ids.iter().zip(["100", "0"])) {
+            let fields = line.split(',').collect::<Vec<_>>();
+            assert_eq!(fields.len(), header.len());
+            assert!(fields.contains(&task_id.to_string().as_str()));
+            assert_eq!(fields[score_column], score);
+        }
+        assert_eq!(csv_field(r#"a, "b""#), r#""a, ""b""""#);
+
+        // the header computed upfront covers every key of the exported rows
+        let project_settings = ProjectSettings::fetch(project_id, &mut conn).await.unwrap();
+        let columns = csv_columns(&project_settings).unwrap();
+        let metrics = task_detailed_metrics(&cx, task_ids[0], &project_settings)
+            .await
+            .unwrap();
+        let flat = flat_detailed_metrics(&metrics).unwrap();
+        let missing = flat
+            .keys()
+            .filter(|key| !columns.contains(key))
+            .collect::<Vec<_>>();
+        assert!(missing.is_empty(), "{missing:?}");
+
+        // an unknown task gets an error row, the tasks after it are still exported
+        let unknown_task_id = Uuid::new_v4();
+        for format in [ExportFormat::Jsonl, ExportFormat::Csv] {
+            let response = do_detailed_export(
+                cx.clone(),
+                DetailedExportRequest {
+                    task_ids: vec![unknown_task_id, task_ids[0]],
+                    format,
+                },
+                project_id,
+            )
+            .await
+            .expect("failed to export detailed metrics");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = std::str::from_utf8(&body).unwrap();
+            let lines = body.lines().collect::<Vec<_>>();
+            let (error_line, exported_line) = match format {
+                ExportFormat::Jsonl => (lines[0], lines[1]),
+                ExportFormat::Csv => (lines[1], lines[2]),
+            };
+            assert!(error_line.contains(&unknown_task_id.to_string()));
+            assert!(error_line.contains("EntityNotFound"));
+            assert!(exported_line.contains(&task_ids[0].to_string()));
+            assert!(!exported_line.contains("EntityNotFound"));
+        }
+
+        cx.config_mut().detailed_export_max_tasks = 1;
+        let err = do_detailed_export(
+            cx,
+            DetailedExportRequest {
+                task_ids,
+                format: ExportFormat::Jsonl,
+            },
+            project_id,
+        )
+        .await
+        .expect_err("unexpected success while exporting too many tasks");
+        assert_eq!(err.kind, ErrorKind::InvalidExportRequest);
+    }
+
+    #[sqlx::test]
+    async fn full_task(pool: sqlx::PgPool) {
+        let transcript = RecognitionData {
+            call_holds: Default::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: Default::default(),
+            speech_recognition_result: vec![SpeechRecognition {
+                text: "hello".to_string(),
+                timestamps: Interval {
+                    start: 0.0,
+                    end: 1.0,
+                },
+                speaker: ParticipantKind::Employee,
+                confidence: None,
+            }],
+        };
+        let raw_transcript = serde_json::to_vec(&transcript).unwrap();
+
+        let mut cx = TestContext::new(pool.clone()).await;
+        cx.worker_client_mock()
+            .expect_metrics_intervals_by_id()
+            .returning(|_| Ok(MetricsIntervals::default()));
+        // the first transcript request finds nothing, as for a task whose transcript was not kept
+        let transcript_requests = AtomicUsize::new(0);
+        cx.worker_client_mock()
+            .expect_raw_transcript_by_id()
+            .returning(
+                move |_| match transcript_requests.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(WorkerClientError::ResponseStatus(StatusCode::NOT_FOUND)),
+                    _ => Ok(raw_transcript.clone().into()),
+                },
+            );
+        let request = TaskCreateRequest {
+            metadata: call_metadata(42),
+            _project_id: Uuid::default(),
+        };
+        let task = do_create(cx.clone(), request, None)
+            .await
+            .expect("failed to create task")
+            .payload()
+            .clone();
+
+        let err = do_full(cx.clone(), task.id, Uuid::default())
+            .await
+            .expect_err("task without metrics has no full view");
+        assert_eq!(err.kind, ErrorKind::EntityNotFound);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let metrics = CallMetrics {
+            task_id: task.id,
+            call_duration: 60.0,
+            ..Default::default()
+        };
+        CallMetrics::insert(metrics, &mut conn).await.unwrap();
+
+        let err = do_full(cx.clone(), task.id, Uuid::default())
+            .await
+            .expect_err("task without transcript has no full view");
+        assert_eq!(err.kind, ErrorKind::EntityNotFound);
+
+        let response = do_full(cx, task.id, Uuid::default())
+            .await
+            .expect("failed to retrieve full task");
+        assert_eq!(response.status(), StatusCode::OK);
+        let full = serde_json::to_value(response.payload()).unwrap();
+        assert_eq!(
+            full["transcript"],
+            serde_json::to_value(&transcript).unwrap()
+        );
+        assert_eq!(full["metrics"]["call_duration"], 60.0);
+        assert_eq!(full["metadata"]["call_id"], 42);
+        assert!(!full["efficiency_metrics"].as_array().unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn stored_scoring_rules(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        cx.worker_client_mock()
+            .expect_metrics_intervals_by_id()
+            .returning(|_| Ok(MetricsIntervals::default()));
+        let request = |call_id| TaskCreateRequest {
+            metadata: call_metadata(call_id),
+            _project_id: Uuid::default(),
+        };
+
+        // the same call scored by a worker requiring more speech and by one with default rules
+        let mut conn = pool.acquire().await.unwrap();
+        let mut task_ids = vec![];
+        for (call_id, scoring) in [
+            (
+                1,
+                Some(ScoringRules {
+                    min_scored_speech: 30.0,
+                    ..Default::default()
+                }),
+            ),
+            (2, None),
+        ] {
+            let task = do_create(cx.clone(), request(call_id), None)
+                .await
+                .expect("failed to create task")
+                .payload()
+                .clone();
+            CallMetrics::insert(
+                CallMetrics {
+                    task_id: task.id,
+                    total_employee_speech: 10.0,
+                    total_client_speech: 10.0,
+                    extras: sqlx::types::Json(CallMetricsExtras {
+                        scoring,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                &mut conn,
+            )
+            .await
+            .unwrap();
+            task_ids.push(task.id);
+        }
+
+        let unscored = |task_id| {
+            let cx = cx.clone();
+            async move {
+                let response = do_detailed_metrics(cx, task_id, Uuid::default())
+                    .await
+                    .expect("error while retrieving call metrics");
+                let metrics = response.payload();
+                assert!(!metrics.efficiency_metrics.is_empty());
+                metrics
+                    .efficiency_metrics
+                    .iter()
+                    .all(|settings_metrics| settings_metrics.unscored)
+            }
+        };
+        assert!(unscored(task_ids[0]).await);
+        assert!(!unscored(task_ids[1]).await);
+    }
+
+    #[sqlx::test]
+    async fn score_preview(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        cx.worker_client_mock()
+            .expect_metrics_intervals_by_id()
+            .returning(|_| Ok(MetricsIntervals::default()));
+        let project_id = Uuid::new_v4();
+        let request = TaskCreateRequest {
+            metadata: call_metadata(42),
+            _project_id: project_id,
+        };
+
+        let task_resp = do_create(cx.clone(), request, None)
+            .await
+            .expect("failed to create task");
+        let task = task_resp.payload();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let dict = Dictionary::insert("test_dict".to_owned(), ParticipantKind::Employee, &mut conn)
+            .await
+            .unwrap();
+        TaskToDict::insert(
+            TaskToDict {
+                task_id: task.id,
+                dictionary_id: dict.id,
+                contains: true,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        let settings = Settings::insert(
+            Settings {
+                id: Uuid::default(),
+                project_id,
+                r#type: SettingsKind::Script,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        let settings_item = SettingsItem::insert(
+            SettingsItem {
+                id: Uuid::default(),
+                settings_id: settings.id,
+                settings_immutable: false,
+                name: "dict_test".to_string(),
+                r#type: SettingsItemKind::Dictionary,
+                score_weight: 1,
+                graded_threshold: None,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        SettingsDictItem::bulk_insert(
+            vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: settings_item.id,
+                dictionary_id: dict.id,
+                contains: true,
+            }],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        CallMetrics::insert(
+            CallMetrics {
+                task_id: task.id,
+                ..Default::default()
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        let detailed_resp = do_detailed_metrics(cx.clone(), task.id, project_id)
+            .await
+            .expect("error while retrieving call metrics");
+
+        let preview_request = ScorePreviewRequest {
+            settings: Settings::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap(),
+            settings_items: SettingsItem::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap(),
+            settings_dict_items: SettingsDictItem::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap(),
+        };
+        let preview_resp = do_score_preview(cx.clone(), task.id, preview_request)
+            .await
+            .expect("error while calculating score preview");
+        assert_eq!(preview_resp.status(), StatusCode::OK);
+        assert_eq!(
+            preview_resp.payload(),
+            &detailed_resp.payload().efficiency_metrics
+        );
+
+        // proposed settings expecting the dictionary to be absent
+        let mut preview_request = ScorePreviewRequest {
+            settings: Settings::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap(),
+            settings_items: SettingsItem::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap(),
+            settings_dict_items: SettingsDictItem::list_by_project_id(project_id, &mut conn)
+                .await
+                .unwrap(),
+        };
+        preview_request.settings_dict_items[0].contains = false;
+        let preview_resp = do_score_preview(cx.clone(), task.id, preview_request)
+            .await
+            .expect("error while calculating score preview");
+        assert_eq!(preview_resp.payload()[0].total_score, 0);
+
+        let stored_dict_items = SettingsDictItem::list_by_project_id(project_id, &mut conn)
+            .await
+            .unwrap();
+        assert!(stored_dict_items[0].contains);
     }
 }
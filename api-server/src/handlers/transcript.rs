@@ -1,19 +1,20 @@
 use axum::body::Body;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::response::Response;
 use http::StatusCode;
 use protocol::entity::speech_recog::RecognitionData;
-use utoipa::OpenApi;
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi};
 use uuid::Uuid;
 
-use crate::clients::worker::WorkerClient;
+use crate::clients::worker::{TranscriptFormat, WorkerClient};
 use crate::context::{AppContext, Context};
 use crate::error::{Error, ErrorKind};
 
 #[derive(OpenApi)]
 #[openapi(
     paths(transcript, download_transcript),
-    components(schemas()),
+    components(schemas(TranscriptFormat)),
     tags(
         (name = "Transcripts", description = "API for handling transcript operations")
     )
@@ -55,70 +56,67 @@ async fn do_transcript<C: Context>(cx: C, id: Uuid) -> Result<Response, Error> {
     Ok(response)
 }
 
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct DownloadTranscriptRequest {
+    /// Plain text by default
+    #[serde(default)]
+    format: TranscriptFormat,
+}
+
 #[utoipa::path(
     get,
     path = "/{id}/download",
     responses(
-        (status = OK, description = "Download the transcript as a text file", content_type = "text/plain"),
+        (status = OK, description = "Download the transcript as a text or SRT file", content((String = "text/plain"), (String = "application/x-subrip"))),
         (status = INTERNAL_SERVER_ERROR, description = "Server error while downloading transcript")
     ),
     params(
-        ("id" = Uuid, Path, description = "Unique identifier for the transcript")
+        ("id" = Uuid, Path, description = "Unique identifier for the transcript"),
+        DownloadTranscriptRequest
     ),
     tags = ["Transcripts"]
 )]
 pub async fn download_transcript(
     State(cx): State<AppContext>,
     Path(id): Path<Uuid>,
+    Query(request): Query<DownloadTranscriptRequest>,
 ) -> Result<Response, Error> {
-    do_download_transcript(cx, id).await
+    do_download_transcript(cx, id, request).await
 }
 
-async fn do_download_transcript<C: Context>(cx: C, id: Uuid) -> Result<Response, Error> {
-    let recog_data = cx
+async fn do_download_transcript<C: Context>(
+    cx: C,
+    id: Uuid,
+    request: DownloadTranscriptRequest,
+) -> Result<Response, Error> {
+    // the worker renders the transcript, the body is streamed through as is
+    let rendered = cx
         .worker_client()
-        .transcript_by_id(id)
+        .rendered_transcript_by_id(id, request.format)
         .await
         .map_err(|err| Error::new(ErrorKind::WorkerRequestFailed, anyhow::anyhow!(err)))?;
-    let response =
-        recog_data
-            .speech_recognition_result
-            .iter()
-            .fold(String::new(), |acc_text, recog_item| {
-                let speaker = recog_item.speaker;
-                let start_interval = format_seconds(recog_item.timestamps.start as i64);
-                let end_interval = format_seconds(recog_item.timestamps.end as i64);
-                let text = &recog_item.text;
-
-                acc_text + &format!("[{speaker} | {start_interval} - {end_interval}]: {text}\n")
-            });
+    let (content_type, filename) = match request.format {
+        TranscriptFormat::Text => ("text/plain; charset=utf-8", "transcript.txt"),
+        TranscriptFormat::Srt => ("application/x-subrip; charset=utf-8", "transcript.srt"),
+    };
 
     let response = Response::builder()
         .status(StatusCode::OK)
-        .header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(http::header::CONTENT_TYPE, content_type)
         .header(
             http::header::CONTENT_DISPOSITION,
-            "attachment; filename=\"transcript.txt\"",
+            format!("attachment; filename=\"{filename}\""),
         )
-        .body(Body::from(response))
+        .body(Body::from_stream(rendered))
         .map_err(|err| Error::new(ErrorKind::SerializationFailed, anyhow::anyhow!(err)))?;
 
     Ok(response)
 }
 
-fn format_seconds(duration: i64) -> String {
-    let seconds = duration % 60;
-    let minutes = (duration / 60) % 60;
-    let hours = (duration / 60) / 60;
-    format!("{:0>2}:{:0>2}:{:0>2}", hours, minutes, seconds)
-}
-
 #[cfg(test)]
 mod tests {
-    use protocol::entity::speech_recog::{
-        CallHolds, Interval, PhraseTimestamps, SpeechRecognition,
-    };
-    use protocol::entity::ParticipantKind;
+    use bytes::Bytes;
+    use futures::StreamExt;
 
     use crate::test_helpers::context::TestContext;
 
@@ -129,36 +127,43 @@ mod tests {
         let mut cx = TestContext::new(pool).await;
 
         cx.worker_client_mock()
-            .expect_transcript_by_id()
-            .with(mockall::predicate::eq(Uuid::default()))
-            .returning(move |_| {
-                Ok(RecognitionData {
-                    call_holds: CallHolds::default(),
-                    emotion_recognition_result: vec![],
-                    phrase_timestamps: PhraseTimestamps::default(),
-                    speech_recognition_result: vec![SpeechRecognition {
-                        text: "test_text".to_string(),
-                        timestamps: Interval {
-                            start: 0f32,
-                            end: 10f32,
-                        },
-                        speaker: ParticipantKind::Client,
-                    }],
-                })
+            .expect_rendered_transcript_by_id()
+            .with(
+                mockall::predicate::eq(Uuid::default()),
+                mockall::predicate::eq(TranscriptFormat::Srt),
+            )
+            .returning(move |_, _| {
+                let chunks = [
+                    "1\n00:00:00,000 --> 00:00:10,000\n",
+                    "[Client] test_text\n\n",
+                ]
+                .map(|chunk| Ok(Bytes::from(chunk)));
+                Ok(futures::stream::iter(chunks).boxed())
             });
 
-        let transcript_text_resp = do_download_transcript(cx, Uuid::default())
+        let request = DownloadTranscriptRequest {
+            format: TranscriptFormat::Srt,
+        };
+        let transcript_resp = do_download_transcript(cx, Uuid::default(), request)
             .await
             .expect("failed to retrieve transcript");
-        assert_eq!(transcript_text_resp.status(), StatusCode::OK);
+        assert_eq!(transcript_resp.status(), StatusCode::OK);
+        assert_eq!(
+            transcript_resp.headers()[http::header::CONTENT_TYPE],
+            "application/x-subrip; charset=utf-8"
+        );
+        assert_eq!(
+            transcript_resp.headers()[http::header::CONTENT_DISPOSITION],
+            "attachment; filename=\"transcript.srt\""
+        );
 
-        let transcript = axum::body::to_bytes(transcript_text_resp.into_body(), usize::MAX)
+        let transcript = axum::body::to_bytes(transcript_resp.into_body(), usize::MAX)
             .await
             .unwrap();
 
         let recog_data_res: String = String::from_utf8(transcript.to_vec()).unwrap();
         assert_eq!(
-            "[Client | 00:00:00 - 00:00:10]: test_text\n",
+            "1\n00:00:00,000 --> 00:00:10,000\n[Client] test_text\n\n",
             recog_data_res
         );
     }
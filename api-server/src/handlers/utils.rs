@@ -1,6 +1,12 @@
+use std::convert::Infallible;
+
+use axum::async_trait;
+use axum::extract::FromRequestParts;
 use axum::response::{IntoResponse, Json};
-use http::StatusCode;
-use serde::Serialize;
+use http::{request::Parts, StatusCode};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::{ErrorExt, ErrorKind};
 
 pub type RequestResult<T> = Result<Response<T>, crate::error::Error>;
 pub type AppResponse<T> = Response<T>;
@@ -16,6 +22,13 @@ impl<T> Response<T> {
         Self { status, payload }
     }
 
+    pub fn try_map<U, E>(self, f: impl FnOnce(T) -> Result<U, E>) -> Result<Response<U>, E> {
+        Ok(Response {
+            status: self.status,
+            payload: f(self.payload)?,
+        })
+    }
+
     #[cfg(test)]
     pub fn status(&self) -> StatusCode {
         self.status
@@ -27,8 +40,97 @@ impl<T> Response<T> {
     }
 }
 
+impl<T: Serialize> Response<T> {
+    /// Serializes the payload with floats rounded to `decimals`, stored values keep full precision
+    pub fn with_rounded_floats(self, decimals: u32) -> RequestResult<serde_json::Value> {
+        self.try_map(|payload| {
+            let mut value = serde_json::to_value(payload)?;
+            round_floats(&mut value, decimals);
+            Ok::<_, serde_json::Error>(value)
+        })
+        .error(ErrorKind::SerializationFailed)
+    }
+}
+
+pub(crate) fn round_floats(value: &mut serde_json::Value, decimals: u32) {
+    match value {
+        serde_json::Value::Number(number) if number.is_f64() => {
+            let scale = 10f64.powi(decimals as i32);
+            let rounded = number.as_f64().map(|float| (float * scale).round() / scale);
+            if let Some(rounded) = rounded.and_then(serde_json::Number::from_f64) {
+                *number = rounded;
+            }
+        }
+        serde_json::Value::Array(items) => {
+            items
+                .iter_mut()
+                .for_each(|item| round_floats(item, decimals));
+        }
+        serde_json::Value::Object(fields) => {
+            fields
+                .values_mut()
+                .for_each(|field| round_floats(field, decimals));
+        }
+        _ => {}
+    }
+}
+
 impl<T: Serialize> IntoResponse for Response<T> {
     fn into_response(self) -> axum::response::Response {
         (self.status, Json(self.payload)).into_response()
     }
 }
+
+/// Distinguishes an explicit `null` (`Some(None)`) from an omitted field (`None`),
+/// to be used together with `#[serde(default)]`
+pub fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Header carrying the caller identity (API key name or user), set by the authenticating
+/// gateway in front of the service.
+pub const PRINCIPAL_HEADER: &str = "x-principal";
+
+#[derive(Debug, Clone, Default)]
+pub struct Principal(pub Option<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Principal {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let principal = parts
+            .headers
+            .get(PRINCIPAL_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+
+        Ok(Self(principal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounded_float_payload() {
+        let payload = serde_json::json!({
+            "ratio": 100.0f32 / 3.0,
+            "count": 3,
+            "nested": [{"score": 2.0f32 / 3.0}],
+        });
+
+        let response = Response::new(StatusCode::OK, payload)
+            .with_rounded_floats(2)
+            .expect("failed to round floats");
+        assert_eq!(
+            serde_json::to_string(response.payload()).unwrap(),
+            r#"{"count":3,"nested":[{"score":0.67}],"ratio":33.33}"#
+        );
+    }
+}
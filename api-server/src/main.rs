@@ -15,12 +15,20 @@ async fn main() -> Result<()> {
     let config = config::load().context("Failed to load config")?;
     info!("App config: {:?}", config);
 
+    if std::env::args().nth(1).as_deref() == Some("seed") {
+        let pool = create_pool(&config.db).await?;
+        let mut conn = pool.acquire().await?;
+        seed::seed(&mut conn).await?;
+        return Ok(());
+    }
+
     let amqp_connection = create_broker_connection().await?;
     let amqp_channel = amqp_connection.create_channel().await?;
     let pool = create_pool(&config.db).await?;
     let cx = crate::context::AppContext::new(amqp_channel, pool, config.clone())?;
 
     let api_listener = tokio::net::TcpListener::bind(&config.http.api_listener_address).await?;
+    let stuck_tasks_handle = tokio::spawn(crate::stuck_tasks::run(cx.clone()));
     let api_handle = tokio::spawn(
         axum::serve(api_listener, crate::handlers::api_router(cx))
             .into_future()
@@ -34,7 +42,8 @@ async fn main() -> Result<()> {
         res
     });
 
-    let (result, number, _) = future::select_all(vec![api_handle, signals_handle]).await;
+    let (result, number, _) =
+        future::select_all(vec![api_handle, stuck_tasks_handle, signals_handle]).await;
     let context = format!("Error from call ai handle #{number}");
     let result = result.context("Join error on handlers")?.context(context);
     if let Err(err) = &result {
@@ -73,5 +82,7 @@ mod context;
 mod db;
 mod error;
 mod handlers;
+mod seed;
+mod stuck_tasks;
 #[cfg(test)]
 mod test_helpers;
@@ -0,0 +1,218 @@
+//! Demo data for local development, run with `api-server seed`
+
+use anyhow::Context as _;
+use chrono::{Duration, Utc};
+use protocol::db::{
+    dictionary::{Dictionary, Phrase},
+    metadata::CallMetadata,
+    metrics::{CallMetrics, CallMetricsExtras},
+    settings::Settings,
+    task::{Task, TaskResultKind, TaskToDict, DEMO_TASK_CREATOR},
+};
+use protocol::entity::ParticipantKind;
+use sqlx::{types::Json, Acquire};
+use tracing::info;
+use uuid::Uuid;
+
+const DEMO_DICTIONARIES: [(&str, ParticipantKind, &[&str]); 2] = [
+    (
+        "demo_greetings",
+        ParticipantKind::Employee,
+        &["добрый день", "здравствуйте", "рады вас слышать"],
+    ),
+    (
+        "demo_complaints",
+        ParticipantKind::Client,
+        &["не работает", "верните деньги", "жалоба"],
+    ),
+];
+
+const DEMO_CALLS_COUNT: i64 = 3;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SeedReport {
+    pub dictionaries_created: usize,
+    pub tasks_created: usize,
+}
+
+/// Seeds the default project with demo dictionaries and processed tasks,
+/// rows which already exist are left untouched so it's safe to run repeatedly.
+/// Transcripts of the tasks live in the worker index and are seeded by `worker seed`
+pub async fn seed(conn: &mut sqlx::PgConnection) -> anyhow::Result<SeedReport> {
+    let mut txn = conn.begin().await?;
+    let project_id = Uuid::default();
+    let mut report = SeedReport::default();
+
+    // default settings of the project are created by migrations
+    let settings = Settings::list_by_project_id(project_id, &mut txn).await?;
+    anyhow::ensure!(
+        !settings.is_empty(),
+        "default settings are missing, run migrations first"
+    );
+
+    let existing = Dictionary::list(&mut txn).await?;
+    let mut dictionaries = vec![];
+    for (name, participant, phrases) in DEMO_DICTIONARIES {
+        if let Some(dict) = existing.iter().find(|dict| dict.name == name) {
+            dictionaries.push(dict.clone());
+            continue;
+        }
+
+        let dict = Dictionary::insert(name.to_string(), participant, &mut txn).await?;
+        let phrases = phrases
+            .iter()
+            .map(|text| Phrase {
+                id: 0,
+                dictionary_id: dict.id,
+                text: text.to_string(),
+            })
+            .collect();
+        Phrase::bulk_insert(phrases, &mut txn).await?;
+        report.dictionaries_created += 1;
+        dictionaries.push(dict);
+    }
+
+    for call_id in 1..=DEMO_CALLS_COUNT {
+        let file_hash = format!("demo-call-{call_id}");
+        if CallMetadata::exists_by_file_hash(project_id, &file_hash, &mut txn).await? {
+            continue;
+        }
+
+        let performed_at = Utc::now() - Duration::days(call_id);
+        let metadata = CallMetadata {
+            metadata_id: Uuid::default(),
+            call_id,
+            performed_at,
+            uploaded_at: performed_at,
+            file_url: format!("s3://demo/{file_hash}.mp3"),
+            file_name: format!("{file_hash}.mp3"),
+            file_hash,
+            duration: 60.0 * call_id as f32,
+            left_channel: ParticipantKind::Client,
+            right_channel: ParticipantKind::Employee,
+            client_name: format!("Demo client {call_id}"),
+            employee_name: "Demo employee".to_string(),
+            inbound: call_id % 2 == 1,
+            left_channel_url: None,
+            right_channel_url: None,
+        }
+        .insert(project_id, &mut txn)
+        .await?;
+
+        let task = Task {
+            id: Uuid::default(),
+            call_metadata_id: metadata.metadata_id,
+            status: TaskResultKind::Ready,
+            failed_reason: None,
+            failure_class: None,
+            failure_kind: None,
+            project_id,
+            created_by: Some(DEMO_TASK_CREATOR.to_string()),
+            reprocessed_by: None,
+            attempts: 1,
+        }
+        .insert(&mut txn)
+        .await?;
+
+        let call_duration = metadata.duration;
+        let total_employee_speech = call_duration * 0.4;
+        let total_client_speech = call_duration * (0.2 + 0.1 * call_id as f32);
+        let script_score = 100 - 20 * call_id as i32;
+        let employee_quality_score = 90 - 10 * call_id as i32;
+        CallMetrics::insert(
+            CallMetrics {
+                task_id: task.id,
+                call_duration,
+                time_to_answer: 1.5,
+                total_employee_speech,
+                total_client_speech,
+                employee_client_speech_ratio: total_employee_speech / total_client_speech * 100.0,
+                employee_speech_ratio: total_employee_speech / call_duration * 100.0,
+                client_speech_ratio: total_client_speech / call_duration * 100.0,
+                call_holds_count: call_id as i32 - 1,
+                silence_pause_count: call_id as i32,
+                total_employee_silence: 2.0 * call_id as f32,
+                client_interruptions_count: 1,
+                total_client_interruptions_duration: 1.2,
+                avg_employee_words_per_min: 120.0,
+                avg_client_words_per_min: 100.0,
+                avg_employee_segment_len: 4.5,
+                avg_client_segment_len: 3.0,
+                script_score,
+                employee_quality_score,
+                overall_score: (script_score + employee_quality_score) / 2,
+                emotion_mode: None,
+                emotion_start_mode: None,
+                emotion_end_mode: None,
+                extras: Json(CallMetricsExtras::default()),
+            },
+            &mut txn,
+        )
+        .await?;
+
+        let task_to_dicts = dictionaries
+            .iter()
+            .enumerate()
+            .map(|(idx, dict)| TaskToDict {
+                task_id: task.id,
+                dictionary_id: dict.id,
+                contains: (call_id as usize + idx) % 2 == 0,
+            })
+            .collect();
+        TaskToDict::bulk_insert(task_to_dicts, &mut txn).await?;
+        report.tasks_created += 1;
+    }
+
+    txn.commit().await.context("Failed to commit demo data")?;
+    info!(
+        "Demo data seeded: {} dictionaries and {} tasks created",
+        report.dictionaries_created, report.tasks_created
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn seed_is_idempotent(pool: sqlx::PgPool) {
+        let mut conn = pool.acquire().await.unwrap();
+
+        let report = seed(&mut conn).await.expect("failed to seed demo data");
+        assert_eq!(
+            report,
+            SeedReport {
+                dictionaries_created: DEMO_DICTIONARIES.len(),
+                tasks_created: DEMO_CALLS_COUNT as usize,
+            }
+        );
+
+        let report = seed(&mut conn).await.expect("failed to reseed demo data");
+        assert_eq!(report, SeedReport::default());
+
+        let demo_tasks = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(1) FROM task_call_metrics
+            JOIN task ON task.id = task_call_metrics.task_id
+            WHERE task.created_by = 'seed'",
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap();
+        assert_eq!(demo_tasks, DEMO_CALLS_COUNT);
+
+        let dictionaries = Dictionary::list(&mut conn).await.unwrap();
+        for (name, _, phrases) in DEMO_DICTIONARIES {
+            let dict = dictionaries
+                .iter()
+                .filter(|dict| dict.name == name)
+                .collect::<Vec<_>>();
+            assert_eq!(dict.len(), 1);
+            let stored = Phrase::list_by_dict_id(dict[0].id, &mut conn)
+                .await
+                .unwrap();
+            assert_eq!(stored.len(), phrases.len());
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use protocol::db::task::Task;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::StuckTaskAction;
+use crate::context::{Context, TaskPublisher};
+use crate::error::Error;
+
+/// Periodically recovers tasks left `processing` by a crashed worker
+pub async fn run<C: Context + Sync>(cx: C) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(cx.config().stuck_tasks.sweep_interval);
+    loop {
+        interval.tick().await;
+        match sweep(&cx).await {
+            Ok(task_ids) if !task_ids.is_empty() => {
+                let action = cx.config().stuck_tasks.action;
+                info!(?task_ids, ?action, "recovered stuck tasks");
+            }
+            Ok(_) => {}
+            Err(err) => warn!("failed to sweep stuck tasks: {err}"),
+        }
+    }
+}
+
+async fn sweep<C: Context + Sync>(cx: &C) -> Result<Vec<Uuid>, Error> {
+    let config = &cx.config().stuck_tasks;
+    let mut conn = cx.get_db_conn().await?;
+    match config.action {
+        StuckTaskAction::Requeue => {
+            let mut task_ids = Task::touch_stuck(config.timeout, &mut conn).await?;
+            if let Err(err) = cx.publisher().publish_batch(&task_ids).await {
+                // their timeout is restarted, so they are picked up by a later sweep
+                let unpublished = err.unpublished(&task_ids);
+                warn!(
+                    ?unpublished,
+                    "failed to re-queue stuck tasks: {}", err.error
+                );
+                task_ids.retain(|id| !unpublished.contains(id));
+            }
+            Ok(task_ids)
+        }
+        StuckTaskAction::Fail => {
+            let reason = format!("processing exceeded {}s", config.timeout.as_secs());
+            Ok(Task::fail_stuck(config.timeout, &reason, &mut conn).await?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::db::task::{TaskFailureClass, TaskFailureKind, TaskResultKind};
+
+    use crate::test_helpers::{context::TestContext, insert_task};
+
+    use super::*;
+
+    async fn insert_processing_task(cx: &TestContext, started_ago: &str) -> Uuid {
+        let task_id = insert_queued_task(cx, "0 seconds").await;
+        let mut conn = cx.get_db_conn().await.unwrap();
+        Task::increment_attempts(&task_id, &mut conn).await.unwrap();
+        sqlx::query("UPDATE task SET started_at = now() - $2::interval WHERE id = $1")
+            .bind(task_id)
+            .bind(started_ago)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        task_id
+    }
+
+    async fn insert_queued_task(cx: &TestContext, queued_ago: &str) -> Uuid {
+        let mut conn = cx.get_db_conn().await.unwrap();
+        let task = insert_task(Uuid::default(), &mut conn).await;
+        sqlx::query("UPDATE task SET queued_at = now() - $2::interval WHERE id = $1")
+            .bind(task.id)
+            .bind(queued_ago)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        task.id
+    }
+
+    #[sqlx::test]
+    async fn stale_task_is_requeued(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let stale_id = insert_processing_task(&cx, "2 hours").await;
+        insert_processing_task(&cx, "1 minute").await;
+
+        let task_ids = sweep(&cx).await.expect("failed to sweep stuck tasks");
+        assert_eq!(task_ids, vec![stale_id]);
+        assert_eq!(cx.publisher().published_task_ids().await, vec![stale_id]);
+
+        // the timeout restarts, so the same task is not re-queued on the next sweep
+        let task_ids = sweep(&cx).await.expect("failed to sweep stuck tasks");
+        assert!(task_ids.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn never_started_task_is_requeued(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let stale_id = insert_queued_task(&cx, "2 hours").await;
+        insert_queued_task(&cx, "1 minute").await;
+
+        let task_ids = sweep(&cx).await.expect("failed to sweep stuck tasks");
+        assert_eq!(task_ids, vec![stale_id]);
+        assert_eq!(cx.publisher().published_task_ids().await, vec![stale_id]);
+
+        let task_ids = sweep(&cx).await.expect("failed to sweep stuck tasks");
+        assert!(task_ids.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn unpublished_task_is_not_reported(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        insert_processing_task(&cx, "2 hours").await;
+        cx.publisher().set_unavailable(true);
+
+        let task_ids = sweep(&cx).await.expect("failed to sweep stuck tasks");
+        assert!(task_ids.is_empty());
+        assert!(cx.publisher().flush().await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn stale_task_is_failed(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool).await;
+        cx.config_mut().stuck_tasks.action = StuckTaskAction::Fail;
+        let stale_id = insert_processing_task(&cx, "2 hours").await;
+
+        let task_ids = sweep(&cx).await.expect("failed to sweep stuck tasks");
+        assert_eq!(task_ids, vec![stale_id]);
+        assert!(cx.publisher().flush().await.is_empty());
+
+        let mut conn = cx.get_db_conn().await.unwrap();
+        let task = Task::get(&stale_id, &mut conn).await.unwrap();
+        assert_eq!(task.status, TaskResultKind::Failed);
+        assert_eq!(task.failure_class, Some(TaskFailureClass::Transient));
+        assert_eq!(task.failure_kind, Some(TaskFailureKind::Unknown));
+    }
+}
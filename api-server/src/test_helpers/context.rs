@@ -1,21 +1,25 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use async_trait::async_trait;
 use serde_json::Value;
 use sqlx::{pool::PoolConnection, PgPool, Postgres};
 use tokio::sync::Mutex;
+use uuid::Uuid;
 
 use crate::{
     clients::worker::MockWorkerClient,
     config::Config,
-    context::TaskPublisher,
+    context::{BatchPublishError, TaskPublisher},
     error::{Error, ErrorExt, ErrorKind},
 };
 
 #[derive(Clone)]
 pub struct TestContext {
     db: PgPool,
-    _config: Config,
+    config: Config,
     publisher: Arc<TestPublisher>,
     worker_client: Arc<MockWorkerClient>,
 }
@@ -24,14 +28,14 @@ impl TestContext {
     pub async fn new(db: PgPool) -> Self {
         Self {
             db,
-            _config: build_config(),
+            config: build_config(),
             publisher: Arc::new(TestPublisher::new()),
             worker_client: Arc::new(MockWorkerClient::new()),
         }
     }
 
-    pub fn _config(&self) -> &Config {
-        &self._config
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
     }
 
     pub fn test_publisher(&self) -> &TestPublisher {
@@ -75,6 +79,10 @@ impl crate::context::Context for TestContext {
         self.worker_client.as_ref()
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
     async fn get_db_conn(&self) -> Result<PoolConnection<Postgres>, Error> {
         let conn = self.db.acquire().await?;
         Ok(conn)
@@ -83,13 +91,31 @@ impl crate::context::Context for TestContext {
 
 pub struct TestPublisher {
     messages: Mutex<Vec<Value>>,
+    unavailable: AtomicBool,
 }
 
 impl TestPublisher {
     fn new() -> Self {
         Self {
             messages: Mutex::new(vec![]),
+            unavailable: AtomicBool::new(false),
+        }
+    }
+
+    /// Makes every following publish fail like an unreachable broker
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    fn check_available(&self) -> Result<(), Error> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(Error::new(
+                ErrorKind::AMQPError,
+                anyhow::anyhow!("broker is unavailable"),
+            ));
         }
+
+        Ok(())
     }
 
     pub async fn flush(&self) -> Vec<Value> {
@@ -97,11 +123,24 @@ impl TestPublisher {
 
         (*messages_lock).drain(0..).collect::<Vec<_>>()
     }
+
+    /// Drains published messages, panicking on any payload that isn't a task id
+    pub async fn published_task_ids(&self) -> Vec<Uuid> {
+        self.flush()
+            .await
+            .into_iter()
+            .map(|message| {
+                serde_json::from_value(message.clone())
+                    .unwrap_or_else(|err| panic!("published {message} is not a task id: {err}"))
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
 impl TaskPublisher for TestPublisher {
     async fn publish<T: serde::Serialize + Sync>(&self, payload: &T) -> Result<(), Error> {
+        self.check_available()?;
         let serialized = serde_json::to_value(payload).error(ErrorKind::SerializationFailed)?;
 
         let mut messages_lock = self.messages.lock().await;
@@ -109,4 +148,45 @@ impl TaskPublisher for TestPublisher {
         (*messages_lock).push(serialized);
         Ok(())
     }
+
+    async fn publish_batch<T: serde::Serialize + Sync>(
+        &self,
+        payloads: &[T],
+    ) -> Result<(), BatchPublishError> {
+        let all_unpublished = |error| BatchPublishError {
+            unpublished: (0..payloads.len()).collect(),
+            error,
+        };
+        self.check_available().map_err(all_unpublished)?;
+        // serialize the whole batch first so a failure records none of it
+        let serialized = payloads
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .error(ErrorKind::SerializationFailed)
+            .map_err(all_unpublished)?;
+
+        let mut messages_lock = self.messages.lock().await;
+
+        (*messages_lock).extend(serialized);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_batch_records_all_ids() {
+        let publisher = TestPublisher::new();
+        let task_ids: Vec<_> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        publisher
+            .publish_batch(&task_ids)
+            .await
+            .expect("failed to publish batch");
+
+        assert_eq!(publisher.published_task_ids().await, task_ids);
+    }
 }
@@ -1 +1,3 @@
 pub mod context;
+
+pub use protocol::test_helpers::{call_metadata, insert_task};
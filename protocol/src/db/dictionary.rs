@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 use crate::entity::ParticipantKind;
 
@@ -8,6 +9,9 @@ pub struct Dictionary {
     pub id: i32,
     pub name: String,
     pub participant: ParticipantKind,
+    /// Phrases are matched against the speech of both participants
+    #[serde(default)]
+    pub any_speaker: bool,
 }
 
 impl Dictionary {
@@ -18,7 +22,8 @@ impl Dictionary {
                 SELECT
                     id,
                     name,
-                    participant as "participant: ParticipantKind"
+                    participant as "participant: ParticipantKind",
+                    any_speaker
                 FROM dictionary 
                 WHERE id = $1
             "#,
@@ -42,7 +47,8 @@ impl Dictionary {
                 RETURNING
                     id,
                     name,
-                    participant as "participant: ParticipantKind"
+                    participant as "participant: ParticipantKind",
+                    any_speaker
             "#,
             name,
             participant as ParticipantKind
@@ -51,11 +57,40 @@ impl Dictionary {
         .await
     }
 
+    pub async fn set_any_speaker(
+        id: i32,
+        any_speaker: bool,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as!(
+            Dictionary,
+            r#"
+                UPDATE dictionary
+                SET any_speaker = $2
+                WHERE id = $1
+                RETURNING
+                    id,
+                    name,
+                    participant as "participant: ParticipantKind",
+                    any_speaker
+            "#,
+            id,
+            any_speaker,
+        )
+        .fetch_optional(conn)
+        .await
+    }
+
+    /// The participant whose speech is searched, `None` when both are
+    pub fn searched_speaker(&self) -> Option<ParticipantKind> {
+        (!self.any_speaker).then_some(self.participant)
+    }
+
     pub async fn list(conn: &mut sqlx::PgConnection) -> sqlx::Result<Vec<Self>> {
         sqlx::query_as!(
             Dictionary,
             r#"
-                SELECT id, name, participant as "participant: ParticipantKind" 
+                SELECT id, name, participant as "participant: ParticipantKind", any_speaker
                 FROM dictionary
             "#
         )
@@ -63,6 +98,42 @@ impl Dictionary {
         .await
     }
 
+    /// Dictionaries linked to the settings item along with the `contains` flag of each link
+    pub async fn list_by_settings_item_id(
+        settings_item_id: Uuid,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<(Self, bool)>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT
+                    dictionary.id,
+                    dictionary.name,
+                    dictionary.participant as "participant: ParticipantKind",
+                    dictionary.any_speaker,
+                    sdi.contains
+                FROM settings_dict_item sdi
+                JOIN dictionary ON dictionary.id = sdi.dictionary_id
+                WHERE sdi.settings_item_id = $1
+            "#,
+            settings_item_id,
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let dictionary = Dictionary {
+                    id: row.id,
+                    name: row.name,
+                    participant: row.participant,
+                    any_speaker: row.any_speaker,
+                };
+                (dictionary, row.contains)
+            })
+            .collect())
+    }
+
     pub async fn delete_by_id(id: i32, conn: &mut sqlx::PgConnection) -> sqlx::Result<()> {
         sqlx::query!(
             r#"
@@ -22,6 +22,11 @@ pub struct CallMetadata {
     pub file_hash: String,
     pub file_url: String,
     pub file_name: String,
+    /// Separate mono recordings of each channel, used for diarization when provided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_channel_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_channel_url: Option<String>,
 
     pub duration: f32,
     pub left_channel: ParticipantKind,
@@ -32,6 +37,25 @@ pub struct CallMetadata {
 }
 
 impl CallMetadata {
+    /// File hashes are unique within a project, the same file can be uploaded to several ones
+    pub async fn exists_by_file_hash(
+        project_id: Uuid,
+        file_hash: &str,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<bool> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM call_metadata WHERE project_id = $1 AND file_hash = $2
+            ) as "exists!"
+            "#,
+            project_id,
+            file_hash,
+        )
+        .fetch_one(conn)
+        .await
+    }
+
     pub async fn get_by_task_id(
         task_id: Uuid,
         conn: &mut sqlx::PgConnection,
@@ -52,7 +76,9 @@ impl CallMetadata {
                 right_channel as "right_channel: ParticipantKind",
                 client_name,
                 employee_name,
-                inbound
+                inbound,
+                left_channel_url,
+                right_channel_url
             FROM call_metadata
             JOIN task ON task.call_metadata_id = call_metadata.id
             WHERE task.id = $1
@@ -63,7 +89,11 @@ impl CallMetadata {
         .await
     }
 
-    pub async fn insert(&self, conn: &mut sqlx::PgConnection) -> sqlx::Result<CallMetadata> {
+    pub async fn insert(
+        &self,
+        project_id: Uuid,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<CallMetadata> {
         sqlx::query_as!(
             CallMetadata,
             r#"
@@ -74,9 +104,11 @@ impl CallMetadata {
                 duration, 
                 left_channel, right_channel, 
                 client_name, employee_name, 
-                inbound
+                inbound,
+                left_channel_url, right_channel_url,
+                project_id
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8::participant_type, $9::participant_type, $10, $11, $12)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8::participant_type, $9::participant_type, $10, $11, $12, $13, $14, $15)
             RETURNING
                 id as metadata_id,
                 call_id,
@@ -90,7 +122,9 @@ impl CallMetadata {
                 right_channel as "right_channel: ParticipantKind",
                 client_name,
                 employee_name,
-                inbound
+                inbound,
+                left_channel_url,
+                right_channel_url
             "#,
             self.call_id,
             self.performed_at,
@@ -103,7 +137,10 @@ impl CallMetadata {
             self.right_channel as ParticipantKind,
             self.client_name,
             self.employee_name,
-            self.inbound
+            self.inbound,
+            self.left_channel_url,
+            self.right_channel_url,
+            project_id
         )
         .fetch_one(conn)
         .await
@@ -1,8 +1,9 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::entity::speech_recog::EmotionKind;
+use crate::entity::{settings_metrics::ScoringRules, speech_recog::EmotionKind};
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, sqlx::FromRow, ToSchema)]
 pub struct CallMetrics {
@@ -28,12 +29,52 @@ pub struct CallMetrics {
     pub avg_employee_words_per_min: f32,
     pub avg_client_words_per_min: f32,
 
+    pub avg_employee_segment_len: f32,
+    pub avg_client_segment_len: f32,
+
     pub script_score: i32,
     pub employee_quality_score: i32,
+    /// Weighted blend of the script and quality scores
+    pub overall_score: i32,
 
     pub emotion_mode: Option<EmotionKind>,
     pub emotion_start_mode: Option<EmotionKind>,
     pub emotion_end_mode: Option<EmotionKind>,
+
+    #[schema(value_type = CallMetricsExtras)]
+    pub extras: Json<CallMetricsExtras>,
+}
+
+/// Metrics without dedicated columns, stored as jsonb
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct CallMetricsExtras {
+    /// employee/client speech ratios for the first, middle and last third of the call
+    pub talk_balance_by_thirds: Vec<TalkBalance>,
+    /// employee/client speech ratio for every 60s window of the call, the last window may be partial
+    pub speech_ratio_by_minute: Vec<f32>,
+    /// call duration without the time spent on hold
+    pub active_call_duration: f32,
+    /// percentage of the call where neither party speaks and the call is not on hold
+    pub dead_air_ratio: f32,
+    /// percentage of the employee segments which are questions
+    pub employee_question_ratio: f32,
+    /// percentage of the client segments which are questions
+    pub client_question_ratio: f32,
+    /// average of the configured emotion weights over the call, 0 without recognized emotions
+    pub sentiment: f32,
+    /// too little speech to score the call, the scores are left zero
+    pub unscored: bool,
+    /// rules the call was scored with, absent on metrics stored before they were recorded
+    /// which are rescored with the default rules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scoring: Option<ScoringRules>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TalkBalance {
+    pub employee_speech_ratio: f32,
+    pub client_speech_ratio: f32,
 }
 
 impl CallMetrics {
@@ -56,18 +97,23 @@ impl CallMetrics {
                     total_client_interruptions_duration,
                     avg_employee_words_per_min,
                     avg_client_words_per_min,
+                    avg_employee_segment_len,
+                    avg_client_segment_len,
                     script_score,
                     employee_quality_score,
+                    overall_score,
                     emotion_mode,
                     emotion_start_mode,
-                    emotion_end_mode
+                    emotion_end_mode,
+                    extras
                 )
                 VALUES (
                     $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, 
-                    $12, $13, $14, $15, $16, $17, 
-                    $18::call_metrics_emotion_type, 
-                    $19::call_metrics_emotion_type,
-                    $20::call_metrics_emotion_type
+                    $12, $13, $14, $15, $16, $17, $18, $19, $20,
+                    $21::call_metrics_emotion_type, 
+                    $22::call_metrics_emotion_type,
+                    $23::call_metrics_emotion_type,
+                    $24
                 )
             "#,
             metrics.task_id,
@@ -85,11 +131,15 @@ impl CallMetrics {
             metrics.total_client_interruptions_duration,
             metrics.avg_employee_words_per_min,
             metrics.avg_client_words_per_min,
+            metrics.avg_employee_segment_len,
+            metrics.avg_client_segment_len,
             metrics.script_score,
             metrics.employee_quality_score,
+            metrics.overall_score,
             metrics.emotion_mode as Option<EmotionKind>,
             metrics.emotion_start_mode as Option<EmotionKind>,
-            metrics.emotion_end_mode as Option<EmotionKind>
+            metrics.emotion_end_mode as Option<EmotionKind>,
+            metrics.extras as _
         )
         .execute(conn)
         .await?;
@@ -116,11 +166,15 @@ impl CallMetrics {
                     total_client_interruptions_duration,
                     avg_employee_words_per_min,
                     avg_client_words_per_min,
+                    avg_employee_segment_len,
+                    avg_client_segment_len,
                     script_score,
                     employee_quality_score,
+                    overall_score,
                     emotion_mode as "emotion_mode: EmotionKind",
                     emotion_start_mode as "emotion_start_mode: EmotionKind",
-                    emotion_end_mode as "emotion_end_mode: EmotionKind"
+                    emotion_end_mode as "emotion_end_mode: EmotionKind",
+                    extras as "extras: Json<CallMetricsExtras>"
                 FROM task_call_metrics
                 WHERE task_id = $1
             "#,
@@ -129,4 +183,27 @@ impl CallMetrics {
         .fetch_one(conn)
         .await
     }
+
+    /// Overwrites only the emotion fields of the stored metrics row
+    pub async fn update_emotions(&self, conn: &mut sqlx::PgConnection) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE task_call_metrics
+                SET
+                    emotion_mode = $2::call_metrics_emotion_type,
+                    emotion_start_mode = $3::call_metrics_emotion_type,
+                    emotion_end_mode = $4::call_metrics_emotion_type,
+                    extras = jsonb_set(extras, '{sentiment}', to_jsonb($5::real))
+                WHERE task_id = $1
+            "#,
+            self.task_id,
+            self.emotion_mode as Option<EmotionKind>,
+            self.emotion_start_mode as Option<EmotionKind>,
+            self.emotion_end_mode as Option<EmotionKind>,
+            self.extras.sentiment
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
 }
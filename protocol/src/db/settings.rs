@@ -1,7 +1,11 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::entity::ParticipantKind;
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, sqlx::Type, ToSchema)]
 #[serde(rename_all = "snake_case")]
 #[sqlx(type_name = "settings_type", rename_all = "snake_case")]
@@ -10,7 +14,7 @@ pub enum SettingsKind {
     Script,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Settings {
     pub id: Uuid,
     pub project_id: Uuid,
@@ -68,6 +72,64 @@ impl Settings {
     }
 }
 
+/// Project-wide preferences which aren't tied to a settings kind
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ProjectPreferences {
+    #[serde(skip_deserializing)]
+    pub project_id: Uuid,
+    /// Participant of imported dictionaries when the import request omits it
+    pub dictionary_import_participant: Option<ParticipantKind>,
+    /// Emotions of the project calls are recognized by the ASR
+    #[serde(default)]
+    pub emotion_recognition: bool,
+}
+
+impl ProjectPreferences {
+    pub async fn fetch_by_project_id(
+        project_id: Uuid,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Option<Self>> {
+        sqlx::query_as!(
+            ProjectPreferences,
+            r#"
+                SELECT
+                    project_id,
+                    dictionary_import_participant as "dictionary_import_participant: ParticipantKind",
+                    emotion_recognition
+                FROM project_preferences
+                WHERE project_id = $1
+            "#,
+            project_id,
+        )
+        .fetch_optional(conn)
+        .await
+    }
+
+    pub async fn upsert(this: Self, conn: &mut sqlx::PgConnection) -> sqlx::Result<Self> {
+        sqlx::query_as!(
+            ProjectPreferences,
+            r#"
+                INSERT INTO project_preferences
+                    (project_id, dictionary_import_participant, emotion_recognition)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (project_id) DO UPDATE
+                SET
+                    dictionary_import_participant = EXCLUDED.dictionary_import_participant,
+                    emotion_recognition = EXCLUDED.emotion_recognition
+                RETURNING
+                    project_id,
+                    dictionary_import_participant as "dictionary_import_participant: ParticipantKind",
+                    emotion_recognition
+            "#,
+            this.project_id,
+            this.dictionary_import_participant as Option<ParticipantKind>,
+            this.emotion_recognition,
+        )
+        .fetch_one(conn)
+        .await
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, sqlx::Type, ToSchema)]
 #[serde(rename_all = "snake_case")]
 #[sqlx(type_name = "settings_item_type", rename_all = "snake_case")]
@@ -83,7 +145,7 @@ pub enum SettingsItemKind {
     Dictionary,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct SettingsItem {
     pub id: Uuid,
     pub settings_id: Uuid,
@@ -91,6 +153,10 @@ pub struct SettingsItem {
     pub r#type: SettingsItemKind,
     pub name: String,
     pub score_weight: i32,
+    /// Graded scoring for holds, pauses and interruptions: the score decreases linearly
+    /// down to zero at the threshold (holds count or seconds), binary when unset
+    #[serde(default)]
+    pub graded_threshold: Option<f32>,
 }
 
 impl SettingsItem {
@@ -102,7 +168,7 @@ impl SettingsItem {
             SettingsItem,
             r#"
                 SELECT si.id, si.settings_id, si.settings_immutable, 
-                    si.type as "type: SettingsItemKind", si.name, si.score_weight
+                    si.type as "type: SettingsItemKind", si.name, si.score_weight, si.graded_threshold
                 FROM settings_item si
                 JOIN settings on si.settings_id = settings.id
                 WHERE project_id = $1
@@ -118,16 +184,18 @@ impl SettingsItem {
             SettingsItem,
             r#"
                 INSERT INTO settings_item
-                    (settings_id, settings_immutable, type, name, score_weight)
-                VALUES ($1, $2, $3::settings_item_type, $4, $5)
+                    (settings_id, settings_immutable, type, name, score_weight, graded_threshold)
+                VALUES ($1, $2, $3::settings_item_type, $4, $5, $6)
                 RETURNING
-                    id, settings_id, settings_immutable, type as "type: SettingsItemKind", name, score_weight
+                    id, settings_id, settings_immutable, type as "type: SettingsItemKind", name, score_weight,
+                    graded_threshold
             "#,
             this.settings_id,
             this.settings_immutable,
             this.r#type as SettingsItemKind,
             this.name,
-            this.score_weight
+            this.score_weight,
+            this.graded_threshold
         )
         .fetch_one(conn)
         .await
@@ -141,7 +209,8 @@ impl SettingsItem {
             SettingsItem,
             r#"
                 SELECT 
-                    id, settings_id, settings_immutable, type as "type: SettingsItemKind", name, score_weight
+                    id, settings_id, settings_immutable, type as "type: SettingsItemKind", name, score_weight,
+                    graded_threshold
                 FROM settings_item
                 WHERE id = $1
             "#,
@@ -155,6 +224,7 @@ impl SettingsItem {
         id: Uuid,
         name: String,
         score_weight: i32,
+        graded_threshold: Option<f32>,
         conn: &mut sqlx::PgConnection,
     ) -> sqlx::Result<()> {
         sqlx::query!(
@@ -162,12 +232,14 @@ impl SettingsItem {
                 UPDATE settings_item
                 SET
                     name = $2,
-                    score_weight = $3
+                    score_weight = $3,
+                    graded_threshold = $4
                 WHERE id = $1
             "#,
             id,
             name,
-            score_weight
+            score_weight,
+            graded_threshold
         )
         .execute(conn)
         .await?;
@@ -190,7 +262,7 @@ impl SettingsItem {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct SettingsDictItem {
     #[serde(skip_deserializing)]
     pub id: Uuid,
@@ -219,7 +291,10 @@ impl SettingsDictItem {
         .await
     }
 
-    pub async fn bulk_insert(this: Vec<Self>, conn: &mut sqlx::PgConnection) -> sqlx::Result<()> {
+    pub async fn bulk_insert(
+        this: Vec<Self>,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<Self>> {
         let mut item_ids = Vec::new();
         let mut dict_ids = Vec::new();
         let mut contains = Vec::new();
@@ -229,23 +304,117 @@ impl SettingsDictItem {
             contains.push(item.contains);
         });
 
-        sqlx::query!(
+        sqlx::query_as!(
+            SettingsDictItem,
             r#"
                 INSERT INTO settings_dict_item
                     (settings_item_id, dictionary_id, contains)
                 SELECT settings_item_id, dictionary_id, contains
                 FROM UNNEST($1::uuid[], $2::int[], $3::bool[]) as a(settings_item_id, dictionary_id, contains)
+                RETURNING id, settings_item_id, dictionary_id, contains
             "#,
             &item_ids,
             &dict_ids,
             &contains
         )
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn list_by_item_id(
+        settings_item_id: Uuid,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<Self>> {
+        sqlx::query_as!(
+            SettingsDictItem,
+            r#"
+                SELECT id, settings_item_id, dictionary_id, contains
+                FROM settings_dict_item
+                WHERE settings_item_id = $1
+            "#,
+            settings_item_id,
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn delete_by_ids(ids: &[Uuid], conn: &mut sqlx::PgConnection) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+                DELETE FROM settings_dict_item
+                WHERE id = ANY($1)
+            "#,
+            ids,
+        )
         .execute(conn)
         .await?;
 
         Ok(())
     }
 
+    pub async fn update_contains_by_id(
+        id: Uuid,
+        contains: bool,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE settings_dict_item
+                SET contains = $2
+                WHERE id = $1
+            "#,
+            id,
+            contains,
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Brings dict links of the item to the requested set keyed on dictionary id,
+    /// links which are already in place keep their ids
+    pub async fn reconcile_by_item_id(
+        settings_item_id: Uuid,
+        dict_items: Vec<Self>,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<()> {
+        let mut requested: HashMap<i32, bool> = dict_items
+            .into_iter()
+            .map(|item| (item.dictionary_id, item.contains))
+            .collect();
+        let existing = Self::list_by_item_id(settings_item_id, conn).await?;
+
+        let mut to_delete = vec![];
+        for item in existing {
+            match requested.remove(&item.dictionary_id) {
+                None => to_delete.push(item.id),
+                Some(contains) if contains != item.contains => {
+                    Self::update_contains_by_id(item.id, contains, conn).await?
+                }
+                Some(_) => {}
+            }
+        }
+        if !to_delete.is_empty() {
+            Self::delete_by_ids(&to_delete, conn).await?;
+        }
+
+        let to_insert: Vec<Self> = requested
+            .into_iter()
+            .map(|(dictionary_id, contains)| Self {
+                id: Uuid::default(),
+                settings_item_id,
+                dictionary_id,
+                contains,
+            })
+            .collect();
+        if !to_insert.is_empty() {
+            Self::bulk_insert(to_insert, conn).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn delete_by_item_id(
         settings_item_id: Uuid,
         conn: &mut sqlx::PgConnection,
@@ -9,16 +9,58 @@ pub enum TaskResultKind {
     Processing,
     Ready,
     Failed,
+    Cancelled,
 }
 
+/// Whether a failed task may succeed if processed again
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "task_failure_class", rename_all = "snake_case")]
+pub enum TaskFailureClass {
+    Transient,
+    Permanent,
+}
+
+/// What a task failed on, for grouping failures
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "task_failure_kind", rename_all = "snake_case")]
+pub enum TaskFailureKind {
+    /// The speech recognition didn't respond or finish in time
+    AsrTimeout,
+    /// The speech recognition refused or failed to process the recording
+    AsrRejected,
+    /// The recording has no recognizable speech
+    NoSpeech,
+    /// The transcript couldn't be indexed
+    IndexError,
+    /// The call metadata of the task is missing or malformed
+    InvalidMetadata,
+    Unknown,
+}
+
+/// Creator of the demo tasks seeded for local development
+pub const DEMO_TASK_CREATOR: &str = "seed";
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Task {
     pub id: Uuid,
     pub call_metadata_id: Uuid,
     pub status: TaskResultKind,
     pub failed_reason: Option<String>,
+    #[serde(default)]
+    pub failure_class: Option<TaskFailureClass>,
+    #[serde(default)]
+    pub failure_kind: Option<TaskFailureKind>,
     #[serde(skip_deserializing)]
     pub project_id: Uuid,
+    #[serde(skip_deserializing)]
+    pub created_by: Option<String>,
+    #[serde(skip_deserializing)]
+    pub reprocessed_by: Option<String>,
+    /// How many times the worker started processing the task, carried over on reprocess
+    #[serde(skip_deserializing)]
+    pub attempts: i32,
 }
 
 impl Task {
@@ -27,18 +69,26 @@ impl Task {
             Task,
             r#"
                 INSERT INTO task
-                    (call_metadata_id, status, project_id)
-                VALUES ($1, $2::task_result_status, $3)
+                    (call_metadata_id, status, project_id, created_by, reprocessed_by, attempts)
+                VALUES ($1, $2::task_result_status, $3, $4, $5, $6)
                 RETURNING
                     id,
                     call_metadata_id,
                     status as "status: TaskResultKind",
                     failed_reason,
-                    project_id
+                    failure_class as "failure_class: TaskFailureClass",
+                    failure_kind as "failure_kind: TaskFailureKind",
+                    project_id,
+                    created_by,
+                    reprocessed_by,
+                    attempts
             "#,
             self.call_metadata_id,
             self.status as TaskResultKind,
-            self.project_id
+            self.project_id,
+            self.created_by,
+            self.reprocessed_by,
+            self.attempts
         )
         .fetch_one(conn)
         .await
@@ -53,7 +103,12 @@ impl Task {
                 call_metadata_id,
                 status as "status: TaskResultKind",
                 failed_reason,
-                project_id
+                failure_class as "failure_class: TaskFailureClass",
+                failure_kind as "failure_kind: TaskFailureKind",
+                project_id,
+                created_by,
+                reprocessed_by,
+                attempts
             FROM task
             WHERE id = $1
             "#,
@@ -63,6 +118,33 @@ impl Task {
         .await
     }
 
+    pub async fn list_ready_by_creator(
+        created_by: &str,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<Task>> {
+        sqlx::query_as!(
+            Task,
+            r#"
+            SELECT
+                id,
+                call_metadata_id,
+                status as "status: TaskResultKind",
+                failed_reason,
+                failure_class as "failure_class: TaskFailureClass",
+                failure_kind as "failure_kind: TaskFailureKind",
+                project_id,
+                created_by,
+                reprocessed_by,
+                attempts
+            FROM task
+            WHERE created_by = $1 AND status = 'ready'
+            "#,
+            created_by,
+        )
+        .fetch_all(conn)
+        .await
+    }
+
     pub async fn update(&self, conn: &mut sqlx::PgConnection) -> sqlx::Result<()> {
         sqlx::query_as!(
             Task,
@@ -70,19 +152,162 @@ impl Task {
                 UPDATE task
                 SET 
                     status = $2, 
-                    failed_reason = $3
+                    failed_reason = $3,
+                    failure_class = $4,
+                    failure_kind = $5,
+                    -- a re-queued task is not started until the worker picks it up again
+                    started_at = CASE WHEN $2::task_result_status = 'processing' THEN NULL ELSE started_at END,
+                    queued_at = CASE WHEN $2::task_result_status = 'processing' THEN now() ELSE queued_at END
                 WHERE 
                     id = $1
             "#,
             self.id,
             self.status as TaskResultKind,
-            self.failed_reason
+            self.failed_reason,
+            self.failure_class as Option<TaskFailureClass>,
+            self.failure_kind as Option<TaskFailureKind>
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the processing outcome unless the task was cancelled meanwhile,
+    /// returns `false` when the cancelled status was kept
+    pub async fn update_unless_cancelled(
+        &self,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE task
+                SET
+                    status = $2,
+                    failed_reason = $3,
+                    failure_class = $4,
+                    failure_kind = $5
+                WHERE
+                    id = $1 AND status <> 'cancelled'
+            "#,
+            self.id,
+            self.status as TaskResultKind,
+            self.failed_reason,
+            self.failure_class as Option<TaskFailureClass>,
+            self.failure_kind as Option<TaskFailureKind>
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Cancels the task only while it's processing, `None` when it isn't processing
+    /// or doesn't exist
+    pub async fn cancel_if_processing(
+        id: &Uuid,
+        failed_reason: &str,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Option<Task>> {
+        sqlx::query_as!(
+            Task,
+            r#"
+                UPDATE task
+                SET status = 'cancelled', failed_reason = $2
+                WHERE id = $1 AND status = 'processing'
+                RETURNING
+                    id,
+                    call_metadata_id,
+                    status as "status: TaskResultKind",
+                    failed_reason,
+                    failure_class as "failure_class: TaskFailureClass",
+                    failure_kind as "failure_kind: TaskFailureKind",
+                    project_id,
+                    created_by,
+                    reprocessed_by,
+                    attempts
+            "#,
+            id,
+            failed_reason
+        )
+        .fetch_optional(conn)
+        .await
+    }
+
+    pub async fn increment_attempts(id: &Uuid, conn: &mut sqlx::PgConnection) -> sqlx::Result<i32> {
+        sqlx::query!(
+            r#"
+                UPDATE task
+                SET attempts = attempts + 1, started_at = now()
+                WHERE id = $1
+                RETURNING attempts
+            "#,
+            id
+        )
+        .fetch_one(conn)
+        .await
+        .map(|r| r.attempts)
+    }
+
+    /// Refreshes the start of a task which is still being processed, so a long running
+    /// task is told apart from one left by a crashed worker
+    pub async fn heartbeat(id: &Uuid, conn: &mut sqlx::PgConnection) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+                UPDATE task
+                SET started_at = now()
+                WHERE id = $1 AND status = 'processing'
+            "#,
+            id
         )
         .execute(conn)
         .await?;
 
         Ok(())
     }
+
+    /// Re-queues tasks with no progress for longer than `timeout`, returning their ids.
+    /// Tasks never picked up by a worker are timed out since they were queued
+    pub async fn touch_stuck(
+        timeout: std::time::Duration,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<Uuid>> {
+        sqlx::query!(
+            r#"
+                UPDATE task
+                SET started_at = NULL, queued_at = now()
+                WHERE status = 'processing'
+                    AND COALESCE(started_at, queued_at) < now() - make_interval(secs => $1)
+                RETURNING id
+            "#,
+            timeout.as_secs_f64()
+        )
+        .fetch_all(conn)
+        .await
+        .map(|rows| rows.into_iter().map(|r| r.id).collect())
+    }
+
+    /// Fails tasks with no progress for longer than `timeout` as transient, returning their ids
+    pub async fn fail_stuck(
+        timeout: std::time::Duration,
+        reason: &str,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<Vec<Uuid>> {
+        sqlx::query!(
+            r#"
+                UPDATE task
+                SET status = 'failed', failed_reason = $2, failure_class = 'transient', failure_kind = 'unknown'
+                WHERE status = 'processing'
+                    AND COALESCE(started_at, queued_at) < now() - make_interval(secs => $1)
+                RETURNING id
+            "#,
+            timeout.as_secs_f64(),
+            reason
+        )
+        .fetch_all(conn)
+        .await
+        .map(|rows| rows.into_iter().map(|r| r.id).collect())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::db::dictionary::Phrase;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DictionaryTestRequest {
+    /// Sample transcript, treated as spoken by the participant of the dictionary
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PhraseMatch {
+    #[serde(flatten)]
+    pub phrase: Phrase,
+    pub matched: bool,
+}
@@ -18,5 +18,6 @@ impl fmt::Display for ParticipantKind {
     }
 }
 
+pub mod dictionary_test;
 pub mod settings_metrics;
 pub mod speech_recog;
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::{
@@ -23,6 +23,206 @@ pub struct TaskSettingsMetrics {
     pub settings: Settings,
     pub total_score: i32, // normalized to 100%
     pub items: Vec<TaskSettingsItemMetric>,
+    /// The call has too little speech to be scored, the score and items are empty
+    pub unscored: bool,
+}
+
+/// How a settings dict item is scored when its dictionary has no match result for the task,
+/// e.g. the dictionary was deleted or has no phrases
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingDictionaryPolicy {
+    /// The dict item is left out, so the remaining dict items decide the match
+    #[default]
+    Ignore,
+    /// The dictionary is treated as not found in the call transcript
+    NotContained,
+    /// The whole settings item is excluded from scoring and from the weights normalization
+    SkipItem,
+    /// Scoring fails
+    Error,
+}
+
+/// Weights of the script and quality scores in the overall call score,
+/// rejected on load unless both are non-negative and their sum is positive
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(try_from = "RawOverallScoreWeights")]
+pub struct OverallScoreWeights {
+    pub script: f32,
+    pub quality: f32,
+}
+
+#[derive(Deserialize)]
+struct RawOverallScoreWeights {
+    script: f32,
+    quality: f32,
+}
+
+impl TryFrom<RawOverallScoreWeights> for OverallScoreWeights {
+    type Error = String;
+
+    fn try_from(raw: RawOverallScoreWeights) -> Result<Self, Self::Error> {
+        let RawOverallScoreWeights { script, quality } = raw;
+        if !(script >= 0.0 && quality >= 0.0 && script + quality > 0.0) {
+            return Err(format!(
+                "overall score weights must be non-negative with a positive sum, \
+                got script {script} and quality {quality}"
+            ));
+        }
+
+        Ok(Self { script, quality })
+    }
+}
+
+impl Default for OverallScoreWeights {
+    fn default() -> Self {
+        Self {
+            script: 0.5,
+            quality: 0.5,
+        }
+    }
+}
+
+/// Configured in the worker and stored with the metrics it scores, so rescoring them
+/// applies the same rules
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ScoringRules {
+    #[serde(default)]
+    pub missing_dictionary_policy: MissingDictionaryPolicy,
+    #[serde(default)]
+    pub overall_score_weights: OverallScoreWeights,
+    /// Calls with less employee and client speech (in seconds) are left unscored
+    #[serde(default)]
+    pub min_scored_speech: f32,
+}
+
+fn overall_score(script_score: i32, quality_score: i32, weights: OverallScoreWeights) -> i32 {
+    let weights_sum = weights.script + weights.quality;
+    if weights_sum <= 0.0 {
+        return 0;
+    }
+
+    ((script_score as f32 * weights.script + quality_score as f32 * weights.quality) / weights_sum)
+        .round() as i32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsItemSource {
+    /// Scored by matching the call transcript against the linked dictionaries
+    Dictionary,
+    /// Scored from the computed call metrics
+    Metric,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, ToSchema)]
+pub struct SettingsItemKindDefinition {
+    pub kind: SettingsItemKind,
+    pub description: &'static str,
+    pub source: SettingsItemSource,
+    pub scoring_rule: &'static str,
+}
+
+const DICTIONARY_SCORING_RULE: &str =
+    "Full weight when the linked dictionaries are found or not found in the transcript as configured";
+
+/// Human readable definitions of every settings item kind, keep in sync with the scoring below
+pub const SETTINGS_ITEM_KINDS: [SettingsItemKindDefinition; 9] = [
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::SpeechRateRatio,
+        description: "Balance of employee and client speech duration",
+        source: SettingsItemSource::Metric,
+        scoring_rule: "Full weight when the employee/client speech ratio is between 80% and 120%",
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::CallHolds,
+        description: "Call holds with silence or music",
+        source: SettingsItemSource::Metric,
+        scoring_rule: "Full weight without holds, with a graded threshold decreases linearly to zero at the threshold holds count",
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::SilencePauses,
+        description: "Employee silence pauses while the client waits for an answer",
+        source: SettingsItemSource::Metric,
+        scoring_rule: "Full weight without pauses, with a graded threshold decreases linearly to zero at the threshold seconds of silence",
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::Interruptions,
+        description: "Client interrupted by the employee",
+        source: SettingsItemSource::Metric,
+        scoring_rule: "Full weight without interruptions, with a graded threshold decreases linearly to zero at the threshold seconds of interruptions",
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::LackingInfoDict,
+        description: "Employee phrases showing a lack of information",
+        source: SettingsItemSource::Dictionary,
+        scoring_rule: DICTIONARY_SCORING_RULE,
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::FillerWordsDict,
+        description: "Filler words in employee speech",
+        source: SettingsItemSource::Dictionary,
+        scoring_rule: DICTIONARY_SCORING_RULE,
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::SlurredSpeechDict,
+        description: "Slurred or unclear employee speech",
+        source: SettingsItemSource::Dictionary,
+        scoring_rule: DICTIONARY_SCORING_RULE,
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::ProfanitySpeechDict,
+        description: "Profanity in employee speech",
+        source: SettingsItemSource::Dictionary,
+        scoring_rule: DICTIONARY_SCORING_RULE,
+    },
+    SettingsItemKindDefinition {
+        kind: SettingsItemKind::Dictionary,
+        description: "Custom script phrases from user dictionaries",
+        source: SettingsItemSource::Dictionary,
+        scoring_rule: DICTIONARY_SCORING_RULE,
+    },
+];
+
+fn dicts_match(
+    item_dicts: &[SettingsDictItem],
+    task_to_dicts: &HashMap<i32, bool>,
+    policy: MissingDictionaryPolicy,
+) -> anyhow::Result<Option<bool>> {
+    // any negative condition means every dictionary condition has to be met
+    let all_match = item_dicts.iter().any(|dict_item| !dict_item.contains);
+
+    let mut matches = vec![];
+    for dict_item in item_dicts {
+        let dict_contains = match task_to_dicts.get(&dict_item.dictionary_id) {
+            Some(dict_contains) => *dict_contains,
+            None => match policy {
+                MissingDictionaryPolicy::Ignore => continue,
+                MissingDictionaryPolicy::NotContained => false,
+                MissingDictionaryPolicy::SkipItem => return Ok(None),
+                MissingDictionaryPolicy::Error => anyhow::bail!(
+                    "dictionary {} of settings item {} is missing",
+                    dict_item.dictionary_id,
+                    dict_item.settings_item_id
+                ),
+            },
+        };
+        matches.push(dict_contains == dict_item.contains);
+    }
+
+    if all_match {
+        Ok(Some(matches.into_iter().all(|matched| matched)))
+    } else {
+        Ok(Some(matches.into_iter().any(|matched| matched)))
+    }
+}
+
+fn graded_match(count: i32, amount: f32, threshold: Option<f32>) -> f32 {
+    match threshold {
+        Some(threshold) if threshold > 0.0 => (1.0 - amount / threshold).clamp(0.0, 1.0),
+        _ if count == 0 => 1.0,
+        _ => 0.0,
+    }
 }
 
 pub fn calculate_settings_metrics(
@@ -31,7 +231,27 @@ pub fn calculate_settings_metrics(
     settings: Vec<Settings>,
     settings_items: Vec<SettingsItem>,
     settings_dict_items: Vec<SettingsDictItem>,
+    rules: ScoringRules,
 ) -> anyhow::Result<Vec<TaskSettingsMetrics>> {
+    // a call left unscored when processed stays so, whatever threshold rescoring uses
+    let total_speech = call_metrics.total_employee_speech + call_metrics.total_client_speech;
+    if call_metrics.extras.unscored || total_speech < rules.min_scored_speech {
+        call_metrics.script_score = 0;
+        call_metrics.employee_quality_score = 0;
+        call_metrics.overall_score = 0;
+        call_metrics.extras.unscored = true;
+
+        return Ok(settings
+            .into_iter()
+            .map(|settings| TaskSettingsMetrics {
+                settings,
+                total_score: 0,
+                items: vec![],
+                unscored: true,
+            })
+            .collect());
+    }
+
     let task_to_dicts: HashMap<i32, bool> = task_to_dicts
         .into_iter()
         .map(|item| (item.dictionary_id, item.contains))
@@ -42,21 +262,6 @@ pub fn calculate_settings_metrics(
 
     let mut result = vec![];
     for settings in settings.into_iter() {
-        let score_point_normalized = {
-            let sum_goal_scores_weights = settings_to_items
-                .get(&settings.id)
-                .ok_or(anyhow::anyhow!(
-                    "can't find related settings {} {:?}",
-                    settings.id,
-                    settings.r#type
-                ))?
-                .iter()
-                .fold(0, |acc, settings_item| acc + settings_item.score_weight);
-            100f32 / sum_goal_scores_weights as f32
-        };
-
-        let mut total_score = 0;
-        let mut settings_items_metrics = vec![];
         let settings_items = settings_to_items
             .remove(&settings.id)
             .ok_or(anyhow::anyhow!(
@@ -64,46 +269,64 @@ pub fn calculate_settings_metrics(
                 settings.id,
                 settings.r#type
             ))?;
+
+        let mut matched_items = vec![];
         for settings_item in settings_items.into_iter() {
+            let threshold = settings_item.graded_threshold;
+            // share of the item weight which is scored, from 0 to 1
             let item_match = match settings_item.r#type {
-                SettingsItemKind::CallHolds => call_metrics.call_holds_count == 0,
-                SettingsItemKind::SilencePauses => call_metrics.silence_pause_count == 0,
-                SettingsItemKind::Interruptions => call_metrics.client_interruptions_count == 0,
+                SettingsItemKind::CallHolds => graded_match(
+                    call_metrics.call_holds_count,
+                    call_metrics.call_holds_count as f32,
+                    threshold,
+                ),
+                SettingsItemKind::SilencePauses => graded_match(
+                    call_metrics.silence_pause_count,
+                    call_metrics.total_employee_silence,
+                    threshold,
+                ),
+                SettingsItemKind::Interruptions => graded_match(
+                    call_metrics.client_interruptions_count,
+                    call_metrics.total_client_interruptions_duration,
+                    threshold,
+                ),
                 SettingsItemKind::SpeechRateRatio => {
-                    call_metrics.employee_client_speech_ratio <= 120.0
-                        && call_metrics.employee_client_speech_ratio >= 80.0
+                    let in_range = call_metrics.employee_client_speech_ratio <= 120.0
+                        && call_metrics.employee_client_speech_ratio >= 80.0;
+                    if in_range {
+                        1.0
+                    } else {
+                        0.0
+                    }
                 }
                 _ => {
                     let item_dicts = items_to_dict_items
                         .remove(&settings_item.id)
                         .unwrap_or(vec![]);
-                    let all_match = item_dicts.iter().any(|dict_item| !dict_item.contains);
-
-                    let dicts_match = if all_match {
-                        item_dicts.iter().all(|dict_item| {
-                            task_to_dicts
-                                .get(&dict_item.dictionary_id)
-                                .map(|dict_contains| *dict_contains == dict_item.contains)
-                                .unwrap_or(true)
-                        })
-                    } else {
-                        item_dicts.iter().any(|dict_item| {
-                            task_to_dicts
-                                .get(&dict_item.dictionary_id)
-                                .map(|dict_contains| *dict_contains == dict_item.contains)
-                                .unwrap_or(false)
-                        })
-                    };
-
-                    dicts_match
+
+                    match dicts_match(&item_dicts, &task_to_dicts, rules.missing_dictionary_policy)?
+                    {
+                        Some(true) => 1.0,
+                        Some(false) => 0.0,
+                        None => continue,
+                    }
                 }
             };
+            matched_items.push((settings_item, item_match));
+        }
 
-            let score = if item_match {
-                settings_item.score_weight as f32 * score_point_normalized
-            } else {
-                0f32
-            };
+        let score_point_normalized = {
+            let sum_goal_scores_weights =
+                matched_items.iter().fold(0, |acc, (settings_item, _)| {
+                    acc + settings_item.score_weight
+                });
+            100f32 / sum_goal_scores_weights as f32
+        };
+
+        let mut total_score = 0;
+        let mut settings_items_metrics = vec![];
+        for (settings_item, item_match) in matched_items.into_iter() {
+            let score = settings_item.score_weight as f32 * score_point_normalized * item_match;
 
             let settings_item_metric = TaskSettingsItemMetric {
                 settings_item,
@@ -130,8 +353,316 @@ pub fn calculate_settings_metrics(
             settings,
             total_score,
             items: settings_items_metrics,
+            unscored: false,
         });
     }
 
+    call_metrics.overall_score = overall_score(
+        call_metrics.script_score,
+        call_metrics.employee_quality_score,
+        rules.overall_score_weights,
+    );
+
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn calculate(policy: MissingDictionaryPolicy) -> anyhow::Result<TaskSettingsMetrics> {
+        let settings_id = Uuid::new_v4();
+        let dict_item_id = Uuid::new_v4();
+        let task_id = Uuid::new_v4();
+
+        let dict_item = |dictionary_id, contains| SettingsDictItem {
+            id: Uuid::new_v4(),
+            settings_item_id: dict_item_id,
+            dictionary_id,
+            contains,
+        };
+        // dictionary 2 has no match result for the task
+        let task_to_dicts = vec![
+            TaskToDict {
+                task_id,
+                dictionary_id: 1,
+                contains: true,
+            },
+            TaskToDict {
+                task_id,
+                dictionary_id: 3,
+                contains: false,
+            },
+        ];
+
+        calculate_settings_metrics(
+            task_to_dicts,
+            &mut CallMetrics::default(),
+            vec![Settings {
+                id: settings_id,
+                project_id: Uuid::default(),
+                r#type: SettingsKind::Script,
+            }],
+            vec![
+                SettingsItem {
+                    id: dict_item_id,
+                    settings_id,
+                    settings_immutable: false,
+                    r#type: SettingsItemKind::Dictionary,
+                    name: "dict_item".to_string(),
+                    score_weight: 1,
+                    graded_threshold: None,
+                },
+                SettingsItem {
+                    id: Uuid::new_v4(),
+                    settings_id,
+                    settings_immutable: false,
+                    r#type: SettingsItemKind::CallHolds,
+                    name: "holds_item".to_string(),
+                    score_weight: 1,
+                    graded_threshold: None,
+                },
+            ],
+            vec![dict_item(1, true), dict_item(2, true), dict_item(3, false)],
+            ScoringRules {
+                missing_dictionary_policy: policy,
+                ..Default::default()
+            },
+        )
+        .map(|mut metrics| metrics.remove(0))
+    }
+
+    #[test]
+    fn missing_dictionary_ignore() {
+        let metrics = calculate(MissingDictionaryPolicy::Ignore).unwrap();
+        assert_eq!(metrics.items.len(), 2);
+        assert_eq!(metrics.total_score, 100);
+    }
+
+    #[test]
+    fn missing_dictionary_not_contained() {
+        let metrics = calculate(MissingDictionaryPolicy::NotContained).unwrap();
+        assert_eq!(metrics.items.len(), 2);
+        assert_eq!(metrics.items[0].score, 0);
+        assert_eq!(metrics.total_score, 50);
+    }
+
+    #[test]
+    fn missing_dictionary_skip_item() {
+        let metrics = calculate(MissingDictionaryPolicy::SkipItem).unwrap();
+        assert_eq!(metrics.items.len(), 1);
+        assert_eq!(metrics.items[0].settings_item.name, "holds_item");
+        assert_eq!(metrics.total_score, 100);
+    }
+
+    #[test]
+    fn missing_dictionary_error() {
+        assert!(calculate(MissingDictionaryPolicy::Error).is_err());
+    }
+
+    #[test]
+    fn graded_interruptions() {
+        let settings_id = Uuid::new_v4();
+        let settings = || {
+            vec![Settings {
+                id: settings_id,
+                project_id: Uuid::default(),
+                r#type: SettingsKind::Quality,
+            }]
+        };
+        let settings_items = |graded_threshold| {
+            vec![SettingsItem {
+                id: Uuid::new_v4(),
+                settings_id,
+                settings_immutable: true,
+                r#type: SettingsItemKind::Interruptions,
+                name: "interruptions".to_string(),
+                score_weight: 1,
+                graded_threshold,
+            }]
+        };
+        let call_metrics = CallMetrics {
+            client_interruptions_count: 2,
+            total_client_interruptions_duration: 4.0,
+            ..Default::default()
+        };
+
+        let metrics = calculate_settings_metrics(
+            vec![],
+            &mut call_metrics.clone(),
+            settings(),
+            settings_items(None),
+            vec![],
+            ScoringRules::default(),
+        )
+        .unwrap();
+        assert_eq!(metrics[0].total_score, 0);
+
+        let metrics = calculate_settings_metrics(
+            vec![],
+            &mut call_metrics.clone(),
+            settings(),
+            settings_items(Some(10.0)),
+            vec![],
+            ScoringRules::default(),
+        )
+        .unwrap();
+        assert_eq!(metrics[0].total_score, 60);
+    }
+
+    #[test]
+    fn near_silent_call_unscored() {
+        let settings_id = Uuid::new_v4();
+        let mut call_metrics = CallMetrics {
+            call_duration: 5.0,
+            total_employee_speech: 0.5,
+            total_client_speech: 0.3,
+            ..Default::default()
+        };
+
+        let metrics = calculate_settings_metrics(
+            vec![],
+            &mut call_metrics,
+            vec![Settings {
+                id: settings_id,
+                project_id: Uuid::default(),
+                r#type: SettingsKind::Script,
+            }],
+            vec![SettingsItem {
+                id: Uuid::new_v4(),
+                settings_id,
+                settings_immutable: true,
+                r#type: SettingsItemKind::CallHolds,
+                name: "holds_item".to_string(),
+                score_weight: 1,
+                graded_threshold: None,
+            }],
+            vec![],
+            ScoringRules {
+                min_scored_speech: 3.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(metrics[0].unscored);
+        assert!(metrics[0].items.is_empty());
+        assert_eq!(metrics[0].total_score, 0);
+        assert_eq!(call_metrics.script_score, 0);
+        assert!(call_metrics.extras.unscored);
+
+        // rescoring the stored metrics keeps the call unscored
+        let metrics = calculate_settings_metrics(
+            vec![],
+            &mut call_metrics,
+            vec![Settings {
+                id: settings_id,
+                project_id: Uuid::default(),
+                r#type: SettingsKind::Script,
+            }],
+            vec![],
+            vec![],
+            ScoringRules::default(),
+        )
+        .unwrap();
+        assert!(metrics[0].unscored);
+    }
+
+    #[test]
+    fn overall_score_blend() {
+        let script_id = Uuid::new_v4();
+        let quality_id = Uuid::new_v4();
+        let settings = || {
+            [
+                (script_id, SettingsKind::Script),
+                (quality_id, SettingsKind::Quality),
+            ]
+            .into_iter()
+            .map(|(id, r#type)| Settings {
+                id,
+                project_id: Uuid::default(),
+                r#type,
+            })
+            .collect()
+        };
+        // interruptions pass and holds fail: script 100, quality 50
+        let settings_items = || {
+            [
+                (script_id, SettingsItemKind::Interruptions),
+                (quality_id, SettingsItemKind::CallHolds),
+                (quality_id, SettingsItemKind::Interruptions),
+            ]
+            .into_iter()
+            .map(|(settings_id, r#type)| SettingsItem {
+                id: Uuid::new_v4(),
+                settings_id,
+                settings_immutable: true,
+                r#type,
+                name: "item".to_string(),
+                score_weight: 1,
+                graded_threshold: None,
+            })
+            .collect()
+        };
+
+        let weighted = |weights| {
+            let mut call_metrics = CallMetrics {
+                call_holds_count: 1,
+                ..Default::default()
+            };
+            calculate_settings_metrics(
+                vec![],
+                &mut call_metrics,
+                settings(),
+                settings_items(),
+                vec![],
+                ScoringRules {
+                    overall_score_weights: weights,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(call_metrics.script_score, 100);
+            assert_eq!(call_metrics.employee_quality_score, 50);
+            call_metrics.overall_score
+        };
+
+        assert_eq!(weighted(OverallScoreWeights::default()), 75);
+        assert_eq!(
+            weighted(OverallScoreWeights {
+                script: 0.2,
+                quality: 0.8
+            }),
+            60
+        );
+        assert_eq!(
+            weighted(OverallScoreWeights {
+                script: 0.0,
+                quality: 0.0
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn overall_score_weights_validated() {
+        let weights = |json| serde_json::from_str::<OverallScoreWeights>(json);
+
+        assert_eq!(
+            weights(r#"{"script": 0.0, "quality": 1.0}"#).unwrap(),
+            OverallScoreWeights {
+                script: 0.0,
+                quality: 1.0
+            }
+        );
+        for invalid in [
+            r#"{"script": -0.5, "quality": 1.0}"#,
+            r#"{"script": 0.0, "quality": 0.0}"#,
+        ] {
+            let err = weights(invalid).expect_err(invalid);
+            assert!(err.to_string().contains("positive sum"));
+        }
+    }
+}
@@ -17,7 +17,17 @@ pub enum EmotionKind {
     Other,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+impl EmotionKind {
+    pub const ALL: [EmotionKind; 5] = [
+        EmotionKind::Neutral,
+        EmotionKind::Positive,
+        EmotionKind::Angry,
+        EmotionKind::Sad,
+        EmotionKind::Other,
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct RecognitionData {
     pub call_holds: CallHolds,
     pub emotion_recognition_result: Vec<EmotionKind>,
@@ -25,7 +35,7 @@ pub struct RecognitionData {
     pub speech_recognition_result: Vec<SpeechRecognition>,
 }
 
-#[derive(Serialize, Default, PartialEq, Deserialize, Debug, ToSchema)]
+#[derive(Clone, Serialize, Default, PartialEq, Deserialize, Debug, ToSchema)]
 pub struct CallHolds {
     #[serde(
         deserialize_with = "vec_ts_tuple_de",
@@ -41,7 +51,7 @@ pub struct CallHolds {
     pub silent: Vec<Interval>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct SpeechRecognition {
     pub text: String,
     #[serde(
@@ -51,6 +61,8 @@ pub struct SpeechRecognition {
     #[schema(value_type = [f32; 2])]
     pub timestamps: Interval,
     pub speaker: ParticipantKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -59,7 +71,7 @@ pub struct Interval {
     pub end: f32,
 }
 
-#[derive(Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct PhraseTimestamps {
     #[serde(
         deserialize_with = "vec_ts_tuple_de",
@@ -75,6 +87,22 @@ pub struct PhraseTimestamps {
     pub employee: Vec<Interval>,
 }
 
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MetricsIntervals {
+    #[serde(
+        deserialize_with = "vec_ts_tuple_de",
+        serialize_with = "vec_ts_tuple_serialize"
+    )]
+    #[schema(value_type = Vec<[f32; 2]>)]
+    pub client_interruptions: Vec<Interval>,
+    #[serde(
+        deserialize_with = "vec_ts_tuple_de",
+        serialize_with = "vec_ts_tuple_serialize"
+    )]
+    #[schema(value_type = Vec<[f32; 2]>)]
+    pub silence_pauses: Vec<Interval>,
+}
+
 fn vec_ts_tuple_de<'de, D>(deserializer: D) -> Result<Vec<Interval>, D::Error>
 where
     D: Deserializer<'de>,
@@ -149,6 +177,7 @@ mod tests {
                 end: 1.0,
             },
             speaker: ParticipantKind::Client,
+            confidence: None,
         })
         .unwrap();
 
@@ -1,3 +1,5 @@
 pub mod auxiliary;
 pub mod db;
 pub mod entity;
+#[cfg(feature = "test")]
+pub mod test_helpers;
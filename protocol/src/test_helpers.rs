@@ -0,0 +1,61 @@
+//! Fixtures shared by the tests of the api-server and the worker
+
+use chrono::DateTime;
+use uuid::Uuid;
+
+use crate::{
+    db::{
+        metadata::CallMetadata,
+        task::{Task, TaskResultKind},
+    },
+    entity::ParticipantKind,
+};
+
+/// Metadata of a test call, the hash is derived from the call id so calls with distinct
+/// ids don't clash
+pub fn call_metadata(call_id: i64) -> CallMetadata {
+    CallMetadata {
+        metadata_id: Uuid::default(),
+        call_id,
+        performed_at: DateTime::default(),
+        uploaded_at: DateTime::default(),
+        file_hash: format!("test_hash_{call_id}"),
+        file_url: "s3://test.mp3".to_string(),
+        file_name: "test.mp3".to_string(),
+        duration: 100.0,
+        left_channel: ParticipantKind::Client,
+        right_channel: ParticipantKind::Employee,
+        client_name: "test_client".to_string(),
+        employee_name: "test_operator".to_string(),
+        inbound: true,
+        left_channel_url: None,
+        right_channel_url: None,
+    }
+}
+
+/// Inserts a processing task of a test call with a unique hash into the project
+pub async fn insert_task(project_id: Uuid, conn: &mut sqlx::PgConnection) -> Task {
+    let metadata = CallMetadata {
+        file_hash: Uuid::new_v4().to_string(),
+        ..call_metadata(42)
+    }
+    .insert(project_id, conn)
+    .await
+    .expect("failed to insert test call metadata");
+
+    Task {
+        id: Uuid::default(),
+        call_metadata_id: metadata.metadata_id,
+        status: TaskResultKind::Processing,
+        failed_reason: None,
+        failure_class: None,
+        failure_kind: None,
+        project_id,
+        created_by: None,
+        reprocessed_by: None,
+        attempts: 0,
+    }
+    .insert(conn)
+    .await
+    .expect("failed to insert test task")
+}
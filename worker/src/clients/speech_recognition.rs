@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use async_trait::async_trait;
 #[cfg(test)]
 use mockall::{automock, predicate::*};
@@ -5,23 +7,29 @@ use protocol::{
     db::metadata::CallMetadata,
     entity::{speech_recog::RecognitionData, ParticipantKind},
 };
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
-use tracing::error;
+use tracing::{debug, error};
 use url::Url;
 
-use crate::config::HttpClientConfig;
+use crate::config::{HttpClientConfig, PollingConfig, RecognitionMode};
 
 #[derive(Error, Debug)]
 pub enum SpeechRecognitionClientError {
     #[error("failed to deserialize response of the HTTP client: {0}")]
     De(#[source] reqwest::Error),
+    #[error("failed to deserialize recognition result: {0}")]
+    Recognition(#[source] serde_json::Error),
     #[error("failed to communicate in HTTP client: {0}")]
     Channel(#[source] reqwest::Error),
     #[error("server failed to perform request of HTTP client: {0}")]
     ResponseStatus(http::StatusCode),
     #[error("failed to parse URL: {0}")]
     BaseUrl(#[source] url::ParseError),
+    #[error("recognition job {0} failed: {1}")]
+    JobFailed(String, String),
+    #[error("recognition job {0} is not done in time")]
+    JobTimeout(String),
 }
 
 #[cfg_attr(test, automock)]
@@ -37,6 +45,8 @@ pub trait SpeechRecognitionClient {
 pub struct HttpSpeechRecognitionClient {
     client: reqwest::Client,
     base_url: Url,
+    mode: RecognitionMode,
+    speaker_labels: HashMap<String, ParticipantKind>,
 }
 
 impl HttpSpeechRecognitionClient {
@@ -46,44 +56,211 @@ impl HttpSpeechRecognitionClient {
         if let Some(timeout) = config.timeout {
             builder = builder.timeout(timeout);
         }
+        if let Some(max_idle) = config.pool.max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = config.pool.idle_timeout {
+            builder = builder.pool_idle_timeout(idle_timeout);
+        }
+        builder = builder.tcp_keepalive(config.pool.tcp_keepalive);
 
-        builder
+        let client = builder
             .build()
             .map_err(SpeechRecognitionClientError::Channel)?;
 
         let base_url = Url::parse(&config.url).map_err(SpeechRecognitionClientError::BaseUrl)?;
 
         Ok(Self {
-            client: reqwest::Client::new(),
+            client,
             base_url,
+            mode: config.mode.clone(),
+            speaker_labels: config.speaker_labels.clone(),
         })
     }
+
+    /// Replaces provider speaker labels, either strings or channel indices,
+    /// with the configured participants before deserializing the result
+    fn recognition_data(
+        &self,
+        mut result: serde_json::Value,
+    ) -> Result<RecognitionData, SpeechRecognitionClientError> {
+        let phrases = result
+            .get_mut("speech_recognition_result")
+            .and_then(|phrases| phrases.as_array_mut());
+        for phrase in phrases.into_iter().flatten() {
+            let Some(speaker) = phrase.get_mut("speaker") else {
+                continue;
+            };
+            let label = match speaker {
+                serde_json::Value::String(label) => label.clone(),
+                serde_json::Value::Number(index) => index.to_string(),
+                _ => continue,
+            };
+            if let Some(participant) = self.speaker_labels.get(&label) {
+                *speaker = serde_json::json!(participant);
+            }
+        }
+
+        serde_json::from_value(result).map_err(SpeechRecognitionClientError::Recognition)
+    }
+
+    async fn transcribe_sync(
+        &self,
+        request: &TranscribeRequest,
+    ) -> Result<RecognitionData, SpeechRecognitionClientError> {
+        let mut req_url = self.base_url.clone();
+        req_url.set_path("extract_info_s3/");
+
+        let res = self
+            .client
+            .post(req_url)
+            .json(request)
+            .send()
+            .await
+            .map_err(SpeechRecognitionClientError::Channel)?;
+
+        self.recognition_data(json_response(res).await?)
+    }
+
+    /// Submits a job to `jobs/`, polls `jobs/{id}` with backoff until it's done
+    /// and fetches the result from `jobs/{id}/result`
+    async fn transcribe_polling(
+        &self,
+        request: &TranscribeRequest,
+        config: &PollingConfig,
+    ) -> Result<RecognitionData, SpeechRecognitionClientError> {
+        let mut req_url = self.base_url.clone();
+        req_url.set_path("jobs/");
+
+        let res = self
+            .client
+            .post(req_url)
+            .json(request)
+            .send()
+            .await
+            .map_err(SpeechRecognitionClientError::Channel)?;
+        let JobSubmitted { job_id } = json_response(res).await?;
+
+        let deadline = tokio::time::Instant::now() + config.max_wait;
+        let mut interval = config.interval;
+        loop {
+            if tokio::time::Instant::now() + interval > deadline {
+                return Err(SpeechRecognitionClientError::JobTimeout(job_id));
+            }
+            tokio::time::sleep(interval).await;
+            interval = (interval * 2).min(config.max_interval);
+
+            let mut req_url = self.base_url.clone();
+            req_url.set_path(&format!("jobs/{job_id}"));
+            let res = self
+                .client
+                .get(req_url)
+                .send()
+                .await
+                .map_err(SpeechRecognitionClientError::Channel)?;
+
+            match json_response(res).await? {
+                JobStatus::Pending => debug!("recognition job {job_id} is pending"),
+                JobStatus::Done => break,
+                JobStatus::Failed { error } => {
+                    return Err(SpeechRecognitionClientError::JobFailed(
+                        job_id,
+                        error.unwrap_or_default(),
+                    ))
+                }
+            }
+        }
+
+        let mut req_url = self.base_url.clone();
+        req_url.set_path(&format!("jobs/{job_id}/result"));
+        let res = self
+            .client
+            .get(req_url)
+            .send()
+            .await
+            .map_err(SpeechRecognitionClientError::Channel)?;
+
+        self.recognition_data(json_response(res).await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JobSubmitted {
+    job_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum JobStatus {
+    Pending,
+    Done,
+    Failed {
+        #[serde(default)]
+        error: Option<String>,
+    },
+}
+
+async fn json_response<T: DeserializeOwned>(
+    res: reqwest::Response,
+) -> Result<T, SpeechRecognitionClientError> {
+    match res.status() {
+        reqwest::StatusCode::OK => res
+            .json::<T>()
+            .await
+            .map_err(SpeechRecognitionClientError::De),
+        otherwise => Err(SpeechRecognitionClientError::ResponseStatus(otherwise)),
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct TranscribeRequest {
     file_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_urls: Option<ChannelUrls>,
     operator_channel: String,
     tasks: Vec<String>,
 }
 
-impl From<&CallMetadata> for TranscribeRequest {
-    fn from(metadata: &CallMetadata) -> Self {
+/// Separate per-channel recordings, sent alongside the mixed file to improve diarization
+#[derive(Clone, Debug, Serialize)]
+struct ChannelUrls {
+    left: String,
+    right: String,
+}
+
+impl TranscribeRequest {
+    pub fn new(metadata: &CallMetadata, emotion_recognition: bool) -> Self {
         let operator_channel = if metadata.left_channel == ParticipantKind::Employee {
             "L".to_string()
         } else {
             "R".to_string()
         };
 
+        let mut tasks = vec!["speech_recognition".to_string()];
+        if emotion_recognition {
+            tasks.push("emotion_recognition".to_string());
+        }
+
+        let channel_urls = match (&metadata.left_channel_url, &metadata.right_channel_url) {
+            (Some(left), Some(right)) => Some(ChannelUrls {
+                left: left.clone(),
+                right: right.clone(),
+            }),
+            _ => None,
+        };
+
         TranscribeRequest {
             file_url: metadata.file_url.clone(),
+            channel_urls,
             operator_channel,
-            tasks: vec![
-                "speech_recognition".to_string(),
-                "emotion_recognition".to_string(),
-            ],
+            tasks,
         }
     }
+
+    #[cfg(test)]
+    pub fn tasks(&self) -> &[String] {
+        &self.tasks
+    }
 }
 
 #[async_trait]
@@ -92,23 +269,215 @@ impl SpeechRecognitionClient for HttpSpeechRecognitionClient {
         &self,
         request: TranscribeRequest,
     ) -> Result<RecognitionData, SpeechRecognitionClientError> {
-        let mut req_url = self.base_url.clone();
-        req_url.set_path("extract_info_s3/");
+        match &self.mode {
+            RecognitionMode::Sync => self.transcribe_sync(&request).await,
+            RecognitionMode::Polling(config) => self.transcribe_polling(&request, config).await,
+        }
+    }
+}
 
-        let res = self
-            .client
-            .post(req_url)
-            .json(&request)
-            .send()
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    use axum::{extract::State, routing::get, routing::post, Json, Router};
+    use protocol::entity::speech_recog::{CallHolds, PhraseTimestamps};
+
+    use crate::config::HttpPoolConfig;
+
+    use super::*;
+
+    fn recog_data() -> RecognitionData {
+        RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn polling_transcribe() {
+        let status_checks = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route(
+                "/jobs/",
+                post(|| async { Json(serde_json::json!({"job_id": "42"})) }),
+            )
+            .route(
+                "/jobs/42",
+                get(|State(checks): State<Arc<AtomicUsize>>| async move {
+                    let status = if checks.fetch_add(1, Ordering::SeqCst) < 2 {
+                        "pending"
+                    } else {
+                        "done"
+                    };
+                    Json(serde_json::json!({ "status": status }))
+                }),
+            )
+            .route("/jobs/42/result", get(|| async { Json(recog_data()) }))
+            .with_state(status_checks.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let polling = |max_wait| {
+            RecognitionMode::Polling(PollingConfig {
+                interval: Duration::from_millis(5),
+                max_interval: Duration::from_millis(10),
+                max_wait,
+            })
+        };
+        let client = |mode| {
+            HttpSpeechRecognitionClient::new(&HttpClientConfig {
+                url: format!("http://{addr}"),
+                timeout: None,
+                pool: HttpPoolConfig::default(),
+                speaker_labels: HashMap::new(),
+                mode,
+            })
+            .unwrap()
+        };
+
+        let result = client(polling(Duration::from_secs(5)))
+            .transcribe(TranscribeRequest::default())
             .await
-            .map_err(SpeechRecognitionClientError::Channel)?;
+            .expect("failed to transcribe");
+        assert_eq!(result, recog_data());
+        assert_eq!(status_checks.load(Ordering::SeqCst), 3);
+
+        let err = client(polling(Duration::from_millis(1)))
+            .transcribe(TranscribeRequest::default())
+            .await
+            .expect_err("job is expected to time out");
+        assert!(matches!(err, SpeechRecognitionClientError::JobTimeout(id) if id == "42"));
+    }
+
+    #[tokio::test]
+    async fn pooled_client_transcribe() {
+        let router =
+            Router::new().route("/extract_info_s3/", post(|| async { Json(recog_data()) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let client = HttpSpeechRecognitionClient::new(&HttpClientConfig {
+            url: format!("http://{addr}"),
+            timeout: Some(Duration::from_secs(5)),
+            pool: HttpPoolConfig {
+                max_idle_per_host: Some(2),
+                idle_timeout: Some(Duration::from_secs(30)),
+                tcp_keepalive: Some(Duration::from_secs(15)),
+            },
+            speaker_labels: HashMap::new(),
+            mode: RecognitionMode::Sync,
+        })
+        .expect("failed to build pooled client");
 
-        match res.status() {
-            reqwest::StatusCode::OK => res
-                .json::<RecognitionData>()
+        // the second request goes over the kept idle connection
+        for _ in 0..2 {
+            let result = client
+                .transcribe(TranscribeRequest::default())
                 .await
-                .map_err(SpeechRecognitionClientError::De),
-            otherwise => Err(SpeechRecognitionClientError::ResponseStatus(otherwise)),
+                .expect("failed to transcribe");
+            assert_eq!(result, recog_data());
         }
     }
+
+    #[test]
+    fn numeric_speaker_labels() {
+        let client = HttpSpeechRecognitionClient::new(&HttpClientConfig {
+            url: "http://localhost".to_string(),
+            timeout: None,
+            pool: HttpPoolConfig::default(),
+            speaker_labels: HashMap::from([
+                ("0".to_string(), ParticipantKind::Employee),
+                ("1".to_string(), ParticipantKind::Client),
+            ]),
+            mode: RecognitionMode::Sync,
+        })
+        .unwrap();
+        let result = |speaker| {
+            serde_json::json!({
+                "call_holds": {"music": [], "silent": []},
+                "emotion_recognition_result": [],
+                "phrase_timestamps": {"client": [], "employee": []},
+                "speech_recognition_result": [
+                    {"text": "hello", "timestamps": [0.0, 1.0], "speaker": 0},
+                    {"text": "hi", "timestamps": [1.0, 2.0], "speaker": speaker},
+                ],
+            })
+        };
+
+        let data = client
+            .recognition_data(result(serde_json::json!(1)))
+            .expect("failed to map numeric speaker labels");
+        let speakers: Vec<_> = data
+            .speech_recognition_result
+            .iter()
+            .map(|phrase| phrase.speaker)
+            .collect();
+        assert_eq!(
+            speakers,
+            vec![ParticipantKind::Employee, ParticipantKind::Client]
+        );
+
+        let data = client
+            .recognition_data(result(serde_json::json!("client")))
+            .expect("failed to keep participant speaker label");
+        assert_eq!(
+            data.speech_recognition_result[1].speaker,
+            ParticipantKind::Client
+        );
+
+        let err = client
+            .recognition_data(result(serde_json::json!(2)))
+            .expect_err("unmapped speaker label");
+        assert!(matches!(err, SpeechRecognitionClientError::Recognition(_)));
+    }
+
+    #[test]
+    fn transcribe_request_channel_urls() {
+        let mut metadata: CallMetadata = serde_json::from_value(serde_json::json!({
+            "call_id": 1,
+            "performed_at": 1_700_000_000_000i64,
+            "uploaded_at": 1_700_000_000_000i64,
+            "file_hash": "hash",
+            "file_url": "s3://bucket/mixed.mp3",
+            "file_name": "mixed.mp3",
+            "duration": 60.0,
+            "left_channel": "employee",
+            "right_channel": "client",
+            "client_name": "client",
+            "employee_name": "employee",
+            "inbound": true,
+        }))
+        .expect("failed to deserialize metadata");
+        assert_eq!(metadata.left_channel_url, None);
+
+        let request = serde_json::to_value(TranscribeRequest::new(&metadata, false)).unwrap();
+        assert_eq!(request["file_url"], "s3://bucket/mixed.mp3");
+        assert!(request.get("channel_urls").is_none());
+
+        metadata.left_channel_url = Some("s3://bucket/left.mp3".to_string());
+        metadata.right_channel_url = Some("s3://bucket/right.mp3".to_string());
+        let serialized = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(serialized["left_channel_url"], "s3://bucket/left.mp3");
+        assert_eq!(serialized["right_channel_url"], "s3://bucket/right.mp3");
+
+        let request = serde_json::to_value(TranscribeRequest::new(&metadata, false)).unwrap();
+        assert_eq!(request["file_url"], "s3://bucket/mixed.mp3");
+        assert_eq!(
+            request["channel_urls"],
+            serde_json::json!({
+                "left": "s3://bucket/left.mp3",
+                "right": "s3://bucket/right.mp3",
+            })
+        );
+    }
 }
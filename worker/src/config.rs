@@ -1,6 +1,9 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
 
-use serde::Deserialize;
+use protocol::entity::{
+    settings_metrics::ScoringRules, speech_recog::EmotionKind, ParticipantKind,
+};
+use serde::{Deserialize, Deserializer};
 
 #[derive(Deserialize, Debug, Clone)]
 pub(crate) struct Config {
@@ -9,6 +12,154 @@ pub(crate) struct Config {
     pub http: HttpConfig,
     pub index_path: String,
     pub amqp_prefetch_count: u16, // in-flight count
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub asr: AsrTasksConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AsrTasksConfig {
+    // how long the per-project decision is reused, preferences changes apply after it expires
+    #[serde(with = "humantime_serde", default = "default_preferences_cache_ttl")]
+    pub preferences_cache_ttl: Duration,
+}
+
+impl Default for AsrTasksConfig {
+    fn default() -> Self {
+        Self {
+            preferences_cache_ttl: default_preferences_cache_ttl(),
+        }
+    }
+}
+
+fn default_preferences_cache_ttl() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MetricsConfig {
+    // sliding window size (in frames) for emotion majority vote, disabled when unset
+    pub emotion_smoothing_window: Option<usize>,
+    // recognized emotions are stored under the mapped category, unmapped ones as is
+    #[serde(default)]
+    pub emotion_mapping: HashMap<EmotionKind, EmotionKind>,
+    #[serde(flatten)]
+    pub scoring: ScoringRules,
+    // minimal overlap in seconds for an employee phrase to count as interruption
+    #[serde(default = "default_overlap_duration_eps")]
+    pub overlap_duration_eps: f32,
+    // segments shorter than this (in seconds) are dropped as noise, disabled when unset
+    pub min_segment_duration: Option<f32>,
+    // transcribed segments with ASR confidence below this are dropped before indexing,
+    // segments without confidence are always kept, disabled when unset
+    pub min_confidence: Option<f32>,
+    // speech ratios are computed against the call duration without holds, speech during
+    // holds isn't counted then
+    #[serde(default)]
+    pub ratios_by_active_duration: bool,
+    // numeric value of every emotion category, the call sentiment is their average over the
+    // recognized (mapped and smoothed) emotions, all categories have to be listed
+    #[serde(
+        default = "default_emotion_weights",
+        deserialize_with = "complete_emotion_weights"
+    )]
+    pub emotion_weights: HashMap<EmotionKind, f32>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            emotion_smoothing_window: None,
+            emotion_mapping: HashMap::new(),
+            scoring: ScoringRules::default(),
+            overlap_duration_eps: default_overlap_duration_eps(),
+            min_segment_duration: None,
+            min_confidence: None,
+            ratios_by_active_duration: false,
+            emotion_weights: default_emotion_weights(),
+        }
+    }
+}
+
+fn default_emotion_weights() -> HashMap<EmotionKind, f32> {
+    HashMap::from([
+        (EmotionKind::Positive, 1.0),
+        (EmotionKind::Neutral, 0.0),
+        (EmotionKind::Other, 0.0),
+        (EmotionKind::Sad, -1.0),
+        (EmotionKind::Angry, -1.0),
+    ])
+}
+
+fn complete_emotion_weights<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<EmotionKind, f32>, D::Error> {
+    let weights = HashMap::<EmotionKind, f32>::deserialize(deserializer)?;
+    match EmotionKind::ALL
+        .iter()
+        .find(|emotion| !weights.contains_key(emotion))
+    {
+        Some(missing) => Err(serde::de::Error::custom(format!(
+            "emotion weight of {missing:?} is missing"
+        ))),
+        None => Ok(weights),
+    }
+}
+
+fn default_overlap_duration_eps() -> f32 {
+    crate::domain::audio_metrics::OVERLAP_DURATION_EPS
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SearchConfig {
+    #[serde(default = "default_boost")]
+    pub employee_transcript_boost: f32,
+    #[serde(default = "default_boost")]
+    pub client_transcript_boost: f32,
+    // transcript fields longer than this (in chars) are truncated before indexing,
+    // the stored payload is kept whole
+    #[serde(default)]
+    pub max_transcript_chars: Option<usize>,
+    // stems transcripts and searched phrases alike, changing it requires reindexing
+    #[serde(default)]
+    pub stemmer_language: Option<tantivy::tokenizer::Language>,
+    #[serde(default)]
+    pub reload_policy: ReloadPolicy,
+    // a transcript is indexed before its keywords are matched, so it stays searchable
+    // when the task fails later on, otherwise only transcripts of processed tasks are indexed
+    #[serde(default)]
+    pub keep_failed_transcripts: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReloadPolicy {
+    /// The reader is reloaded right after every commit, indexed calls are searchable at once
+    #[default]
+    Manual,
+    /// The reader picks up commits in the background shortly after they land,
+    /// indexing doesn't wait for the reload
+    OnCommitWithDelay,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            employee_transcript_boost: default_boost(),
+            client_transcript_boost: default_boost(),
+            max_transcript_chars: None,
+            stemmer_language: None,
+            reload_policy: ReloadPolicy::default(),
+            keep_failed_transcripts: false,
+        }
+    }
+}
+
+fn default_boost() -> f32 {
+    1.0
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -16,6 +167,61 @@ pub struct HttpClientConfig {
     pub url: String,
     #[serde(with = "humantime_serde")]
     pub timeout: Option<Duration>,
+    #[serde(default)]
+    pub pool: HttpPoolConfig,
+    // provider speaker labels (e.g. `0`, `spk1`) mapped to participants,
+    // labels missing here must already be participant kinds
+    #[serde(default)]
+    pub speaker_labels: HashMap<String, ParticipantKind>,
+    #[serde(default)]
+    pub mode: RecognitionMode,
+}
+
+/// Connection reuse of an HTTP client, reqwest defaults apply to unset values
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HttpPoolConfig {
+    /// Idle connections kept open per host
+    pub max_idle_per_host: Option<usize>,
+    /// Idle connections are closed after this long
+    #[serde(with = "humantime_serde", default)]
+    pub idle_timeout: Option<Duration>,
+    /// TCP keep-alive interval, disabled when unset
+    #[serde(with = "humantime_serde", default)]
+    pub tcp_keepalive: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecognitionMode {
+    /// A single request returns the recognition result
+    #[default]
+    Sync,
+    /// The request returns a job id, the job status is polled until the result is ready
+    Polling(PollingConfig),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PollingConfig {
+    // delay before the first status check, doubled after every pending status
+    #[serde(with = "humantime_serde", default = "default_poll_interval")]
+    pub interval: Duration,
+    #[serde(with = "humantime_serde", default = "default_max_poll_interval")]
+    pub max_interval: Duration,
+    // total time to wait for the job before giving up
+    #[serde(with = "humantime_serde", default = "default_max_poll_wait")]
+    pub max_wait: Duration,
+}
+
+fn default_poll_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_poll_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_max_poll_wait() -> Duration {
+    Duration::from_secs(60 * 60)
 }
 
 #[derive(Clone, Debug, serde::Deserialize)]
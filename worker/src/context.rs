@@ -1,18 +1,24 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use sqlx::pool::PoolConnection;
 use sqlx::{PgPool, Postgres};
 
 use crate::clients::speech_recognition::{HttpSpeechRecognitionClient, SpeechRecognitionClient};
-use crate::config::Config;
+use crate::config::{Config, MetricsConfig, SearchConfig};
+use crate::domain::asr_tasks::AsrTasks;
 use crate::indexer::{Indexer, TantivyIndexer};
 
 #[async_trait]
 pub trait Context {
-    type Indexer: Indexer;
+    type Indexer: Indexer + Sync;
     type SpeechRecognitionClient: SpeechRecognitionClient;
 
     fn indexer(&self) -> &Self::Indexer;
     fn speech_recognition(&self) -> &Self::SpeechRecognitionClient;
+    fn metrics_config(&self) -> &MetricsConfig;
+    fn search_config(&self) -> &SearchConfig;
+    fn asr_tasks(&self) -> &AsrTasks;
     async fn get_db_conn(&self) -> anyhow::Result<PoolConnection<Postgres>>;
 }
 
@@ -21,14 +27,20 @@ pub struct AppContext {
     db: PgPool,
     indexer: TantivyIndexer,
     speech_recognition: HttpSpeechRecognitionClient,
+    metrics_config: MetricsConfig,
+    search_config: SearchConfig,
+    asr_tasks: Arc<AsrTasks>,
 }
 
 impl AppContext {
     pub fn new(config: &Config, pool: PgPool) -> anyhow::Result<Self> {
         Ok(Self {
             db: pool,
-            indexer: TantivyIndexer::new(&config.index_path)?,
+            indexer: TantivyIndexer::new(&config.index_path, &config.search)?,
             speech_recognition: HttpSpeechRecognitionClient::new(&config.speech_recognition)?,
+            metrics_config: config.metrics.clone(),
+            search_config: config.search.clone(),
+            asr_tasks: Arc::new(AsrTasks::new(&config.asr)),
         })
     }
 }
@@ -46,6 +58,18 @@ impl Context for AppContext {
         &self.speech_recognition
     }
 
+    fn metrics_config(&self) -> &MetricsConfig {
+        &self.metrics_config
+    }
+
+    fn search_config(&self) -> &SearchConfig {
+        &self.search_config
+    }
+
+    fn asr_tasks(&self) -> &AsrTasks {
+        &self.asr_tasks
+    }
+
     async fn get_db_conn(&self) -> anyhow::Result<PoolConnection<Postgres>> {
         let conn = self.db.acquire().await?;
         Ok(conn)
@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use protocol::db::settings::ProjectPreferences;
+use uuid::Uuid;
+
+use crate::config::AsrTasksConfig;
+
+/// Decides which recognition tasks are requested for a project, the decision is cached
+/// per project as it depends on the project preferences only
+pub struct AsrTasks {
+    ttl: Duration,
+    emotions_by_project: Mutex<HashMap<Uuid, (Instant, bool)>>,
+}
+
+impl AsrTasks {
+    pub fn new(config: &AsrTasksConfig) -> Self {
+        Self {
+            ttl: config.preferences_cache_ttl,
+            emotions_by_project: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Projects without preferences don't have their emotions recognized
+    pub async fn emotion_recognition(
+        &self,
+        project_id: Uuid,
+        conn: &mut sqlx::PgConnection,
+    ) -> sqlx::Result<bool> {
+        let cached = self
+            .emotions_by_project
+            .lock()
+            .expect("poisoned asr tasks cache")
+            .get(&project_id)
+            .filter(|(computed_at, _)| computed_at.elapsed() < self.ttl)
+            .map(|(_, emotion_recognition)| *emotion_recognition);
+        if let Some(emotion_recognition) = cached {
+            return Ok(emotion_recognition);
+        }
+
+        let emotion_recognition = ProjectPreferences::fetch_by_project_id(project_id, conn)
+            .await?
+            .is_some_and(|preferences| preferences.emotion_recognition);
+        self.emotions_by_project
+            .lock()
+            .expect("poisoned asr tasks cache")
+            .insert(project_id, (Instant::now(), emotion_recognition));
+
+        Ok(emotion_recognition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn emotion_recognition_by_preferences(pool: sqlx::PgPool) {
+        let mut conn = pool.acquire().await.unwrap();
+        let project_id = Uuid::new_v4();
+
+        let asr_tasks = AsrTasks::new(&AsrTasksConfig::default());
+        assert!(!asr_tasks
+            .emotion_recognition(project_id, &mut conn)
+            .await
+            .unwrap());
+
+        // the cached decision is kept until it expires
+        ProjectPreferences::upsert(
+            ProjectPreferences {
+                project_id,
+                emotion_recognition: true,
+                ..Default::default()
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        assert!(!asr_tasks
+            .emotion_recognition(project_id, &mut conn)
+            .await
+            .unwrap());
+
+        let asr_tasks = AsrTasks::new(&AsrTasksConfig {
+            preferences_cache_ttl: Duration::ZERO,
+        });
+        assert!(asr_tasks
+            .emotion_recognition(project_id, &mut conn)
+            .await
+            .unwrap());
+    }
+}
@@ -1,22 +1,27 @@
 use std::collections::HashMap;
 
 use protocol::{
-    db::metrics::CallMetrics,
+    db::metrics::{CallMetrics, CallMetricsExtras, TalkBalance},
     entity::{
-        speech_recog::{CallHolds, EmotionKind, Interval, RecognitionData, SpeechRecognition},
+        speech_recog::{
+            CallHolds, EmotionKind, Interval, MetricsIntervals, RecognitionData, SpeechRecognition,
+        },
         ParticipantKind,
     },
 };
+use sqlx::types::Json;
 use uuid::Uuid;
 
-const OVERLAP_DURATION_EPS: f32 = 1.0;
+use crate::config::MetricsConfig;
+
+pub(crate) const OVERLAP_DURATION_EPS: f32 = 1.0;
 const PAUSE_DURATION: f32 = 5.0;
 
 fn intervals_overlap(first_interval: &Interval, seconds_interval: &Interval) -> bool {
     first_interval.start < seconds_interval.end && seconds_interval.start < first_interval.end
 }
 
-fn is_interruption(employee_interval: &Interval, client_interval: &Interval) -> bool {
+fn is_interruption(employee_interval: &Interval, client_interval: &Interval, eps: f32) -> bool {
     let overlap_start = employee_interval.start.max(client_interval.start);
     let overlap_end = employee_interval.end.min(client_interval.end);
 
@@ -24,26 +29,39 @@ fn is_interruption(employee_interval: &Interval, client_interval: &Interval) ->
 
     employee_interval.start > client_interval.start
         && employee_interval.start < client_interval.end
-        && overlap_duration >= OVERLAP_DURATION_EPS
+        && overlap_duration >= eps
 }
 
-fn find_interruptions(
+fn find_interruption_intervals(
     employee_intervals: &Vec<Interval>,
     client_intervals: &Vec<Interval>,
-) -> (f32, i32) {
-    let mut interruptions_count: i32 = 0;
-    let mut total_interruption_time = 0.0;
+    eps: f32,
+) -> Vec<Interval> {
+    let mut interruptions = vec![];
 
     for employee_interval in employee_intervals {
         for client_interval in client_intervals {
-            if is_interruption(employee_interval, client_interval) {
-                interruptions_count += 1;
-                total_interruption_time += employee_interval.end - employee_interval.start;
+            if is_interruption(employee_interval, client_interval, eps) {
+                interruptions.push(employee_interval.clone());
                 break;
             }
         }
     }
-    (total_interruption_time, interruptions_count)
+    interruptions
+}
+
+fn find_interruptions(
+    employee_intervals: &Vec<Interval>,
+    client_intervals: &Vec<Interval>,
+    eps: f32,
+) -> (f32, i32) {
+    let interruptions = find_interruption_intervals(employee_intervals, client_intervals, eps);
+    let total_interruption_time = interruptions
+        .iter()
+        .map(|interval| interval.end - interval.start)
+        .sum();
+
+    (total_interruption_time, interruptions.len() as i32)
 }
 
 fn time_to_answer(employee_intervals: &[Interval]) -> Option<f32> {
@@ -68,13 +86,23 @@ fn speech_percentage(total_speech: f32, total_call_duration: f32) -> f32 {
     (total_speech / total_call_duration) * 100.0
 }
 
-fn count_pauses(
+/// Share of the call spent speaking, the speech intervals are merged so it can't exceed 100%
+fn call_speech_share(total_speech: f32, call_duration: f32) -> f32 {
+    let share = speech_percentage(total_speech, call_duration);
+    debug_assert!(
+        (0.0..=100.0 + 1e-3).contains(&share),
+        "speech share {share} is out of 0..=100"
+    );
+    share.min(100.0)
+}
+
+fn find_pause_intervals(
     employee_intervals: &[Interval],
     client_intervals: &[Interval],
     holds: &CallHolds,
-) -> (i32, f32) {
+) -> Vec<Interval> {
     if employee_intervals.is_empty() || client_intervals.is_empty() {
-        return (0, 0.0);
+        return vec![];
     }
 
     let mut hold_intervals = vec![];
@@ -102,8 +130,7 @@ fn count_pauses(
     intervals.sort_by(|a, b| a.1.start.partial_cmp(&b.1.start).unwrap());
 
     let mut previous_end: Option<f32> = None;
-    let mut pause_count = 0;
-    let mut pause_sum = 0.0;
+    let mut pauses = vec![];
     for interval in intervals {
         if let Some(ref end) = previous_end {
             if interval.0 == ParticipantKind::Employee
@@ -113,8 +140,10 @@ fn count_pauses(
                     .iter()
                     .any(|hold| intervals_overlap(hold, interval.1))
             {
-                pause_count += 1;
-                pause_sum += interval.1.start - *end;
+                pauses.push(Interval {
+                    start: *end,
+                    end: interval.1.start,
+                });
             }
         }
         if interval.0 == ParticipantKind::Employee {
@@ -124,7 +153,143 @@ fn count_pauses(
         }
     }
 
-    (pause_count, pause_sum)
+    pauses
+}
+
+fn merge_intervals(intervals: &[Interval]) -> Vec<Interval> {
+    let mut sorted = intervals.to_vec();
+    sorted.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+
+    let mut merged: Vec<Interval> = vec![];
+    for interval in sorted {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+            _ => merged.push(interval),
+        }
+    }
+    merged
+}
+
+fn overlap_duration(intervals: &[Interval], window: &Interval) -> f32 {
+    intervals
+        .iter()
+        .map(|interval| (interval.end.min(window.end) - interval.start.max(window.start)).max(0.0))
+        .sum()
+}
+
+fn talk_balance_by_thirds(
+    employee_intervals: &[Interval],
+    client_intervals: &[Interval],
+    call_duration: f32,
+) -> Vec<TalkBalance> {
+    if call_duration == 0.0 {
+        return vec![];
+    }
+
+    let employee_intervals = merge_intervals(employee_intervals);
+    let client_intervals = merge_intervals(client_intervals);
+    let third_duration = call_duration / 3.0;
+
+    (0..3)
+        .map(|third| {
+            let window = Interval {
+                start: third_duration * third as f32,
+                end: third_duration * (third + 1) as f32,
+            };
+            TalkBalance {
+                employee_speech_ratio: speech_percentage(
+                    overlap_duration(&employee_intervals, &window),
+                    third_duration,
+                ),
+                client_speech_ratio: speech_percentage(
+                    overlap_duration(&client_intervals, &window),
+                    third_duration,
+                ),
+            }
+        })
+        .collect()
+}
+
+fn active_call_duration(call_duration: f32, holds: &CallHolds) -> f32 {
+    let holds: Vec<Interval> = holds.music.iter().chain(&holds.silent).cloned().collect();
+    let call = Interval {
+        start: 0.0,
+        end: call_duration,
+    };
+
+    (call_duration - overlap_duration(&merge_intervals(&holds), &call)).max(0.0)
+}
+
+/// Duration of the merged speech intervals which falls outside of the holds
+fn speech_outside_holds(speech: &[Interval], holds: &CallHolds) -> f32 {
+    let holds: Vec<Interval> = holds.music.iter().chain(&holds.silent).cloned().collect();
+    let during_holds: f32 = merge_intervals(&holds)
+        .iter()
+        .map(|hold| overlap_duration(speech, hold))
+        .sum();
+
+    (total_speech_duration(speech) - during_holds).max(0.0)
+}
+
+fn dead_air_ratio(
+    employee_intervals: &[Interval],
+    client_intervals: &[Interval],
+    holds: &CallHolds,
+    call_duration: f32,
+) -> f32 {
+    let covered: Vec<Interval> = employee_intervals
+        .iter()
+        .chain(client_intervals)
+        .chain(&holds.music)
+        .chain(&holds.silent)
+        .cloned()
+        .collect();
+    let call = Interval {
+        start: 0.0,
+        end: call_duration,
+    };
+    let dead_air = (call_duration - overlap_duration(&merge_intervals(&covered), &call)).max(0.0);
+
+    speech_percentage(dead_air, call_duration)
+}
+
+const SPEECH_RATIO_WINDOW: f32 = 60.0;
+
+fn speech_ratio_by_minute(
+    employee_intervals: &[Interval],
+    client_intervals: &[Interval],
+    call_duration: f32,
+) -> Vec<f32> {
+    let employee_intervals = merge_intervals(employee_intervals);
+    let client_intervals = merge_intervals(client_intervals);
+    let windows_count = (call_duration / SPEECH_RATIO_WINDOW).ceil() as usize;
+
+    (0..windows_count)
+        .map(|minute| {
+            let window = Interval {
+                start: SPEECH_RATIO_WINDOW * minute as f32,
+                end: (SPEECH_RATIO_WINDOW * (minute + 1) as f32).min(call_duration),
+            };
+            speech_percentage(
+                overlap_duration(&employee_intervals, &window),
+                overlap_duration(&client_intervals, &window),
+            )
+        })
+        .collect()
+}
+
+fn count_pauses(
+    employee_intervals: &[Interval],
+    client_intervals: &[Interval],
+    holds: &CallHolds,
+) -> (i32, f32) {
+    let pauses = find_pause_intervals(employee_intervals, client_intervals, holds);
+    let pause_sum = pauses
+        .iter()
+        .map(|interval| interval.end - interval.start)
+        .sum();
+
+    (pauses.len() as i32, pause_sum)
 }
 
 fn calculate_words_per_minute(
@@ -144,6 +309,32 @@ fn calculate_words_per_minute(
     total_words as f32 / speech_time_min
 }
 
+fn avg_segment_len(transcriptions: &[SpeechRecognition], speaker: ParticipantKind) -> f32 {
+    let durations: Vec<f32> = transcriptions
+        .iter()
+        .filter(|recog| recog.speaker == speaker)
+        .map(|recog| recog.timestamps.end - recog.timestamps.start)
+        .collect();
+    if durations.is_empty() {
+        return 0.0;
+    }
+
+    durations.iter().sum::<f32>() / durations.len() as f32
+}
+
+/// Segments ending with a question mark are counted as questions
+fn question_ratio(transcriptions: &[SpeechRecognition], speaker: ParticipantKind) -> f32 {
+    let (questions, total) = transcriptions
+        .iter()
+        .filter(|recog| recog.speaker == speaker)
+        .fold((0, 0), |(questions, total), recog| {
+            let is_question = recog.text.trim_end().ends_with('?');
+            (questions + is_question as usize, total + 1)
+        });
+
+    speech_percentage(questions as f32, total as f32)
+}
+
 fn call_emotional_mode(emotions: &Vec<EmotionKind>) -> Option<EmotionKind> {
     let mut occurrence: HashMap<EmotionKind, i32> = HashMap::new();
 
@@ -157,28 +348,134 @@ fn call_emotional_mode(emotions: &Vec<EmotionKind>) -> Option<EmotionKind> {
         .map(|(emotion, _)| emotion)
 }
 
-pub fn process_metrics(recog_data: &RecognitionData) -> CallMetrics {
-    let (silence_pause_count, total_employee_silence) = count_pauses(
+fn smooth_emotions(emotions: &[EmotionKind], window: usize) -> Vec<EmotionKind> {
+    if window <= 1 {
+        return emotions.to_vec();
+    }
+
+    let half = window / 2;
+    (0..emotions.len())
+        .map(|idx| {
+            let frame = &emotions[idx.saturating_sub(half)..(idx + half + 1).min(emotions.len())];
+            let mut occurrence: HashMap<EmotionKind, usize> = HashMap::new();
+            for emotion in frame {
+                *occurrence.entry(*emotion).or_insert(0) += 1;
+            }
+            let max_count = occurrence.values().copied().max().unwrap_or(0);
+
+            // on a tie keep the current frame, otherwise the earliest leader in the window
+            if occurrence[&emotions[idx]] == max_count {
+                emotions[idx]
+            } else {
+                *frame
+                    .iter()
+                    .find(|emotion| occurrence[*emotion] == max_count)
+                    .unwrap()
+            }
+        })
+        .collect()
+}
+
+fn drop_short_segments(intervals: &[Interval], min_duration: Option<f32>) -> Vec<Interval> {
+    let min_duration = min_duration.unwrap_or(0.0);
+    intervals
+        .iter()
+        .filter(|interval| interval.end - interval.start >= min_duration)
+        .cloned()
+        .collect()
+}
+
+pub struct EmotionModes {
+    pub mode: Option<EmotionKind>,
+    pub start_mode: Option<EmotionKind>,
+    pub end_mode: Option<EmotionKind>,
+    pub sentiment: f32,
+}
+
+fn call_sentiment(emotions: &[EmotionKind], weights: &HashMap<EmotionKind, f32>) -> f32 {
+    if emotions.is_empty() {
+        return 0.0;
+    }
+
+    let total: f32 = emotions
+        .iter()
+        .map(|emotion| weights.get(emotion).copied().unwrap_or(0.0))
+        .sum();
+    total / emotions.len() as f32
+}
+
+pub fn process_emotions(recog_data: &RecognitionData, config: &MetricsConfig) -> EmotionModes {
+    let emotions: Vec<_> = recog_data
+        .emotion_recognition_result
+        .iter()
+        .map(|emotion| *config.emotion_mapping.get(emotion).unwrap_or(emotion))
+        .collect();
+    let emotions = match config.emotion_smoothing_window {
+        Some(window) => smooth_emotions(&emotions, window),
+        None => emotions,
+    };
+
+    EmotionModes {
+        mode: call_emotional_mode(&emotions),
+        start_mode: emotions.first().cloned(),
+        end_mode: emotions.last().cloned(),
+        sentiment: call_sentiment(&emotions, &config.emotion_weights),
+    }
+}
+
+pub fn drop_low_confidence_segments(recog_data: &mut RecognitionData, min_confidence: Option<f32>) {
+    let Some(min_confidence) = min_confidence else {
+        return;
+    };
+    recog_data.speech_recognition_result.retain(|recog| {
+        recog
+            .confidence
+            .is_none_or(|confidence| confidence >= min_confidence)
+    });
+}
+
+pub fn process_metrics(recog_data: &RecognitionData, config: &MetricsConfig) -> CallMetrics {
+    let employee_intervals = drop_short_segments(
         &recog_data.phrase_timestamps.employee,
+        config.min_segment_duration,
+    );
+    let client_intervals = drop_short_segments(
         &recog_data.phrase_timestamps.client,
+        config.min_segment_duration,
+    );
+    let min_duration = config.min_segment_duration.unwrap_or(0.0);
+    let transcriptions: Vec<SpeechRecognition> = recog_data
+        .speech_recognition_result
+        .iter()
+        .filter(|recog| recog.timestamps.end - recog.timestamps.start >= min_duration)
+        .cloned()
+        .collect();
+
+    let (silence_pause_count, total_employee_silence) = count_pauses(
+        &employee_intervals,
+        &client_intervals,
         &recog_data.call_holds,
     );
 
     let (total_client_interruptions_duration, client_interruptions_count) = find_interruptions(
-        &recog_data.phrase_timestamps.employee,
-        &recog_data.phrase_timestamps.client,
+        &employee_intervals,
+        &client_intervals,
+        config.overlap_duration_eps,
     );
 
-    let total_employee_speech = total_speech_duration(&recog_data.phrase_timestamps.employee);
-    let total_client_speech = total_speech_duration(&recog_data.phrase_timestamps.client);
+    // overlapping segments of a participant are counted once
+    let employee_speech = merge_intervals(&employee_intervals);
+    let client_speech = merge_intervals(&client_intervals);
+    let total_employee_speech = total_speech_duration(&employee_speech);
+    let total_client_speech = total_speech_duration(&client_speech);
 
     let avg_employee_words_per_min = calculate_words_per_minute(
-        &recog_data.speech_recognition_result,
+        &transcriptions,
         total_employee_speech,
         ParticipantKind::Employee,
     );
     let avg_client_words_per_min = calculate_words_per_minute(
-        &recog_data.speech_recognition_result,
+        &transcriptions,
         total_client_speech,
         ParticipantKind::Client,
     );
@@ -193,16 +490,29 @@ pub fn process_metrics(recog_data: &RecognitionData) -> CallMetrics {
         .unwrap_or(0f32);
 
     let holds_count = recog_data.call_holds.silent.len() + recog_data.call_holds.music.len();
+    let active_call_duration = active_call_duration(call_duration, &recog_data.call_holds);
+    let (ratios_duration, employee_ratio_speech, client_ratio_speech) =
+        if config.ratios_by_active_duration {
+            (
+                active_call_duration,
+                speech_outside_holds(&employee_speech, &recog_data.call_holds),
+                speech_outside_holds(&client_speech, &recog_data.call_holds),
+            )
+        } else {
+            (call_duration, total_employee_speech, total_client_speech)
+        };
+
+    let emotions = process_emotions(recog_data, config);
 
     CallMetrics {
         task_id: Uuid::default(),
         call_duration,
-        time_to_answer: time_to_answer(&recog_data.phrase_timestamps.employee).unwrap_or(0.0),
+        time_to_answer: time_to_answer(&employee_intervals).unwrap_or(0.0),
         total_employee_speech,
         total_client_speech,
         employee_client_speech_ratio: speech_percentage(total_employee_speech, total_client_speech),
-        employee_speech_ratio: speech_percentage(total_employee_speech, call_duration),
-        client_speech_ratio: speech_percentage(total_client_speech, call_duration),
+        employee_speech_ratio: call_speech_share(employee_ratio_speech, ratios_duration),
+        client_speech_ratio: call_speech_share(client_ratio_speech, ratios_duration),
         call_holds_count: holds_count as i32,
         silence_pause_count,
         total_employee_silence,
@@ -210,11 +520,63 @@ pub fn process_metrics(recog_data: &RecognitionData) -> CallMetrics {
         total_client_interruptions_duration,
         avg_employee_words_per_min: avg_employee_words_per_min.round(),
         avg_client_words_per_min: avg_client_words_per_min.round(),
+        avg_employee_segment_len: avg_segment_len(&transcriptions, ParticipantKind::Employee),
+        avg_client_segment_len: avg_segment_len(&transcriptions, ParticipantKind::Client),
         employee_quality_score: 0,
         script_score: 0,
-        emotion_mode: call_emotional_mode(&recog_data.emotion_recognition_result),
-        emotion_start_mode: recog_data.emotion_recognition_result.first().cloned(),
-        emotion_end_mode: recog_data.emotion_recognition_result.last().cloned(),
+        overall_score: 0,
+        emotion_mode: emotions.mode,
+        emotion_start_mode: emotions.start_mode,
+        emotion_end_mode: emotions.end_mode,
+        extras: Json(CallMetricsExtras {
+            talk_balance_by_thirds: talk_balance_by_thirds(
+                &employee_intervals,
+                &client_intervals,
+                call_duration,
+            ),
+            speech_ratio_by_minute: speech_ratio_by_minute(
+                &employee_intervals,
+                &client_intervals,
+                call_duration,
+            ),
+            active_call_duration,
+            dead_air_ratio: dead_air_ratio(
+                &employee_intervals,
+                &client_intervals,
+                &recog_data.call_holds,
+                call_duration,
+            ),
+            employee_question_ratio: question_ratio(&transcriptions, ParticipantKind::Employee),
+            client_question_ratio: question_ratio(&transcriptions, ParticipantKind::Client),
+            sentiment: emotions.sentiment,
+            unscored: false,
+            // recorded when the call is scored
+            scoring: None,
+        }),
+    }
+}
+
+pub fn process_intervals(recog_data: &RecognitionData, config: &MetricsConfig) -> MetricsIntervals {
+    let employee_intervals = drop_short_segments(
+        &recog_data.phrase_timestamps.employee,
+        config.min_segment_duration,
+    );
+    let client_intervals = drop_short_segments(
+        &recog_data.phrase_timestamps.client,
+        config.min_segment_duration,
+    );
+
+    MetricsIntervals {
+        client_interruptions: find_interruption_intervals(
+            &employee_intervals,
+            &client_intervals,
+            config.overlap_duration_eps,
+        ),
+        silence_pauses: find_pause_intervals(
+            &employee_intervals,
+            &client_intervals,
+            &recog_data.call_holds,
+        ),
     }
 }
 
@@ -223,14 +585,19 @@ mod tests {
     use std::vec;
 
     use protocol::entity::{
-        speech_recog::{CallHolds, EmotionKind, Interval, SpeechRecognition},
+        speech_recog::{
+            CallHolds, EmotionKind, Interval, PhraseTimestamps, RecognitionData, SpeechRecognition,
+        },
         ParticipantKind,
     };
 
+    use crate::config::MetricsConfig;
     use crate::domain::audio_metrics::{
-        calculate_words_per_minute, call_emotional_mode, count_pauses, find_interruptions,
-        intervals_overlap, is_interruption, speech_percentage, time_to_answer,
-        total_speech_duration,
+        avg_segment_len, calculate_words_per_minute, call_emotional_mode, count_pauses,
+        drop_low_confidence_segments, find_interruptions, intervals_overlap, is_interruption,
+        merge_intervals, process_intervals, process_metrics, question_ratio, smooth_emotions,
+        speech_percentage, speech_ratio_by_minute, talk_balance_by_thirds, time_to_answer,
+        total_speech_duration, OVERLAP_DURATION_EPS,
     };
 
     #[test]
@@ -266,7 +633,7 @@ mod tests {
             start: 5.0,
             end: 15.0,
         };
-        assert!(is_interruption(&employee, &client));
+        assert!(is_interruption(&employee, &client, OVERLAP_DURATION_EPS));
 
         let employee = Interval {
             start: 5.0,
@@ -276,7 +643,7 @@ mod tests {
             start: 6.0,
             end: 12.0,
         };
-        assert!(!is_interruption(&employee, &client));
+        assert!(!is_interruption(&employee, &client, OVERLAP_DURATION_EPS));
 
         let employee = Interval {
             start: 0.0,
@@ -286,7 +653,7 @@ mod tests {
             start: 6.0,
             end: 10.0,
         };
-        assert!(!is_interruption(&employee, &client));
+        assert!(!is_interruption(&employee, &client, OVERLAP_DURATION_EPS));
     }
 
     #[test]
@@ -315,10 +682,402 @@ mod tests {
                 end: 20.0,
             },
         ];
-        let interruptions = find_interruptions(&employee_intervals, &client_intervals);
+        let interruptions =
+            find_interruptions(&employee_intervals, &client_intervals, OVERLAP_DURATION_EPS);
         assert_eq!(interruptions, (7.0, 2));
     }
 
+    #[test]
+    fn test_process_intervals() {
+        let employee_intervals = vec![
+            Interval {
+                start: 2.0,
+                end: 4.0,
+            },
+            Interval {
+                start: 9.0,
+                end: 12.0,
+            },
+            Interval {
+                start: 18.0,
+                end: 22.0,
+            },
+            Interval {
+                start: 30.0,
+                end: 32.0,
+            },
+        ];
+        let client_intervals = vec![
+            Interval {
+                start: 5.0,
+                end: 10.0,
+            },
+            Interval {
+                start: 15.0,
+                end: 20.0,
+            },
+        ];
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps {
+                client: client_intervals.clone(),
+                employee: employee_intervals.clone(),
+            },
+            speech_recognition_result: vec![],
+        };
+
+        let intervals = process_intervals(&recog_data, &MetricsConfig::default());
+        assert_eq!(
+            intervals.client_interruptions,
+            vec![employee_intervals[1].clone(), employee_intervals[2].clone()]
+        );
+        assert_eq!(
+            find_interruptions(&employee_intervals, &client_intervals, OVERLAP_DURATION_EPS),
+            (7.0, intervals.client_interruptions.len() as i32)
+        );
+        assert_eq!(
+            intervals.silence_pauses,
+            vec![Interval {
+                start: 22.0,
+                end: 30.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_emotion_smoothing() {
+        let emotions = vec![
+            EmotionKind::Neutral,
+            EmotionKind::Angry,
+            EmotionKind::Neutral,
+            EmotionKind::Sad,
+            EmotionKind::Sad,
+            EmotionKind::Sad,
+        ];
+        assert_eq!(smooth_emotions(&emotions, 1), emotions);
+        assert_eq!(
+            smooth_emotions(&emotions, 3),
+            vec![
+                EmotionKind::Neutral,
+                EmotionKind::Neutral,
+                EmotionKind::Neutral,
+                EmotionKind::Sad,
+                EmotionKind::Sad,
+                EmotionKind::Sad,
+            ]
+        );
+
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![
+                EmotionKind::Angry,
+                EmotionKind::Neutral,
+                EmotionKind::Neutral,
+                EmotionKind::Neutral,
+                EmotionKind::Positive,
+            ],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![],
+        };
+
+        let metrics = process_metrics(&recog_data, &MetricsConfig::default());
+        assert_eq!(metrics.emotion_start_mode, Some(EmotionKind::Angry));
+        assert_eq!(metrics.emotion_end_mode, Some(EmotionKind::Positive));
+
+        let config = MetricsConfig {
+            emotion_smoothing_window: Some(5),
+            ..Default::default()
+        };
+        let metrics = process_metrics(&recog_data, &config);
+        assert_eq!(metrics.emotion_start_mode, Some(EmotionKind::Neutral));
+        assert_eq!(metrics.emotion_end_mode, Some(EmotionKind::Neutral));
+        assert_eq!(metrics.emotion_mode, Some(EmotionKind::Neutral));
+    }
+
+    #[test]
+    fn test_drop_low_confidence_segments() {
+        let segment = |text: &str, confidence: Option<f32>| SpeechRecognition {
+            text: text.to_string(),
+            timestamps: Interval {
+                start: 0.0,
+                end: 1.0,
+            },
+            speaker: ParticipantKind::Employee,
+            confidence,
+        };
+        let mut recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![
+                segment("confident", Some(0.9)),
+                segment("noise", Some(0.2)),
+                segment("unknown", None),
+            ],
+        };
+
+        drop_low_confidence_segments(&mut recog_data, None);
+        assert_eq!(recog_data.speech_recognition_result.len(), 3);
+
+        drop_low_confidence_segments(&mut recog_data, Some(0.5));
+        let texts: Vec<&str> = recog_data
+            .speech_recognition_result
+            .iter()
+            .map(|recog| recog.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["confident", "unknown"]);
+    }
+
+    #[test]
+    fn test_min_segment_duration() {
+        let employee_segment = |start: f32, end: f32| SpeechRecognition {
+            text: "word".to_string(),
+            timestamps: Interval { start, end },
+            speaker: ParticipantKind::Employee,
+            confidence: None,
+        };
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps {
+                client: vec![Interval {
+                    start: 25.0,
+                    end: 30.0,
+                }],
+                employee: vec![
+                    Interval {
+                        start: 0.0,
+                        end: 2.0,
+                    },
+                    Interval {
+                        start: 8.0,
+                        end: 8.1,
+                    },
+                    Interval {
+                        start: 15.0,
+                        end: 20.0,
+                    },
+                ],
+            },
+            speech_recognition_result: vec![
+                employee_segment(0.0, 2.0),
+                employee_segment(8.0, 8.1),
+                employee_segment(15.0, 20.0),
+            ],
+        };
+
+        let metrics = process_metrics(&recog_data, &MetricsConfig::default());
+        assert_eq!(metrics.silence_pause_count, 2);
+
+        let config = MetricsConfig {
+            min_segment_duration: Some(0.2),
+            ..Default::default()
+        };
+        let metrics = process_metrics(&recog_data, &config);
+        assert_eq!(metrics.silence_pause_count, 1);
+        assert_eq!(metrics.total_employee_silence, 13.0);
+        assert_eq!(metrics.total_employee_speech, 7.0);
+        assert_eq!(metrics.avg_employee_words_per_min, 17.0);
+    }
+
+    #[test]
+    fn test_active_call_duration() {
+        let recog_data = RecognitionData {
+            call_holds: CallHolds {
+                music: vec![Interval {
+                    start: 20.0,
+                    end: 40.0,
+                }],
+                silent: vec![Interval {
+                    start: 35.0,
+                    end: 50.0,
+                }],
+            },
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps {
+                client: vec![Interval {
+                    start: 50.0,
+                    end: 80.0,
+                }],
+                employee: vec![Interval {
+                    start: 0.0,
+                    end: 20.0,
+                }],
+            },
+            speech_recognition_result: vec![],
+        };
+
+        let metrics = process_metrics(&recog_data, &MetricsConfig::default());
+        assert_eq!(metrics.call_duration, 80.0);
+        assert_eq!(metrics.extras.active_call_duration, 50.0);
+        assert_eq!(metrics.employee_speech_ratio, 25.0);
+
+        let config = MetricsConfig {
+            ratios_by_active_duration: true,
+            ..Default::default()
+        };
+        let metrics = process_metrics(&recog_data, &config);
+        assert_eq!(metrics.call_duration, 80.0);
+        assert_eq!(metrics.employee_speech_ratio, 40.0);
+        assert!((metrics.client_speech_ratio - 60.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_overlapping_speech_ratios() {
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps {
+                client: vec![Interval {
+                    start: 0.0,
+                    end: 10.0,
+                }],
+                employee: vec![
+                    Interval {
+                        start: 0.0,
+                        end: 8.0,
+                    },
+                    Interval {
+                        start: 2.0,
+                        end: 10.0,
+                    },
+                ],
+            },
+            speech_recognition_result: vec![],
+        };
+
+        let metrics = process_metrics(&recog_data, &MetricsConfig::default());
+        assert_eq!(metrics.call_duration, 10.0);
+        assert_eq!(metrics.total_employee_speech, 10.0);
+        assert_eq!(metrics.employee_speech_ratio, 100.0);
+        assert_eq!(metrics.client_speech_ratio, 100.0);
+        assert_eq!(metrics.employee_client_speech_ratio, 100.0);
+
+        // speech during a hold doesn't count against the active duration
+        let recog_data = RecognitionData {
+            call_holds: CallHolds {
+                music: vec![Interval {
+                    start: 5.0,
+                    end: 10.0,
+                }],
+                silent: vec![],
+            },
+            ..recog_data
+        };
+        let config = MetricsConfig {
+            ratios_by_active_duration: true,
+            ..Default::default()
+        };
+        let metrics = process_metrics(&recog_data, &config);
+        assert_eq!(metrics.extras.active_call_duration, 5.0);
+        assert_eq!(metrics.employee_speech_ratio, 100.0);
+        assert_eq!(metrics.client_speech_ratio, 100.0);
+    }
+
+    #[test]
+    fn test_dead_air_ratio() {
+        let recog_data = RecognitionData {
+            call_holds: CallHolds {
+                music: vec![Interval {
+                    start: 60.0,
+                    end: 80.0,
+                }],
+                silent: vec![],
+            },
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps {
+                client: vec![
+                    Interval {
+                        start: 5.0,
+                        end: 30.0,
+                    },
+                    Interval {
+                        start: 80.0,
+                        end: 100.0,
+                    },
+                ],
+                employee: vec![Interval {
+                    start: 0.0,
+                    end: 10.0,
+                }],
+            },
+            speech_recognition_result: vec![],
+        };
+
+        // silence in 30..60, the hold in 60..80 is not dead air
+        let metrics = process_metrics(&recog_data, &MetricsConfig::default());
+        assert_eq!(metrics.call_duration, 100.0);
+        assert!((metrics.extras.dead_air_ratio - 30.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_emotion_weights() {
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![
+                EmotionKind::Angry,
+                EmotionKind::Sad,
+                EmotionKind::Positive,
+                EmotionKind::Neutral,
+            ],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![],
+        };
+
+        let metrics = process_metrics(&recog_data, &MetricsConfig::default());
+        assert_eq!(metrics.extras.sentiment, -0.25);
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "emotion_weights": {
+                "positive": 2.0,
+                "neutral": 0.0,
+                "other": 0.0,
+                "sad": -1.0,
+                "angry": -3.0
+            }
+        }))
+        .unwrap();
+        let metrics = process_metrics(&recog_data, &config);
+        assert_eq!(metrics.extras.sentiment, -0.5);
+
+        let incomplete = serde_json::from_value::<MetricsConfig>(serde_json::json!({
+            "emotion_weights": { "positive": 1.0, "angry": -2.0 }
+        }));
+        assert!(incomplete.is_err());
+    }
+
+    #[test]
+    fn test_question_ratio() {
+        let segment = |text: &str, speaker: ParticipantKind| SpeechRecognition {
+            text: text.to_string(),
+            timestamps: Interval {
+                start: 0.0,
+                end: 1.0,
+            },
+            speaker,
+            confidence: None,
+        };
+        let transcriptions = vec![
+            segment("How can I help you?", ParticipantKind::Employee),
+            segment("My order is late.", ParticipantKind::Client),
+            segment("What is the order number? ", ParticipantKind::Employee),
+            segment("It is 42", ParticipantKind::Client),
+            segment("Thank you.", ParticipantKind::Employee),
+            segment("When will it arrive?", ParticipantKind::Client),
+            segment("Tomorrow.", ParticipantKind::Employee),
+        ];
+
+        assert_eq!(
+            question_ratio(&transcriptions, ParticipantKind::Employee),
+            50.0
+        );
+        assert!(
+            (question_ratio(&transcriptions, ParticipantKind::Client) - 100.0 / 3.0).abs() < 1e-3
+        );
+        assert_eq!(question_ratio(&[], ParticipantKind::Client), 0.0);
+    }
+
     #[test]
     fn test_time_to_answer() {
         let employee_intervals = vec![Interval {
@@ -360,6 +1119,122 @@ mod tests {
         assert_eq!(speech_percentage(total_speech, total_call_duration), 20.0);
     }
 
+    #[test]
+    fn test_merge_intervals() {
+        let intervals = vec![
+            Interval {
+                start: 5.0,
+                end: 8.0,
+            },
+            Interval {
+                start: 0.0,
+                end: 3.0,
+            },
+            Interval {
+                start: 2.0,
+                end: 4.0,
+            },
+        ];
+        assert_eq!(
+            merge_intervals(&intervals),
+            vec![
+                Interval {
+                    start: 0.0,
+                    end: 4.0,
+                },
+                Interval {
+                    start: 5.0,
+                    end: 8.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_talk_balance_by_thirds() {
+        assert!(talk_balance_by_thirds(&[], &[], 0.0).is_empty());
+
+        // employee dominates the opening, client the rest of the call
+        let employee_intervals = vec![
+            Interval {
+                start: 0.0,
+                end: 20.0,
+            },
+            Interval {
+                start: 15.0,
+                end: 25.0,
+            },
+            Interval {
+                start: 60.0,
+                end: 63.0,
+            },
+        ];
+        let client_intervals = vec![
+            Interval {
+                start: 25.0,
+                end: 30.0,
+            },
+            Interval {
+                start: 30.0,
+                end: 60.0,
+            },
+            Interval {
+                start: 63.0,
+                end: 90.0,
+            },
+        ];
+
+        let balance = talk_balance_by_thirds(&employee_intervals, &client_intervals, 90.0);
+        assert_eq!(balance.len(), 3);
+        assert!((balance[0].employee_speech_ratio - 250.0 / 3.0).abs() < 1e-3);
+        assert!((balance[0].client_speech_ratio - 50.0 / 3.0).abs() < 1e-3);
+        assert_eq!(balance[1].employee_speech_ratio, 0.0);
+        assert_eq!(balance[1].client_speech_ratio, 100.0);
+        assert_eq!(balance[2].employee_speech_ratio, 10.0);
+        assert_eq!(balance[2].client_speech_ratio, 90.0);
+    }
+
+    #[test]
+    fn test_speech_ratio_by_minute() {
+        assert!(speech_ratio_by_minute(&[], &[], 0.0).is_empty());
+
+        // the employee lets the client talk more as the call goes on
+        let employee_intervals = vec![
+            Interval {
+                start: 0.0,
+                end: 40.0,
+            },
+            Interval {
+                start: 60.0,
+                end: 90.0,
+            },
+            Interval {
+                start: 120.0,
+                end: 130.0,
+            },
+        ];
+        let client_intervals = vec![
+            Interval {
+                start: 40.0,
+                end: 60.0,
+            },
+            Interval {
+                start: 90.0,
+                end: 120.0,
+            },
+            Interval {
+                start: 130.0,
+                end: 170.0,
+            },
+        ];
+
+        let ratios = speech_ratio_by_minute(&employee_intervals, &client_intervals, 170.0);
+        assert_eq!(ratios.len(), 3);
+        assert_eq!(ratios[0], 200.0);
+        assert_eq!(ratios[1], 100.0);
+        assert_eq!(ratios[2], 25.0);
+    }
+
     #[test]
     fn test_count_pauses() {
         let employee_intervals = vec![
@@ -511,6 +1386,7 @@ mod tests {
             SpeechRecognition {
                 text: String::from("Hello this is a test."),
                 speaker: ParticipantKind::Employee,
+                confidence: None,
                 timestamps: Interval {
                     start: 0.0,
                     end: 20.0,
@@ -519,6 +1395,7 @@ mod tests {
             SpeechRecognition {
                 text: String::from("This is another test."),
                 speaker: ParticipantKind::Employee,
+                confidence: None,
                 timestamps: Interval {
                     start: 25.0,
                     end: 55.0,
@@ -527,6 +1404,7 @@ mod tests {
             SpeechRecognition {
                 text: String::from("And another one."),
                 speaker: ParticipantKind::Employee,
+                confidence: None,
                 timestamps: Interval {
                     start: 60.0,
                     end: 70.0,
@@ -538,6 +1416,33 @@ mod tests {
         assert_eq!(wpm, 12.0);
     }
 
+    #[test]
+    fn test_avg_segment_len() {
+        assert_eq!(avg_segment_len(&[], ParticipantKind::Employee), 0.0);
+
+        let segment = |start: f32, end: f32, speaker| SpeechRecognition {
+            text: "text".to_string(),
+            timestamps: Interval { start, end },
+            speaker,
+            confidence: None,
+        };
+        let transcriptions = vec![
+            segment(0.0, 2.0, ParticipantKind::Employee),
+            segment(2.5, 12.5, ParticipantKind::Client),
+            segment(13.0, 16.0, ParticipantKind::Employee),
+            segment(16.0, 23.0, ParticipantKind::Employee),
+        ];
+
+        assert_eq!(
+            avg_segment_len(&transcriptions, ParticipantKind::Employee),
+            4.0
+        );
+        assert_eq!(
+            avg_segment_len(&transcriptions, ParticipantKind::Client),
+            10.0
+        );
+    }
+
     #[test]
     fn test_call_emotional_mode() {
         let emotions = vec![];
@@ -5,7 +5,12 @@ use protocol::db::dictionary::{Dictionary, Phrase};
 use protocol::db::metrics::CallMetrics;
 use protocol::db::settings::{Settings, SettingsDictItem, SettingsItem};
 use protocol::db::task::TaskToDict;
-use protocol::entity::settings_metrics::{self};
+use protocol::entity::dictionary_test::PhraseMatch;
+use protocol::entity::settings_metrics::{self, TaskSettingsMetrics};
+use protocol::entity::speech_recog::{
+    CallHolds, Interval, PhraseTimestamps, RecognitionData, SpeechRecognition,
+};
+use sqlx::Acquire;
 use tracing::warn;
 use uuid::Uuid;
 
@@ -15,61 +20,391 @@ pub async fn process_metrics<C: Context>(
     cx: &C,
     id: Uuid,
     project_id: Uuid,
+    recog_data: &RecognitionData,
     call_metrics: &mut CallMetrics,
 ) -> anyhow::Result<Vec<TaskToDict>> {
-    let phrases = {
-        let mut conn = cx.get_db_conn().await?;
-        Phrase::list_all(&mut conn).await?
-    };
-    let dicts = {
+    let (task_to_dicts, _) =
+        calculate_metrics(cx, id, project_id, recog_data, call_metrics).await?;
+
+    Ok(task_to_dicts)
+}
+
+/// Phrases are matched against the transcript itself, so it doesn't have to be indexed
+pub async fn calculate_metrics<C: Context>(
+    cx: &C,
+    id: Uuid,
+    project_id: Uuid,
+    recog_data: &RecognitionData,
+    call_metrics: &mut CallMetrics,
+) -> anyhow::Result<(Vec<TaskToDict>, Vec<TaskSettingsMetrics>)> {
+    // the reads share one repeatable read snapshot, so dictionaries and the settings
+    // referencing them are consistent with each other
+    let (phrases, dicts, settings, settings_items, settings_dict_items) = {
         let mut conn = cx.get_db_conn().await?;
-        Dictionary::list(&mut conn).await?
+        let mut txn = conn.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *txn)
+            .await?;
+        let phrases = Phrase::list_all(&mut txn).await?;
+        let dicts = Dictionary::list(&mut txn).await?;
+        let settings = Settings::list_by_project_id(project_id, &mut txn).await?;
+        let settings_items = SettingsItem::list_by_project_id(project_id, &mut txn).await?;
+        let settings_dict_items =
+            SettingsDictItem::list_by_project_id(project_id, &mut txn).await?;
+        txn.commit().await?;
+        (
+            phrases,
+            dicts,
+            settings,
+            settings_items,
+            settings_dict_items,
+        )
     };
 
     let grouped: HashMap<i32, Vec<Phrase>> =
         auxiliary::group_by(phrases, |phrase| phrase.dictionary_id, |_| true);
 
-    let mut task_to_dicts: Vec<TaskToDict> = vec![];
-
-    for (dictionary_id, phrases) in grouped {
-        let dict = match dicts.iter().find(|dict| dict.id == dictionary_id) {
-            None => {
+    // the transcript is tokenized once, every phrase is matched against its tokens
+    let mut matcher = cx.indexer().transcript_matcher(recog_data)?;
+    let task_to_dicts = tokio::task::spawn_blocking(move || {
+        let mut task_to_dicts = vec![];
+        for (dictionary_id, phrases) in grouped {
+            let Some(dict) = dicts.iter().find(|dict| dict.id == dictionary_id) else {
                 warn!("skipping non-existing dictionary {dictionary_id}");
                 continue;
-            }
-            Some(dict) => dict,
-        };
+            };
+            let speaker = dict.searched_speaker();
+            let contains = phrases
+                .iter()
+                .any(|phrase| matcher.matches(&phrase.text, speaker));
 
-        let mut contains = false;
-        for phrase in phrases {
-            contains = cx
-                .indexer()
-                .search_phrase(id, &phrase.text, &dict.participant)
-                .await?;
-            if contains {
-                break;
-            }
+            task_to_dicts.push(TaskToDict {
+                task_id: id,
+                dictionary_id,
+                contains,
+            });
         }
+        task_to_dicts
+    })
+    .await?;
 
-        task_to_dicts.push(TaskToDict {
-            task_id: id,
-            dictionary_id,
-            contains,
-        })
-    }
-
-    let mut conn = cx.get_db_conn().await?;
-    let settings = Settings::list_by_project_id(project_id, &mut conn).await?;
-    let settings_items = SettingsItem::list_by_project_id(project_id, &mut conn).await?;
-    let settings_dict_items = SettingsDictItem::list_by_project_id(project_id, &mut conn).await?;
-
-    settings_metrics::calculate_settings_metrics(
+    // the api rescores the stored metrics with the rules they were scored with
+    let rules = cx.metrics_config().scoring;
+    call_metrics.extras.scoring = Some(rules);
+    let settings_metrics = settings_metrics::calculate_settings_metrics(
         task_to_dicts.clone(),
         call_metrics,
         settings,
         settings_items,
         settings_dict_items,
+        rules,
     )?;
 
-    Ok(task_to_dicts)
+    Ok((task_to_dicts, settings_metrics))
+}
+
+/// Matches every phrase of the dictionary against a sample text spoken by its participant
+pub async fn test_dictionary<C: Context>(
+    cx: &C,
+    dictionary_id: i32,
+    text: String,
+) -> anyhow::Result<Vec<PhraseMatch>> {
+    let (dict, phrases) = {
+        let mut conn = cx.get_db_conn().await?;
+        let dict = Dictionary::fetch_by_id(dictionary_id, &mut conn)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("dictionary {dictionary_id} not found"))?;
+        let phrases = Phrase::list_by_dict_id(dictionary_id, &mut conn).await?;
+        (dict, phrases)
+    };
+
+    let recog_data = RecognitionData {
+        call_holds: CallHolds::default(),
+        emotion_recognition_result: vec![],
+        phrase_timestamps: PhraseTimestamps::default(),
+        speech_recognition_result: vec![SpeechRecognition {
+            text,
+            timestamps: Interval {
+                start: 0.0,
+                end: 0.0,
+            },
+            speaker: dict.participant,
+            confidence: None,
+        }],
+    };
+
+    // the sample is matched like a processed call, without being indexed
+    let mut matcher = cx.indexer().transcript_matcher(&recog_data)?;
+    let matches = phrases
+        .into_iter()
+        .map(|phrase| PhraseMatch {
+            matched: matcher.matches(&phrase.text, dict.searched_speaker()),
+            phrase,
+        })
+        .collect();
+
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::{
+        db::{
+            dictionary::{Dictionary, Phrase},
+            metrics::CallMetrics,
+            settings::{Settings, SettingsDictItem, SettingsItem, SettingsItemKind, SettingsKind},
+        },
+        entity::{
+            speech_recog::{
+                CallHolds, Interval, PhraseTimestamps, RecognitionData, SpeechRecognition,
+            },
+            ParticipantKind,
+        },
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        config::SearchConfig, context::Context, indexer::TantivyIndexer,
+        test_helpers::context::TestContext,
+    };
+
+    use super::{calculate_metrics, test_dictionary};
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn keyword_metrics_by_dictionaries(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let task_id = Uuid::new_v4();
+        let project_id = Uuid::new_v4();
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        let mut dict_ids = vec![];
+        for (name, participant, phrase) in [
+            ("greeting", ParticipantKind::Employee, "good afternoon"),
+            ("farewell", ParticipantKind::Employee, "goodbye"),
+            ("complaint", ParticipantKind::Client, "good afternoon"),
+        ] {
+            let dict = Dictionary::insert(name.to_owned(), participant, &mut conn)
+                .await
+                .unwrap();
+            Phrase::bulk_insert(
+                vec![Phrase {
+                    id: 0,
+                    dictionary_id: dict.id,
+                    text: phrase.to_owned(),
+                }],
+                &mut conn,
+            )
+            .await
+            .unwrap();
+            dict_ids.push(dict.id);
+        }
+
+        let settings = Settings::insert(
+            Settings {
+                id: Uuid::default(),
+                project_id,
+                r#type: SettingsKind::Script,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        let settings_item = SettingsItem::insert(
+            SettingsItem {
+                id: Uuid::default(),
+                settings_id: settings.id,
+                settings_immutable: true,
+                name: "greeting".to_string(),
+                r#type: SettingsItemKind::Dictionary,
+                score_weight: 1,
+                graded_threshold: None,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        SettingsDictItem::bulk_insert(
+            vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: settings_item.id,
+                dictionary_id: dict_ids[0],
+                contains: true,
+            }],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        drop(conn);
+
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![SpeechRecognition {
+                text: "Good afternoon".to_string(),
+                timestamps: Interval {
+                    start: 0.0,
+                    end: 2.0,
+                },
+                speaker: ParticipantKind::Employee,
+                confidence: None,
+            }],
+        };
+        let mut metrics = CallMetrics::default();
+        let (mut task_to_dicts, settings_metrics) =
+            calculate_metrics(&cx, task_id, project_id, &recog_data, &mut metrics)
+                .await
+                .unwrap();
+
+        task_to_dicts.sort_by_key(|task_to_dict| task_to_dict.dictionary_id);
+        let contains: Vec<(i32, bool)> = task_to_dicts
+            .iter()
+            .filter(|task_to_dict| dict_ids.contains(&task_to_dict.dictionary_id))
+            .map(|task_to_dict| (task_to_dict.dictionary_id, task_to_dict.contains))
+            .collect();
+        assert_eq!(
+            contains,
+            vec![
+                (dict_ids[0], true),
+                (dict_ids[1], false),
+                (dict_ids[2], false)
+            ]
+        );
+        assert_eq!(settings_metrics.len(), 1);
+        assert_eq!(settings_metrics[0].total_score, 100);
+        assert_eq!(metrics.extras.scoring, Some(cx.metrics_config().scoring));
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn any_speaker_dictionary_matches_client(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let task_id = Uuid::new_v4();
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        let mut dict_ids = vec![];
+        for (name, any_speaker) in [("policy", true), ("employee_policy", false)] {
+            let dict = Dictionary::insert(name.to_owned(), ParticipantKind::Employee, &mut conn)
+                .await
+                .unwrap();
+            let dict = Dictionary::set_any_speaker(dict.id, any_speaker, &mut conn)
+                .await
+                .unwrap()
+                .unwrap();
+            Phrase::bulk_insert(
+                vec![Phrase {
+                    id: 0,
+                    dictionary_id: dict.id,
+                    text: "recorded line".to_owned(),
+                }],
+                &mut conn,
+            )
+            .await
+            .unwrap();
+            dict_ids.push(dict.id);
+        }
+        drop(conn);
+
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![SpeechRecognition {
+                text: "Is this a recorded line?".to_string(),
+                timestamps: Interval {
+                    start: 0.0,
+                    end: 2.0,
+                },
+                speaker: ParticipantKind::Client,
+                confidence: None,
+            }],
+        };
+        let mut metrics = CallMetrics::default();
+        let (mut task_to_dicts, _) =
+            calculate_metrics(&cx, task_id, Uuid::new_v4(), &recog_data, &mut metrics)
+                .await
+                .unwrap();
+
+        task_to_dicts.sort_by_key(|task_to_dict| task_to_dict.dictionary_id);
+        let contains: Vec<(i32, bool)> = task_to_dicts
+            .iter()
+            .filter(|task_to_dict| dict_ids.contains(&task_to_dict.dictionary_id))
+            .map(|task_to_dict| (task_to_dict.dictionary_id, task_to_dict.contains))
+            .collect();
+        assert_eq!(contains, vec![(dict_ids[0], true), (dict_ids[1], false)]);
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn dictionary_phrases_matched_against_sample(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        let dict = Dictionary::insert("greetings".to_owned(), ParticipantKind::Employee, &mut conn)
+            .await
+            .unwrap();
+        let phrases = ["good afternoon", "how can i help", "goodbye"]
+            .into_iter()
+            .map(|text| Phrase {
+                id: 0,
+                dictionary_id: dict.id,
+                text: text.to_owned(),
+            })
+            .collect();
+        Phrase::bulk_insert(phrases, &mut conn).await.unwrap();
+        drop(conn);
+
+        let matches = test_dictionary(
+            &cx,
+            dict.id,
+            "Good afternoon, how can I help you today?".to_owned(),
+        )
+        .await
+        .unwrap();
+
+        let mut matched: Vec<(&str, bool)> = matches
+            .iter()
+            .map(|phrase_match| (phrase_match.phrase.text.as_str(), phrase_match.matched))
+            .collect();
+        matched.sort();
+        assert_eq!(
+            matched,
+            vec![
+                ("good afternoon", true),
+                ("goodbye", false),
+                ("how can i help", true)
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn dictionary_sample_matched_with_configured_stemmer(pool: sqlx::PgPool) {
+        let search_config = SearchConfig {
+            stemmer_language: Some(tantivy::tokenizer::Language::English),
+            ..Default::default()
+        };
+        let mut cx = TestContext::new(pool)
+            .await
+            .with_indexer(TantivyIndexer::in_memory(&search_config).unwrap());
+        *cx.search_config_mut() = search_config;
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        let dict = Dictionary::insert("delays".to_owned(), ParticipantKind::Employee, &mut conn)
+            .await
+            .unwrap();
+        Phrase::bulk_insert(
+            vec![Phrase {
+                id: 0,
+                dictionary_id: dict.id,
+                text: "delays order".to_owned(),
+            }],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        drop(conn);
+
+        let matches = test_dictionary(&cx, dict.id, "Sorry about the delayed orders".to_owned())
+            .await
+            .unwrap();
+        assert!(matches[0].matched);
+    }
 }
@@ -1,2 +1,5 @@
+pub mod asr_tasks;
 pub mod audio_metrics;
 pub mod keywords;
+pub mod recompute;
+pub mod transcript;
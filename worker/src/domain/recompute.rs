@@ -0,0 +1,266 @@
+use protocol::{
+    db::metrics::CallMetrics,
+    entity::{settings_metrics::TaskSettingsMetrics, speech_recog::RecognitionData},
+};
+use uuid::Uuid;
+
+use crate::{context::Context, domain, indexer::Indexer};
+
+pub async fn recompute_metrics<C: Context>(
+    cx: &C,
+    project_id: Uuid,
+    recog_data: &RecognitionData,
+) -> anyhow::Result<(CallMetrics, Vec<TaskSettingsMetrics>)> {
+    let mut metrics = domain::audio_metrics::process_metrics(recog_data, cx.metrics_config());
+    let (_, settings_metrics) = domain::keywords::calculate_metrics(
+        cx,
+        Uuid::default(),
+        project_id,
+        recog_data,
+        &mut metrics,
+    )
+    .await?;
+
+    Ok((metrics, settings_metrics))
+}
+
+/// Re-derives the emotion fields of stored metrics from the indexed transcript
+pub async fn recompute_emotions<C: Context>(cx: &C, task_id: Uuid) -> anyhow::Result<CallMetrics> {
+    let payload = cx.indexer().load_transcript_payload(task_id).await?;
+    let recog_data: RecognitionData = serde_json::from_slice(&payload)?;
+
+    let mut conn = cx.get_db_conn().await?;
+    let mut metrics = CallMetrics::fetch_by_task_id(task_id, &mut conn).await?;
+    let emotions = domain::audio_metrics::process_emotions(&recog_data, cx.metrics_config());
+    metrics.emotion_mode = emotions.mode;
+    metrics.emotion_start_mode = emotions.start_mode;
+    metrics.emotion_end_mode = emotions.end_mode;
+    metrics.extras.sentiment = emotions.sentiment;
+    metrics.update_emotions(&mut conn).await?;
+
+    Ok(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::{
+        db::{
+            dictionary::{Dictionary, Phrase},
+            metrics::CallMetrics,
+            settings::{Settings, SettingsDictItem, SettingsItem, SettingsItemKind, SettingsKind},
+            task::TaskResultKind,
+        },
+        entity::{
+            speech_recog::{
+                CallHolds, EmotionKind, Interval, PhraseTimestamps, RecognitionData,
+                SpeechRecognition,
+            },
+            ParticipantKind,
+        },
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        config::SearchConfig,
+        context::Context,
+        indexer::{Indexer, TantivyIndexer},
+        test_helpers::{context::TestContext, insert_task},
+    };
+
+    use super::{recompute_emotions, recompute_metrics};
+
+    /// A script settings scored by the employee saying the phrase
+    async fn insert_phrase_settings(project_id: Uuid, phrase: &str, conn: &mut sqlx::PgConnection) {
+        let dict = Dictionary::insert(
+            "test_dict".to_owned(),
+            ParticipantKind::Employee,
+            &mut *conn,
+        )
+        .await
+        .unwrap();
+        Phrase::bulk_insert(
+            vec![Phrase {
+                id: 0,
+                dictionary_id: dict.id,
+                text: phrase.to_owned(),
+            }],
+            &mut *conn,
+        )
+        .await
+        .unwrap();
+
+        let settings = Settings::insert(
+            Settings {
+                id: Uuid::default(),
+                project_id,
+                r#type: SettingsKind::Script,
+            },
+            &mut *conn,
+        )
+        .await
+        .unwrap();
+        let settings_item = SettingsItem::insert(
+            SettingsItem {
+                id: Uuid::default(),
+                settings_id: settings.id,
+                settings_immutable: true,
+                name: "dict_test".to_string(),
+                r#type: SettingsItemKind::Dictionary,
+                score_weight: 1,
+                graded_threshold: None,
+            },
+            &mut *conn,
+        )
+        .await
+        .unwrap();
+        SettingsDictItem::bulk_insert(
+            vec![SettingsDictItem {
+                id: Uuid::default(),
+                settings_item_id: settings_item.id,
+                dictionary_id: dict.id,
+                contains: true,
+            }],
+            &mut *conn,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn recompute_without_persisting(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::new_v4();
+        let mut conn = cx.get_db_conn().await.unwrap();
+        insert_phrase_settings(project_id, "test phrase", &mut conn).await;
+
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps {
+                client: vec![],
+                employee: vec![Interval {
+                    start: 2.0,
+                    end: 10.0,
+                }],
+            },
+            speech_recognition_result: vec![SpeechRecognition {
+                text: "test phrase".to_string(),
+                timestamps: Interval {
+                    start: 2.0,
+                    end: 10.0,
+                },
+                speaker: ParticipantKind::Employee,
+                confidence: None,
+            }],
+        };
+
+        let (metrics, settings_metrics) = recompute_metrics(&cx, project_id, &recog_data)
+            .await
+            .expect("failed to recompute metrics");
+        assert_eq!(metrics.call_duration, 10.0);
+        assert_eq!(metrics.time_to_answer, 2.0);
+        assert_eq!(metrics.script_score, 100);
+        assert_eq!(settings_metrics.len(), 1);
+        assert_eq!(settings_metrics[0].total_score, 100);
+
+        let stored = CallMetrics::fetch_by_task_id(metrics.task_id, &mut conn).await;
+        assert!(stored.is_err());
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn recompute_with_configured_stemmer(pool: sqlx::PgPool) {
+        let search_config = SearchConfig {
+            stemmer_language: Some(tantivy::tokenizer::Language::English),
+            ..Default::default()
+        };
+        let mut cx = TestContext::new(pool.clone())
+            .await
+            .with_indexer(TantivyIndexer::in_memory(&search_config).unwrap());
+        *cx.search_config_mut() = search_config;
+        let project_id = Uuid::new_v4();
+        let mut conn = cx.get_db_conn().await.unwrap();
+        insert_phrase_settings(project_id, "delays order", &mut conn).await;
+
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![SpeechRecognition {
+                text: "Sorry about the delayed orders".to_string(),
+                timestamps: Interval {
+                    start: 0.0,
+                    end: 4.0,
+                },
+                speaker: ParticipantKind::Employee,
+                confidence: None,
+            }],
+        };
+
+        let (_, settings_metrics) = recompute_metrics(&cx, project_id, &recog_data)
+            .await
+            .expect("failed to recompute metrics");
+        assert_eq!(settings_metrics[0].total_score, 100);
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn recompute_emotions_only(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        let mut task = insert_task(Uuid::default(), &mut conn).await;
+        task.status = TaskResultKind::Ready;
+        task.update(&mut conn).await.unwrap();
+
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![
+                EmotionKind::Sad,
+                EmotionKind::Angry,
+                EmotionKind::Sad,
+            ],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![],
+        };
+        cx.indexer()
+            .index_speech_recog(task.id, &recog_data)
+            .await
+            .unwrap();
+        let metrics = CallMetrics {
+            task_id: task.id,
+            call_duration: 10.0,
+            script_score: 80,
+            emotion_mode: Some(EmotionKind::Sad),
+            emotion_start_mode: Some(EmotionKind::Sad),
+            emotion_end_mode: Some(EmotionKind::Sad),
+            ..Default::default()
+        };
+        CallMetrics::insert(metrics.clone(), &mut conn)
+            .await
+            .unwrap();
+
+        cx.metrics_config_mut()
+            .emotion_mapping
+            .insert(EmotionKind::Sad, EmotionKind::Other);
+        let recomputed = recompute_emotions(&cx, task.id)
+            .await
+            .expect("failed to recompute emotions");
+        assert_eq!(recomputed.emotion_mode, Some(EmotionKind::Other));
+        assert!((recomputed.extras.sentiment + 1.0 / 3.0).abs() < 1e-3);
+
+        let stored = CallMetrics::fetch_by_task_id(task.id, &mut conn)
+            .await
+            .unwrap();
+        let mut extras = metrics.extras.clone();
+        extras.sentiment = recomputed.extras.sentiment;
+        assert_eq!(
+            stored,
+            CallMetrics {
+                emotion_mode: Some(EmotionKind::Other),
+                emotion_start_mode: Some(EmotionKind::Other),
+                emotion_end_mode: Some(EmotionKind::Other),
+                extras,
+                ..metrics
+            }
+        );
+    }
+}
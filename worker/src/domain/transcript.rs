@@ -0,0 +1,106 @@
+use protocol::entity::speech_recog::{RecognitionData, SpeechRecognition};
+
+/// One `[speaker | start - end]: text` line per recognized phrase, rendered on demand
+pub fn text_chunks(recog_data: RecognitionData) -> impl Iterator<Item = String> {
+    recog_data
+        .speech_recognition_result
+        .into_iter()
+        .map(|recog_item| text_line(&recog_item))
+}
+
+/// SubRip subtitles with the speaker prepended to the cue text, one cue rendered at a time
+pub fn srt_chunks(recog_data: RecognitionData) -> impl Iterator<Item = String> {
+    recog_data
+        .speech_recognition_result
+        .into_iter()
+        .enumerate()
+        .map(|(idx, recog_item)| srt_cue(idx, &recog_item))
+}
+
+fn text_line(recog_item: &SpeechRecognition) -> String {
+    let speaker = recog_item.speaker;
+    let start_interval = format_seconds(recog_item.timestamps.start as i64);
+    let end_interval = format_seconds(recog_item.timestamps.end as i64);
+    let text = &recog_item.text;
+
+    format!("[{speaker} | {start_interval} - {end_interval}]: {text}\n")
+}
+
+fn srt_cue(idx: usize, recog_item: &SpeechRecognition) -> String {
+    let start = format_srt_timestamp(recog_item.timestamps.start);
+    let end = format_srt_timestamp(recog_item.timestamps.end);
+
+    format!(
+        "{}\n{start} --> {end}\n[{}] {}\n\n",
+        idx + 1,
+        recog_item.speaker,
+        recog_item.text
+    )
+}
+
+fn format_seconds(duration: i64) -> String {
+    let seconds = duration % 60;
+    let minutes = (duration / 60) % 60;
+    let hours = (duration / 60) / 60;
+    format!("{:0>2}:{:0>2}:{:0>2}", hours, minutes, seconds)
+}
+
+fn format_srt_timestamp(seconds: f32) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as i64;
+    format!("{},{:0>3}", format_seconds(millis / 1000), millis % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::entity::{
+        speech_recog::{CallHolds, Interval, PhraseTimestamps, SpeechRecognition},
+        ParticipantKind,
+    };
+
+    use super::*;
+
+    fn recog_data() -> RecognitionData {
+        RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![
+                SpeechRecognition {
+                    text: "hello".to_string(),
+                    timestamps: Interval {
+                        start: 0.0,
+                        end: 1.5,
+                    },
+                    speaker: ParticipantKind::Employee,
+                    confidence: None,
+                },
+                SpeechRecognition {
+                    text: "hi".to_string(),
+                    timestamps: Interval {
+                        start: 61.25,
+                        end: 3725.0,
+                    },
+                    speaker: ParticipantKind::Client,
+                    confidence: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn text_transcript() {
+        assert_eq!(
+            text_chunks(recog_data()).collect::<String>(),
+            "[Employee | 00:00:00 - 00:00:01]: hello\n[Client | 00:01:01 - 01:02:05]: hi\n"
+        );
+    }
+
+    #[test]
+    fn srt_transcript() {
+        assert_eq!(
+            srt_chunks(recog_data()).collect::<String>(),
+            "1\n00:00:00,000 --> 00:00:01,500\n[Employee] hello\n\n\
+            2\n00:01:01,250 --> 01:02:05,000\n[Client] hi\n\n"
+        );
+    }
+}
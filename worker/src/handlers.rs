@@ -1,36 +1,416 @@
+use std::convert::Infallible;
+
 use axum::{
     body::Body,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::{IntoResponse, Response},
-    routing::get,
-    Router,
+    routing::{get, post},
+    Json, Router,
 };
+use futures::stream;
 use http::StatusCode;
+use protocol::{
+    db::metrics::CallMetrics,
+    entity::{
+        dictionary_test::DictionaryTestRequest, settings_metrics::TaskSettingsMetrics,
+        speech_recog::RecognitionData,
+    },
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
 use uuid::Uuid;
 
 use crate::{
     context::{AppContext, Context},
-    indexer::Indexer,
+    domain,
+    indexer::{Indexer, IndexerError},
 };
 
 pub fn int_api_router(cx: AppContext) -> Router {
-    Router::new().nest(
-        "/api/v1",
-        Router::new()
-            .route("/transcript/:id", get(transcript))
-            .with_state(cx),
-    )
+    Router::new()
+        .route("/ready", get(ready))
+        .with_state(cx.clone())
+        .nest(
+            "/api/v1",
+            Router::new()
+                .route("/transcript/:id", get(transcript))
+                .route("/transcript/:id/intervals", get(intervals))
+                .route("/search", get(search))
+                .route("/recompute", post(recompute))
+                .route("/recompute/:id/emotions", post(recompute_emotions))
+                .route("/dictionaries/:id/test", post(test_dictionary))
+                .with_state(cx),
+        )
 }
 
-pub async fn transcript(State(cx): State<AppContext>, Path(id): Path<Uuid>) -> Response {
-    let payload = match cx.indexer().load_transcript_payload(id).await {
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    db: bool,
+    index: bool,
+    indexed_docs: Option<u64>,
+}
+
+pub async fn ready(State(cx): State<AppContext>) -> Response {
+    do_ready(&cx).await.into_response()
+}
+
+async fn do_ready<C: Context>(cx: &C) -> (StatusCode, Json<ReadinessReport>) {
+    let db = match cx.get_db_conn().await {
+        Ok(mut conn) => sqlx::query("SELECT 1").execute(&mut *conn).await.is_ok(),
+        Err(err) => {
+            warn!("Readiness db check failed: {err}");
+            false
+        }
+    };
+
+    let indexed_docs = match cx.indexer().health().await {
+        Ok(num_docs) => Some(num_docs),
+        Err(err) => {
+            warn!("Readiness index check failed: {err}");
+            None
+        }
+    };
+
+    let report = ReadinessReport {
+        db,
+        index: indexed_docs.is_some(),
+        indexed_docs,
+    };
+    let status = if report.db && report.index {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptFormat {
+    #[default]
+    Json,
+    Text,
+    Srt,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranscriptRequest {
+    /// Latest version when omitted
+    version: Option<u64>,
+    #[serde(default)]
+    format: TranscriptFormat,
+}
+
+pub async fn transcript(
+    State(cx): State<AppContext>,
+    Path(id): Path<Uuid>,
+    Query(request): Query<TranscriptRequest>,
+) -> Response {
+    do_transcript(&cx, id, request).await
+}
+
+async fn do_transcript<C: Context>(cx: &C, id: Uuid, request: TranscriptRequest) -> Response {
+    let payload = match request.version {
+        Some(version) => cx.indexer().load_transcript_version(id, version).await,
+        None => cx.indexer().load_transcript_payload(id).await,
+    };
+    let payload = match payload {
         Ok(bytes) => bytes,
         Err(err) => return err.into_response(),
     };
 
+    let (content_type, body) = match request.format {
+        TranscriptFormat::Json => ("application/json", Body::from(payload)),
+        format => {
+            let recog_data: RecognitionData = match serde_json::from_slice(&payload) {
+                Ok(recog_data) => recog_data,
+                Err(err) => {
+                    error!("Failed to deserialize transcript payload {id}: {err}");
+                    let body = Json(serde_json::json!({"error": format!("{err}")}));
+                    return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+                }
+            };
+            // long calls are rendered phrase by phrase into a chunked body
+            let (content_type, chunks): (_, Box<dyn Iterator<Item = String> + Send>) =
+                if format == TranscriptFormat::Srt {
+                    (
+                        "application/x-subrip; charset=utf-8",
+                        Box::new(domain::transcript::srt_chunks(recog_data)),
+                    )
+                } else {
+                    (
+                        "text/plain; charset=utf-8",
+                        Box::new(domain::transcript::text_chunks(recog_data)),
+                    )
+                };
+            let body = Body::from_stream(stream::iter(chunks.map(Ok::<_, Infallible>)));
+            (content_type, body)
+        }
+    };
+
     Response::builder()
         .status(StatusCode::OK)
-        .header(http::header::CONTENT_TYPE, "application/json")
-        .body(Body::from(payload))
+        .header(http::header::CONTENT_TYPE, content_type)
+        .body(body)
         .expect("http body bytes payload")
 }
+
+pub async fn intervals(State(cx): State<AppContext>, Path(id): Path<Uuid>) -> Response {
+    let payload = match cx.indexer().load_transcript_payload(id).await {
+        Ok(bytes) => bytes,
+        Err(err) => return err.into_response(),
+    };
+
+    let recog_data: RecognitionData = match serde_json::from_slice(&payload) {
+        Ok(recog_data) => recog_data,
+        Err(err) => {
+            error!("Failed to deserialize transcript payload {id}: {err}");
+            let body = Json(serde_json::json!({"error": format!("{err}")}));
+            return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+        }
+    };
+
+    Json(domain::audio_metrics::process_intervals(
+        &recog_data,
+        cx.metrics_config(),
+    ))
+    .into_response()
+}
+
+/// Upper bound of transcripts returned by a single search
+const MAX_SEARCH_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchRequest {
+    phrase: String,
+    /// Clamped to `1..=MAX_SEARCH_LIMIT`
+    limit: usize,
+}
+
+pub async fn search(
+    State(cx): State<AppContext>,
+    Query(request): Query<SearchRequest>,
+) -> Response {
+    let limit = request.limit.clamp(1, MAX_SEARCH_LIMIT);
+    match cx
+        .indexer()
+        .search_transcripts(&request.phrase, limit)
+        .await
+    {
+        Ok(transcripts) => Json(transcripts).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecomputeRequest {
+    project_id: Uuid,
+    recog_data: RecognitionData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecomputeResponse {
+    metrics: CallMetrics,
+    settings_metrics: Vec<TaskSettingsMetrics>,
+}
+
+pub async fn recompute(
+    State(cx): State<AppContext>,
+    Json(request): Json<RecomputeRequest>,
+) -> Response {
+    match domain::recompute::recompute_metrics(&cx, request.project_id, &request.recog_data).await {
+        Ok((metrics, settings_metrics)) => Json(RecomputeResponse {
+            metrics,
+            settings_metrics,
+        })
+        .into_response(),
+        Err(err) => {
+            error!("Failed to recompute metrics: {err:#}");
+            let body = Json(serde_json::json!({"error": format!("{err}")}));
+            (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+        }
+    }
+}
+
+pub async fn recompute_emotions(State(cx): State<AppContext>, Path(id): Path<Uuid>) -> Response {
+    do_recompute_emotions(&cx, id).await
+}
+
+async fn do_recompute_emotions<C: Context>(cx: &C, id: Uuid) -> Response {
+    match domain::recompute::recompute_emotions(cx, id).await {
+        Ok(metrics) => Json(metrics).into_response(),
+        Err(err) => {
+            // an unknown task has neither a transcript nor metrics
+            let not_found = matches!(
+                err.downcast_ref::<IndexerError>(),
+                Some(IndexerError::TranscriptNotFound(_))
+            ) || matches!(
+                err.downcast_ref::<sqlx::Error>(),
+                Some(sqlx::Error::RowNotFound)
+            );
+            let status = if not_found {
+                StatusCode::NOT_FOUND
+            } else {
+                error!("Failed to recompute emotions of {id}: {err:#}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            let body = Json(serde_json::json!({"error": format!("{err}")}));
+            (status, body).into_response()
+        }
+    }
+}
+
+pub async fn test_dictionary(
+    State(cx): State<AppContext>,
+    Path(id): Path<i32>,
+    Json(request): Json<DictionaryTestRequest>,
+) -> Response {
+    match domain::keywords::test_dictionary(&cx, id, request.text).await {
+        Ok(matches) => Json(matches).into_response(),
+        Err(err) => {
+            error!("Failed to test dictionary {id}: {err:#}");
+            let body = Json(serde_json::json!({"error": format!("{err}")}));
+            (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::entity::{
+        speech_recog::{CallHolds, Interval, PhraseTimestamps, SpeechRecognition},
+        ParticipantKind,
+    };
+
+    use crate::{indexer::MockIndexer, test_helpers::context::TestContext};
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn readiness(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let (status, Json(report)) = do_ready(&cx).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(report.db && report.index);
+        assert_eq!(report.indexed_docs, Some(0));
+
+        let mut indexer = MockIndexer::new();
+        indexer.expect_health().returning(|| {
+            Err(IndexerError::Index(tantivy::TantivyError::SystemError(
+                "corrupted index".to_string(),
+            )))
+        });
+        let cx = cx.with_indexer(indexer);
+
+        let (status, Json(report)) = do_ready(&cx).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(report.db);
+        assert!(!report.index);
+        assert_eq!(report.indexed_docs, None);
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn transcript_formats(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let id = Uuid::new_v4();
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![
+                SpeechRecognition {
+                    text: "test phrase".to_string(),
+                    timestamps: Interval {
+                        start: 1.0,
+                        end: 2.5,
+                    },
+                    speaker: ParticipantKind::Employee,
+                    confidence: None,
+                },
+                SpeechRecognition {
+                    text: "another phrase".to_string(),
+                    timestamps: Interval {
+                        start: 3.0,
+                        end: 7200.0,
+                    },
+                    speaker: ParticipantKind::Client,
+                    confidence: None,
+                },
+            ],
+        };
+        cx.indexer()
+            .index_speech_recog(id, &recog_data)
+            .await
+            .unwrap();
+
+        let body = |response: Response| async move {
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        let request = |format| TranscriptRequest {
+            version: None,
+            format,
+        };
+        let response = do_transcript(&cx, id, request(TranscriptFormat::Srt)).await;
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "application/x-subrip; charset=utf-8"
+        );
+        let srt = body(response).await;
+        assert!(srt.starts_with("1\n00:00:01,000 --> 00:00:02,500\n[Employee] test phrase\n\n"));
+        assert_eq!(
+            srt,
+            domain::transcript::srt_chunks(recog_data.clone()).collect::<String>()
+        );
+
+        // rendered bodies are streamed without a known length
+        let response = do_transcript(&cx, id, request(TranscriptFormat::Text)).await;
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert!(response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .is_none());
+        let text = body(response).await;
+        assert_eq!(
+            text,
+            domain::transcript::text_chunks(recog_data.clone()).collect::<String>()
+        );
+
+        let json = body(do_transcript(&cx, id, request(TranscriptFormat::default())).await).await;
+        assert_eq!(
+            serde_json::from_str::<RecognitionData>(&json).unwrap(),
+            recog_data
+        );
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn recompute_emotions_not_found(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+
+        let response = do_recompute_emotions(&cx, Uuid::new_v4()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // a transcript without stored metrics
+        let id = Uuid::new_v4();
+        let recog_data = RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![],
+        };
+        cx.indexer()
+            .index_speech_recog(id, &recog_data)
+            .await
+            .unwrap();
+        let response = do_recompute_emotions(&cx, id).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -7,33 +7,45 @@ use http::StatusCode;
 use mockall::{automock, predicate::*};
 use protocol::entity::{speech_recog::RecognitionData, ParticipantKind};
 use tantivy::{
-    collector::TopDocs,
+    collector::{Count, TopDocs},
     directory::{error::OpenDirectoryError, MmapDirectory, RamDirectory},
     doc,
-    query::{BooleanQuery, Occur, PhraseQuery, Query, TermQuery},
+    query::{
+        BooleanQuery, BoostQuery, EmptyQuery, Occur, PhraseQuery, Query, RegexQuery, TermQuery,
+    },
     schema::{
-        document::Value, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, STORED, STRING,
+        document::Value, Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, STORED,
+        STRING,
     },
-    tokenizer::{LowerCaser, SimpleTokenizer, TextAnalyzer},
-    Directory, Index, IndexReader, IndexWriter, TantivyDocument, TantivyError, Term,
+    tokenizer::{LowerCaser, SimpleTokenizer, Stemmer, TextAnalyzer},
+    Directory, Index, IndexReader, IndexWriter, Searcher, TantivyDocument, TantivyError, Term,
 };
 use thiserror::Error;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::config::{ReloadPolicy, SearchConfig};
+
 #[derive(Error, Debug)]
 pub enum IndexerError {
     #[error("Indexer error: {0}")]
     Index(#[source] TantivyError),
     #[error("Failed to open index directory error: {0}")]
     OpenDirectory(#[source] OpenDirectoryError),
+    #[error(
+        "Index path {0} is not a writable directory: {1}, create it and grant the worker write \
+        permission or point `index_path` to a writable volume"
+    )]
+    IndexPathUnavailable(String, #[source] std::io::Error),
     #[error("Indexer serialize error: {0}")]
     Ser(#[source] serde_json::Error),
     #[error("Indexer async block waiting error: {0}")]
     TaskJoin(#[source] tokio::task::JoinError),
     #[error("Requested transcript not found for doc id: {0}")]
     TranscriptNotFound(Uuid),
+    #[error("Requested transcript version {1} not found for doc id: {0}")]
+    TranscriptVersionNotFound(Uuid, u64),
     #[error("Payload extraction error for doc id: {0}")]
     Payload(Uuid),
 }
@@ -42,8 +54,14 @@ impl IntoResponse for IndexerError {
     fn into_response(self) -> axum::response::Response {
         error!("Service Error {}", self);
 
+        let status = match self {
+            IndexerError::TranscriptNotFound(_) | IndexerError::TranscriptVersionNotFound(..) => {
+                StatusCode::NOT_FOUND
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
         let body = Json(serde_json::json!({"error": format!("{self}")}));
-        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+        (status, body).into_response()
     }
 }
 
@@ -56,32 +74,126 @@ pub trait Indexer {
         recog_data: &RecognitionData,
     ) -> Result<(), IndexerError>;
 
-    async fn search_phrase(
+    /// Removes the latest transcript version, the archived versions are kept
+    async fn delete_transcript(&self, id: Uuid) -> Result<(), IndexerError>;
+
+    /// Tokenizes a transcript which isn't indexed exactly like the indexed one, so phrases
+    /// can be matched against it
+    fn transcript_matcher(
+        &self,
+        recog_data: &RecognitionData,
+    ) -> Result<TranscriptMatcher, IndexerError>;
+
+    /// Latest transcripts matching the phrase in the speech of either participant,
+    /// ranked by the configured field boosts
+    async fn search_transcripts(
         &self,
-        id: Uuid,
         phrase: &str,
-        speaker: &ParticipantKind,
-    ) -> Result<bool, IndexerError>;
+        limit: usize,
+    ) -> Result<Vec<RecognitionData>, IndexerError>;
 
     async fn load_transcript_payload(&self, id: Uuid) -> Result<Bytes, IndexerError>;
+
+    /// Loads a specific transcript version, versions start from 1 and grow on every reindex
+    async fn load_transcript_version(&self, id: Uuid, version: u64) -> Result<Bytes, IndexerError>;
+
+    /// Reopens the index segments and returns the number of indexed documents
+    async fn health(&self) -> Result<u64, IndexerError>;
 }
 
 #[derive(Clone)]
 pub struct TantivyIndexer {
     reader: IndexReader,
+    // the previous versions of a reindexed transcript are looked up through a reader
+    // reloaded on every commit, whatever the reload policy of the query path is
+    writer_reader: IndexReader,
     writer: Arc<Mutex<IndexWriter>>,
+    search_config: SearchConfig,
 }
 
 const CLIENT_TRANSCRIPT_FIELD: &str = "client_trancript";
 const EMPLOYEE_TRANSCRIPT_FIELD: &str = "employee_transcript";
 const PAYLOAD_FIELD: &str = "payload";
 const UUID_FIELD: &str = "uuid";
+/// Previous transcript versions are stored under `{uuid}@{version}` keys without
+/// transcript fields, so only the latest version is searchable
+const VERSION_SEPARATOR: char = '@';
+
+fn version_key(id: Uuid, version: u64) -> String {
+    format!("{id}{VERSION_SEPARATOR}{version}")
+}
+
+fn find_payload(searcher: &Searcher, key: &str, id: Uuid) -> Result<Option<Vec<u8>>, IndexerError> {
+    let schema = searcher.schema();
+    let id_field = schema.get_field(UUID_FIELD).map_err(IndexerError::Index)?;
+    let payload_field = schema
+        .get_field(PAYLOAD_FIELD)
+        .map_err(IndexerError::Index)?;
+
+    let query = TermQuery::new(
+        Term::from_field_text(id_field, key),
+        IndexRecordOption::Basic,
+    );
+
+    let mut top_docs = searcher
+        .search(&query, &TopDocs::with_limit(1))
+        .map_err(IndexerError::Index)?;
+
+    let Some((_, doc_address)) = top_docs.pop() else {
+        return Ok(None);
+    };
+
+    let retrieved_doc: TantivyDocument = searcher.doc(doc_address).map_err(IndexerError::Index)?;
+
+    let payload = retrieved_doc
+        .get_first(payload_field)
+        .and_then(|payload| payload.as_bytes())
+        .ok_or(IndexerError::Payload(id))?;
+
+    Ok(Some(payload.to_vec()))
+}
+
+fn archived_versions_count(searcher: &Searcher, id: Uuid) -> Result<u64, IndexerError> {
+    let id_field = searcher
+        .schema()
+        .get_field(UUID_FIELD)
+        .map_err(IndexerError::Index)?;
+    let query = RegexQuery::from_pattern(&format!("{id}{VERSION_SEPARATOR}[0-9]+"), id_field)
+        .map_err(IndexerError::Index)?;
+
+    searcher
+        .search(&query, &Count)
+        .map(|count| count as u64)
+        .map_err(IndexerError::Index)
+}
 
 impl TantivyIndexer {
-    pub fn new(index_path: &str) -> Result<Self, IndexerError> {
-        let create_dir_res = std::fs::create_dir(index_path);
-        info!("crating index dir: {:?}", create_dir_res);
+    pub fn new(index_path: &str, search_config: &SearchConfig) -> Result<Self, IndexerError> {
+        if cfg!(test) {
+            return Self::with_directory(Box::new(RamDirectory::create()), search_config);
+        }
+
+        Self::open(index_path, search_config)
+    }
+
+    /// Opens the index persisted at `index_path`, the directory is created when missing
+    pub fn open(index_path: &str, search_config: &SearchConfig) -> Result<Self, IndexerError> {
+        ensure_writable_dir(index_path)?;
+        let dir = MmapDirectory::open(index_path).map_err(IndexerError::OpenDirectory)?;
+
+        Self::with_directory(Box::new(dir), search_config)
+    }
+
+    /// Ephemeral index which is dropped together with the indexer, nothing is persisted
+    #[cfg(test)]
+    pub fn in_memory(search_config: &SearchConfig) -> Result<Self, IndexerError> {
+        Self::with_directory(Box::new(RamDirectory::create()), search_config)
+    }
 
+    fn with_directory(
+        dir: Box<dyn Directory>,
+        search_config: &SearchConfig,
+    ) -> Result<Self, IndexerError> {
         let mut schema_builder = Schema::builder();
 
         let text_field_indexing = TextFieldIndexing::default()
@@ -96,34 +208,153 @@ impl TantivyIndexer {
 
         let schema = schema_builder.build();
 
-        let dir = if cfg!(test) {
-            Box::new(RamDirectory::create()) as Box<dyn Directory>
-        } else {
-            Box::new(MmapDirectory::open(index_path).map_err(IndexerError::OpenDirectory)?)
-                as Box<dyn Directory>
-        };
-
         let index = Index::open_or_create(dir, schema).map_err(IndexerError::Index)?;
 
-        let tokenizer = TextAnalyzer::builder(SimpleTokenizer::default())
-            .filter(LowerCaser)
-            .build();
-        index.tokenizers().register("custom_tokenizer", tokenizer);
+        let tokenizer =
+            TextAnalyzer::builder(SimpleTokenizer::default()).filter_dynamic(LowerCaser);
+        let tokenizer = match search_config.stemmer_language {
+            Some(language) => tokenizer.filter_dynamic(Stemmer::new(language)),
+            None => tokenizer,
+        };
+        index
+            .tokenizers()
+            .register("custom_tokenizer", tokenizer.build());
 
         let index_writer: IndexWriter = index.writer(150_000_000).map_err(IndexerError::Index)?;
         let reader_builder = index.reader_builder();
+        let reload_policy = match search_config.reload_policy {
+            ReloadPolicy::Manual => tantivy::ReloadPolicy::Manual,
+            ReloadPolicy::OnCommitWithDelay => tantivy::ReloadPolicy::OnCommitWithDelay,
+        };
         let reader = reader_builder
+            .reload_policy(reload_policy)
+            .try_into()
+            .map_err(IndexerError::Index)?;
+        let writer_reader = index
+            .reader_builder()
             .reload_policy(tantivy::ReloadPolicy::Manual)
             .try_into()
             .map_err(IndexerError::Index)?;
 
         Ok(Self {
             reader,
+            writer_reader,
             writer: Arc::new(Mutex::new(index_writer)),
+            search_config: search_config.clone(),
+        })
+    }
+
+    /// Commits whatever is left in the writer and reloads the reader regardless of
+    /// the reload policy, run once on shutdown
+    pub async fn flush(&self) -> Result<(), IndexerError> {
+        let mut index_writer = self.writer.clone().lock_owned().await;
+        let reader = self.reader.clone();
+        let writer_reader = self.writer_reader.clone();
+
+        tokio::task::spawn_blocking(move || {
+            index_writer.commit().map_err(IndexerError::Index)?;
+            writer_reader.reload().map_err(IndexerError::Index)?;
+            reader.reload().map_err(IndexerError::Index)
         })
+        .await
+        .map_err(IndexerError::TaskJoin)?
     }
 }
 
+fn ensure_writable_dir(index_path: &str) -> Result<(), IndexerError> {
+    let unavailable = |err| IndexerError::IndexPathUnavailable(index_path.to_string(), err);
+
+    std::fs::create_dir_all(index_path).map_err(unavailable)?;
+    let probe_path = std::path::Path::new(index_path).join(".write_probe");
+    std::fs::write(&probe_path, []).map_err(unavailable)?;
+    std::fs::remove_file(&probe_path).map_err(unavailable)?;
+    info!("index dir {index_path} is writable");
+
+    Ok(())
+}
+
+fn speaker_transcript(recog_data: &RecognitionData, speaker: ParticipantKind) -> String {
+    recog_data
+        .speech_recognition_result
+        .iter()
+        .filter(|recog| recog.speaker == speaker)
+        .fold("".to_string(), |cur, next| cur + " " + &next.text)
+}
+
+/// Cuts the transcript to at most `max_chars` chars, returns whether it was cut
+fn truncate_transcript(transcript: &mut String, max_chars: usize) -> bool {
+    match transcript.char_indices().nth(max_chars) {
+        Some((byte_idx, _)) => {
+            transcript.truncate(byte_idx);
+            true
+        }
+        None => false,
+    }
+}
+
+fn tokenize(tokenizer: &mut TextAnalyzer, text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    tokenizer
+        .token_stream(text)
+        .process(&mut |token| tokens.push(token.text.clone()));
+    tokens
+}
+
+/// Tokens of both participants of a transcript, phrases are tokenized the same way
+pub struct TranscriptMatcher {
+    tokenizer: TextAnalyzer,
+    client_tokens: Vec<String>,
+    employee_tokens: Vec<String>,
+}
+
+impl TranscriptMatcher {
+    /// The phrase has to be said by the speaker, or by one of the participants when it's
+    /// `None`, it isn't matched across both
+    pub fn matches(&mut self, phrase: &str, speaker: Option<ParticipantKind>) -> bool {
+        let phrase_tokens = tokenize(&mut self.tokenizer, phrase);
+        if phrase_tokens.is_empty() {
+            return false;
+        }
+
+        let speakers = match speaker {
+            Some(speaker) => vec![speaker],
+            None => vec![ParticipantKind::Client, ParticipantKind::Employee],
+        };
+        speakers.into_iter().any(|speaker| {
+            let tokens = match speaker {
+                ParticipantKind::Client => &self.client_tokens,
+                ParticipantKind::Employee => &self.employee_tokens,
+            };
+            tokens
+                .windows(phrase_tokens.len())
+                .any(|window| window == phrase_tokens.as_slice())
+        })
+    }
+}
+
+/// Phrases go through the field tokenizer, so they are normalized exactly like the indexed text
+fn phrase_query(
+    searcher: &Searcher,
+    field: Field,
+    phrase: &str,
+) -> Result<Box<dyn Query>, IndexerError> {
+    let mut tokenizer = searcher
+        .index()
+        .tokenizer_for_field(field)
+        .map_err(IndexerError::Index)?;
+    let mut terms = vec![];
+    tokenizer
+        .token_stream(phrase)
+        .process(&mut |token| terms.push(Term::from_field_text(field, &token.text)));
+
+    let query = match terms.len() {
+        0 => Box::new(EmptyQuery) as Box<dyn Query>,
+        1 => Box::new(TermQuery::new(terms.remove(0), IndexRecordOption::Basic)) as Box<dyn Query>,
+        _ => Box::new(PhraseQuery::new(terms)) as Box<dyn Query>,
+    };
+    Ok(query)
+}
+
 #[async_trait]
 impl Indexer for TantivyIndexer {
     async fn index_speech_recog(
@@ -146,21 +377,34 @@ impl Indexer for TantivyIndexer {
             .map_err(IndexerError::Index)?;
 
         let payload_to_bytes = serde_json::to_vec(&recog_data).map_err(IndexerError::Ser)?;
-        let client_transcript = recog_data
-            .speech_recognition_result
-            .iter()
-            .filter(|recog| recog.speaker == ParticipantKind::Client)
-            .fold("".to_string(), |cur, next| cur + " " + &next.text);
-        let employee_transcript = recog_data
-            .speech_recognition_result
-            .iter()
-            .filter(|recog| recog.speaker == ParticipantKind::Employee)
-            .fold("".to_string(), |cur, next| cur + " " + &next.text);
+        let mut client_transcript = speaker_transcript(recog_data, ParticipantKind::Client);
+        let mut employee_transcript = speaker_transcript(recog_data, ParticipantKind::Employee);
+        if let Some(max_chars) = self.search_config.max_transcript_chars {
+            for transcript in [&mut client_transcript, &mut employee_transcript] {
+                if truncate_transcript(transcript, max_chars) {
+                    warn!("transcript of {id} exceeds {max_chars} chars and is truncated for indexing");
+                }
+            }
+        }
 
         let mut index_writer = self.writer.clone().lock_owned().await;
         let reader = self.reader.clone();
+        let writer_reader = self.writer_reader.clone();
+        let reload_policy = self.search_config.reload_policy;
 
         tokio::task::spawn_blocking(move || {
+            let searcher = writer_reader.searcher();
+            if let Some(previous_payload) = find_payload(&searcher, &id.to_string(), id)? {
+                let previous_version = archived_versions_count(&searcher, id)? + 1;
+                index_writer
+                    .add_document(doc!(
+                            id_field => version_key(id, previous_version),
+                            payload_field => previous_payload,
+                    ))
+                    .map_err(IndexerError::Index)?;
+                index_writer.delete_term(Term::from_field_text(id_field, &id.to_string()));
+            }
+
             index_writer
                 .add_document(doc!(
                         client_transcript_field => client_transcript,
@@ -175,93 +419,462 @@ impl Indexer for TantivyIndexer {
                 .map(|_| ())
                 .map_err(IndexerError::Index)?;
 
-            reader.reload().map_err(IndexerError::Index)
+            // still under the writer lock, so the next reindex sees this version
+            writer_reader.reload().map_err(IndexerError::Index)?;
+            match reload_policy {
+                ReloadPolicy::Manual => reader.reload().map_err(IndexerError::Index),
+                ReloadPolicy::OnCommitWithDelay => Ok(()),
+            }
+        })
+        .await
+        .map_err(IndexerError::TaskJoin)?
+    }
+
+    async fn delete_transcript(&self, id: Uuid) -> Result<(), IndexerError> {
+        let id_field = self
+            .reader
+            .searcher()
+            .schema()
+            .get_field(UUID_FIELD)
+            .map_err(IndexerError::Index)?;
+
+        let mut index_writer = self.writer.clone().lock_owned().await;
+        let reader = self.reader.clone();
+        let writer_reader = self.writer_reader.clone();
+        let reload_policy = self.search_config.reload_policy;
+
+        tokio::task::spawn_blocking(move || {
+            index_writer.delete_term(Term::from_field_text(id_field, &id.to_string()));
+            index_writer.commit().map_err(IndexerError::Index)?;
+
+            writer_reader.reload().map_err(IndexerError::Index)?;
+            match reload_policy {
+                ReloadPolicy::Manual => reader.reload().map_err(IndexerError::Index),
+                ReloadPolicy::OnCommitWithDelay => Ok(()),
+            }
         })
         .await
         .map_err(IndexerError::TaskJoin)?
     }
 
-    async fn search_phrase(
+    fn transcript_matcher(
         &self,
-        id: Uuid,
-        phrase: &str,
-        speaker: &ParticipantKind,
-    ) -> Result<bool, IndexerError> {
+        recog_data: &RecognitionData,
+    ) -> Result<TranscriptMatcher, IndexerError> {
+        // both transcript fields share the tokenizer
         let searcher = self.reader.searcher();
-        let schema = searcher.schema();
+        let field = searcher
+            .schema()
+            .get_field(CLIENT_TRANSCRIPT_FIELD)
+            .map_err(IndexerError::Index)?;
+        let mut tokenizer = searcher
+            .index()
+            .tokenizer_for_field(field)
+            .map_err(IndexerError::Index)?;
 
-        let id_field = schema.get_field(UUID_FIELD).map_err(IndexerError::Index)?;
-        let transcript_field = if speaker == &ParticipantKind::Client {
-            schema
-                .get_field(CLIENT_TRANSCRIPT_FIELD)
-                .map_err(IndexerError::Index)?
-        } else {
-            schema
-                .get_field(EMPLOYEE_TRANSCRIPT_FIELD)
-                .map_err(IndexerError::Index)?
+        let max_chars = self.search_config.max_transcript_chars;
+        let mut speaker_tokens = |speaker| {
+            let mut transcript = speaker_transcript(recog_data, speaker);
+            if let Some(max_chars) = max_chars {
+                truncate_transcript(&mut transcript, max_chars);
+            }
+            tokenize(&mut tokenizer, &transcript)
         };
+        let client_tokens = speaker_tokens(ParticipantKind::Client);
+        let employee_tokens = speaker_tokens(ParticipantKind::Employee);
 
-        let words: Vec<&str> = phrase.split_whitespace().collect();
-        let query = if words.len() > 1 {
-            let terms = words
-                .into_iter()
-                .map(|word| Term::from_field_text(transcript_field, word))
-                .collect();
-            Box::new(PhraseQuery::new(terms)) as Box<dyn Query>
-        } else {
-            Box::new(TermQuery::new(
-                Term::from_field_text(transcript_field, words.first().expect("non empty vec")),
-                IndexRecordOption::Basic,
-            )) as Box<dyn Query>
-        };
+        Ok(TranscriptMatcher {
+            tokenizer,
+            client_tokens,
+            employee_tokens,
+        })
+    }
 
-        let nested_query = BooleanQuery::new(vec![
-            (Occur::Must, query),
+    async fn search_transcripts(
+        &self,
+        phrase: &str,
+        limit: usize,
+    ) -> Result<Vec<RecognitionData>, IndexerError> {
+        // tantivy panics on a zero limit
+        if limit == 0 {
+            return Ok(vec![]);
+        }
+
+        let searcher = self.reader.searcher();
+        let schema = searcher.schema();
+
+        let payload_field = schema
+            .get_field(PAYLOAD_FIELD)
+            .map_err(IndexerError::Index)?;
+        let client_transcript_field = schema
+            .get_field(CLIENT_TRANSCRIPT_FIELD)
+            .map_err(IndexerError::Index)?;
+        let employee_transcript_field = schema
+            .get_field(EMPLOYEE_TRANSCRIPT_FIELD)
+            .map_err(IndexerError::Index)?;
+
+        let query = BooleanQuery::new(vec![
             (
-                Occur::Must,
-                Box::new(TermQuery::new(
-                    Term::from_field_text(id_field, &id.to_string()),
-                    IndexRecordOption::Basic,
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    phrase_query(&searcher, client_transcript_field, phrase)?,
+                    self.search_config.client_transcript_boost,
+                )) as Box<dyn Query>,
+            ),
+            (
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    phrase_query(&searcher, employee_transcript_field, phrase)?,
+                    self.search_config.employee_transcript_boost,
                 )),
             ),
         ]);
 
         let top_docs = searcher
-            .search(&nested_query, &TopDocs::with_limit(1))
+            .search(&query, &TopDocs::with_limit(limit))
             .map_err(IndexerError::Index)?;
 
-        Ok(!top_docs.is_empty())
+        // ids aren't stored, so the matched calls are identified by their payloads
+        let mut transcripts = vec![];
+        for (_, doc_address) in top_docs {
+            let retrieved_doc: TantivyDocument =
+                searcher.doc(doc_address).map_err(IndexerError::Index)?;
+            let Some(payload) = retrieved_doc
+                .get_first(payload_field)
+                .and_then(|payload| payload.as_bytes())
+            else {
+                continue;
+            };
+            transcripts.push(serde_json::from_slice(payload).map_err(IndexerError::Ser)?);
+        }
+
+        Ok(transcripts)
     }
 
     async fn load_transcript_payload(&self, id: Uuid) -> Result<Bytes, IndexerError> {
         let searcher = self.reader.searcher();
-        let schema = searcher.schema();
 
-        let id_field = schema.get_field(UUID_FIELD).map_err(IndexerError::Index)?;
-        let payload_field = schema
-            .get_field(PAYLOAD_FIELD)
-            .map_err(IndexerError::Index)?;
+        find_payload(&searcher, &id.to_string(), id)?
+            .map(Bytes::from)
+            .ok_or(IndexerError::TranscriptNotFound(id))
+    }
 
-        let query = TermQuery::new(
-            Term::from_field_text(id_field, &id.to_string()),
-            IndexRecordOption::Basic,
+    async fn load_transcript_version(&self, id: Uuid, version: u64) -> Result<Bytes, IndexerError> {
+        let searcher = self.reader.searcher();
+
+        if let Some(payload) = find_payload(&searcher, &version_key(id, version), id)? {
+            return Ok(Bytes::from(payload));
+        }
+
+        let latest_version = archived_versions_count(&searcher, id)? + 1;
+        if version != latest_version {
+            return Err(IndexerError::TranscriptVersionNotFound(id, version));
+        }
+
+        find_payload(&searcher, &id.to_string(), id)?
+            .map(Bytes::from)
+            .ok_or(IndexerError::TranscriptVersionNotFound(id, version))
+    }
+
+    async fn health(&self) -> Result<u64, IndexerError> {
+        let reader = self.reader.clone();
+
+        tokio::task::spawn_blocking(move || {
+            reader.reload().map_err(IndexerError::Index)?;
+            Ok(reader.searcher().num_docs())
+        })
+        .await
+        .map_err(IndexerError::TaskJoin)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::entity::speech_recog::{
+        CallHolds, Interval, PhraseTimestamps, SpeechRecognition,
+    };
+
+    use super::*;
+
+    /// Transcripts found by the phrase, either participant may have said it
+    async fn found(indexer: &TantivyIndexer, phrase: &str) -> Vec<RecognitionData> {
+        indexer.search_transcripts(phrase, 10).await.unwrap()
+    }
+
+    fn recog_data(text: &str, speaker: ParticipantKind) -> RecognitionData {
+        RecognitionData {
+            call_holds: CallHolds::default(),
+            emotion_recognition_result: vec![],
+            phrase_timestamps: PhraseTimestamps::default(),
+            speech_recognition_result: vec![SpeechRecognition {
+                text: text.to_string(),
+                timestamps: Interval {
+                    start: 0.0,
+                    end: 5.0,
+                },
+                speaker,
+                confidence: None,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn search_transcripts_boost() {
+        let employee_call = recog_data("test phrase", ParticipantKind::Employee);
+        let client_call = recog_data("test phrase", ParticipantKind::Client);
+
+        for (employee_boost, expected_first) in [(3.0, &employee_call), (0.3, &client_call)] {
+            let indexer = TantivyIndexer::new(
+                "",
+                &SearchConfig {
+                    employee_transcript_boost: employee_boost,
+                    client_transcript_boost: 1.0,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            indexer
+                .index_speech_recog(Uuid::new_v4(), &employee_call)
+                .await
+                .unwrap();
+            indexer
+                .index_speech_recog(Uuid::new_v4(), &client_call)
+                .await
+                .unwrap();
+
+            let found = indexer.search_transcripts("test phrase", 10).await.unwrap();
+            assert_eq!(found.len(), 2);
+            assert_eq!(&found[0], expected_first);
+            assert!(indexer
+                .search_transcripts("test phrase", 0)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn transcript_versions() {
+        let indexer = TantivyIndexer::in_memory(&SearchConfig::default()).unwrap();
+        let id = Uuid::new_v4();
+        let original = recog_data("original phrase", ParticipantKind::Employee);
+        let edited = recog_data("edited phrase", ParticipantKind::Employee);
+
+        indexer.index_speech_recog(id, &original).await.unwrap();
+        indexer.index_speech_recog(id, &edited).await.unwrap();
+
+        let payload = |bytes: Bytes| serde_json::from_slice::<RecognitionData>(&bytes).unwrap();
+        assert_eq!(
+            payload(indexer.load_transcript_payload(id).await.unwrap()),
+            edited
+        );
+        assert_eq!(
+            payload(indexer.load_transcript_version(id, 1).await.unwrap()),
+            original
+        );
+        assert_eq!(
+            payload(indexer.load_transcript_version(id, 2).await.unwrap()),
+            edited
         );
+        assert!(matches!(
+            indexer.load_transcript_version(id, 3).await,
+            Err(IndexerError::TranscriptVersionNotFound(_, 3))
+        ));
+
+        // only the latest version is searchable
+        assert_eq!(found(&indexer, "edited").await, vec![edited.clone()]);
+        assert!(found(&indexer, "original").await.is_empty());
+        assert_eq!(found(&indexer, "phrase").await, vec![edited]);
+    }
 
-        let mut top_docs = searcher
-            .search(&query, &TopDocs::with_limit(1))
-            .map_err(IndexerError::Index)?;
+    #[test]
+    fn unwritable_index_path() {
+        // a regular file in place of a parent directory can't be created even by root
+        let file_path = std::env::temp_dir().join(format!("index-file-{}", Uuid::new_v4()));
+        std::fs::write(&file_path, b"not a directory").unwrap();
+        let index_path = file_path.join("index");
+        let index_path = index_path.to_str().unwrap();
+
+        let res = TantivyIndexer::open(index_path, &SearchConfig::default());
+        std::fs::remove_file(&file_path).unwrap();
+
+        match res {
+            Err(IndexerError::IndexPathUnavailable(path, _)) => assert_eq!(path, index_path),
+            Err(err) => panic!("unexpected error: {err}"),
+            Ok(_) => panic!("index opened at unwritable path"),
+        }
+    }
+
+    #[tokio::test]
+    async fn deleted_transcript_keeps_versions() {
+        let indexer = TantivyIndexer::in_memory(&SearchConfig::default()).unwrap();
+        let id = Uuid::new_v4();
+        let original = recog_data("original phrase", ParticipantKind::Employee);
+        let edited = recog_data("edited phrase", ParticipantKind::Employee);
+
+        indexer.index_speech_recog(id, &original).await.unwrap();
+        indexer.index_speech_recog(id, &edited).await.unwrap();
+        indexer.delete_transcript(id).await.unwrap();
+
+        assert!(matches!(
+            indexer.load_transcript_payload(id).await,
+            Err(IndexerError::TranscriptNotFound(_))
+        ));
+        assert!(found(&indexer, "edited phrase").await.is_empty());
+        let archived = indexer.load_transcript_version(id, 1).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<RecognitionData>(&archived).unwrap(),
+            original
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_transcript() {
+        let indexer = TantivyIndexer::in_memory(&SearchConfig {
+            max_transcript_chars: Some(20),
+            ..Default::default()
+        })
+        .unwrap();
+        let id = Uuid::new_v4();
+        let text = format!("начало разговора {} конец", "слово ".repeat(1000));
+        let recog_data = recog_data(&text, ParticipantKind::Client);
+
+        indexer
+            .index_speech_recog(id, &recog_data)
+            .await
+            .expect("failed to index oversized transcript");
+
+        assert_eq!(found(&indexer, "начало").await, vec![recog_data.clone()]);
+        assert!(found(&indexer, "конец").await.is_empty());
+
+        let payload = indexer.load_transcript_payload(id).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<RecognitionData>(&payload).unwrap(),
+            recog_data
+        );
+    }
+
+    #[tokio::test]
+    async fn stemmed_phrase_matches() {
+        let indexer = TantivyIndexer::in_memory(&SearchConfig {
+            stemmer_language: Some(tantivy::tokenizer::Language::English),
+            ..Default::default()
+        })
+        .unwrap();
+        let id = Uuid::new_v4();
+        let recog_data = recog_data(
+            "The customer kept calling about delayed orders",
+            ParticipantKind::Client,
+        );
+        indexer.index_speech_recog(id, &recog_data).await.unwrap();
+
+        for (phrase, expected) in [
+            ("Calls", true),
+            ("delays, order", true),
+            ("orders delayed", false),
+            ("?", false),
+        ] {
+            assert_eq!(
+                !found(&indexer, phrase).await.is_empty(),
+                expected,
+                "{phrase}"
+            );
+        }
+    }
 
-        let (_, doc_address) = top_docs.pop().ok_or(IndexerError::TranscriptNotFound(id))?;
+    #[tokio::test]
+    async fn unindexed_phrase_matches_like_indexed() {
+        let indexer = TantivyIndexer::in_memory(&SearchConfig {
+            stemmer_language: Some(tantivy::tokenizer::Language::English),
+            ..Default::default()
+        })
+        .unwrap();
+        let recog_data = recog_data(
+            "The customer kept calling about delayed orders",
+            ParticipantKind::Client,
+        );
 
-        let retrieved_doc: TantivyDocument =
-            searcher.doc(doc_address).map_err(IndexerError::Index)?;
+        let mut matcher = indexer.transcript_matcher(&recog_data).unwrap();
+        let client = Some(ParticipantKind::Client);
+        for (phrase, speaker, expected) in [
+            ("Calls", client, true),
+            ("delays, order", client, true),
+            ("orders delayed", client, false),
+            ("?", client, false),
+            ("delays, order", None, true),
+            ("delays, order", Some(ParticipantKind::Employee), false),
+        ] {
+            assert_eq!(matcher.matches(phrase, speaker), expected, "{phrase}");
+        }
+        assert_eq!(indexer.health().await.unwrap(), 0);
+    }
 
-        let payload = retrieved_doc
-            .get_first(payload_field)
-            .ok_or(IndexerError::Payload(id))?;
+    #[tokio::test]
+    async fn indexed_docs_visible_by_reload_policy() {
+        let client = ParticipantKind::Client;
+
+        let indexer = TantivyIndexer::in_memory(&SearchConfig::default()).unwrap();
+        let id = Uuid::new_v4();
+        indexer
+            .index_speech_recog(id, &recog_data("hello world", client))
+            .await
+            .unwrap();
+        assert_eq!(found(&indexer, "hello").await.len(), 1);
+
+        let indexer = TantivyIndexer::in_memory(&SearchConfig {
+            reload_policy: ReloadPolicy::OnCommitWithDelay,
+            ..Default::default()
+        })
+        .unwrap();
+        let id = Uuid::new_v4();
+        indexer
+            .index_speech_recog(id, &recog_data("hello world", client))
+            .await
+            .unwrap();
+        // the background reload is not awaited, so force it instead of sleeping
+        indexer.reader.reload().unwrap();
+        assert_eq!(found(&indexer, "hello").await.len(), 1);
+    }
 
-        let payload = payload.as_bytes().ok_or(IndexerError::Payload(id))?;
+    #[tokio::test]
+    async fn reindex_archives_version_without_reload() {
+        let client = ParticipantKind::Client;
+        let indexer = TantivyIndexer::in_memory(&SearchConfig {
+            reload_policy: ReloadPolicy::OnCommitWithDelay,
+            ..Default::default()
+        })
+        .unwrap();
+        let id = Uuid::new_v4();
+        for text in ["original", "edited"] {
+            indexer
+                .index_speech_recog(id, &recog_data(text, client))
+                .await
+                .unwrap();
+        }
+
+        indexer.reader.reload().unwrap();
+        assert_eq!(indexer.reader.searcher().num_docs(), 2);
+        let archived: RecognitionData =
+            serde_json::from_slice(&indexer.load_transcript_version(id, 1).await.unwrap()).unwrap();
+        assert_eq!(archived.speech_recognition_result[0].text, "original");
+        assert!(found(&indexer, "original").await.is_empty());
+    }
 
-        Ok(Bytes::copy_from_slice(payload))
+    #[tokio::test]
+    async fn pending_docs_committed_on_flush() {
+        let indexer = TantivyIndexer::in_memory(&SearchConfig::default()).unwrap();
+        let id = Uuid::new_v4();
+        {
+            let index_writer = indexer.writer.lock().await;
+            let id_field = index_writer.index().schema().get_field(UUID_FIELD).unwrap();
+            index_writer
+                .add_document(doc!(id_field => id.to_string()))
+                .unwrap();
+        }
+        indexer.reader.reload().unwrap();
+        assert_eq!(indexer.reader.searcher().num_docs(), 0);
+
+        indexer.flush().await.unwrap();
+        assert_eq!(indexer.reader.searcher().num_docs(), 1);
     }
 }
@@ -7,6 +7,7 @@ use sqlx::{postgres::PgPoolOptions, PgPool};
 use tracing::{info, warn};
 
 use crate::config::DbConnectionConfig;
+use crate::context::Context as _;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -16,7 +17,13 @@ async fn main() -> Result<()> {
 
     let pool = create_pool(&config.db).await?;
 
-    let cx = crate::context::AppContext::new(&config, pool)?;
+    let cx = crate::context::AppContext::new(&config, pool)
+        .context("Failed to initialize worker context")?;
+
+    if std::env::args().nth(1).as_deref() == Some("seed") {
+        seed::seed(&cx).await?;
+        return Ok(());
+    }
 
     let broker_pipe_handle = tokio::spawn(crate::pipe::run_broker_pipe(
         cx.clone(),
@@ -43,6 +50,12 @@ async fn main() -> Result<()> {
 
     let (result, number, _) =
         future::select_all(vec![broker_pipe_handle, int_api_handle, signals_handle]).await;
+    // runs whichever handle finished first, the pipe ending included
+    match cx.indexer().flush().await {
+        Ok(()) => info!("Index committed on shutdown"),
+        Err(err) => warn!("Failed to commit index on shutdown: {err}"),
+    }
+
     let context = format!("Error from call ai handle #{number}");
     let result = result.context("Join error on handlers")?.context(context);
     if let Err(err) = &result {
@@ -74,5 +87,6 @@ mod domain;
 mod handlers;
 mod indexer;
 mod pipe;
+mod seed;
 #[cfg(test)]
 mod test_helpers;
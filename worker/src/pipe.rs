@@ -1,3 +1,5 @@
+use std::{future::Future, time::Duration};
+
 use anyhow::Context as _;
 use futures::{Stream, StreamExt};
 use lapin::{
@@ -12,15 +14,34 @@ use lapin::{
 use protocol::db::{
     metadata::CallMetadata,
     metrics::CallMetrics,
-    task::{Task, TaskResultKind, TaskToDict},
+    task::{Task, TaskFailureClass, TaskFailureKind, TaskResultKind, TaskToDict},
 };
 use sqlx::Acquire;
-use tracing::{debug, error};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+use crate::clients::speech_recognition::{
+    SpeechRecognitionClient, SpeechRecognitionClientError, TranscribeRequest,
+};
 use crate::context::Context;
-use crate::indexer::Indexer;
-use crate::{clients::speech_recognition::SpeechRecognitionClient, domain};
+use crate::domain;
+use crate::indexer::{Indexer, IndexerError};
+
+/// Must stay well below the stuck tasks timeout of the api server
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+#[error("task {0} was cancelled")]
+struct TaskCancelled(Uuid);
+
+#[derive(Error, Debug)]
+#[error("transcript of task {0} has no speech")]
+struct NoSpeech(Uuid);
+
+#[derive(Error, Debug)]
+#[error("call metadata of task {0} is unavailable: {1}")]
+struct InvalidMetadata(Uuid, #[source] sqlx::Error);
 
 async fn create_broker_connection() -> anyhow::Result<lapin::Connection> {
     let url = std::env::var("RABBITMQ_URL")?;
@@ -120,38 +141,215 @@ async fn process<C: Context>(delivery: &Delivery, cx: &C) -> anyhow::Result<()>
     };
     match process_task(&mut task, cx).await {
         Ok(_) => Ok(()),
+        // the cancelled status is already stored by the api
+        Err(err) if err.is::<TaskCancelled>() => {
+            info!("{err}, processing stopped");
+            Ok(())
+        }
         Err(err) => {
             task.status = TaskResultKind::Failed;
             task.failed_reason = Some(err.to_string());
+            task.failure_class = Some(classify_failure(&err));
+            task.failure_kind = Some(failure_kind(&err));
             let mut conn = cx.get_db_conn().await?;
-            Task::update(&task, &mut conn).await?;
+            if !task.update_unless_cancelled(&mut conn).await? {
+                info!("task {task_id} was cancelled, failure not stored: {err}");
+                return Ok(());
+            }
             Err(err)
         }
     }
 }
 
+/// Failures caused by unavailable dependencies are transient, anything else
+/// (bad input, rejected requests, broken data) won't be fixed by a retry
+fn classify_failure(err: &anyhow::Error) -> TaskFailureClass {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<SpeechRecognitionClientError>() {
+            return match err {
+                SpeechRecognitionClientError::Channel(_)
+                | SpeechRecognitionClientError::JobTimeout(_) => TaskFailureClass::Transient,
+                SpeechRecognitionClientError::ResponseStatus(status)
+                    if status.is_server_error()
+                        || *status == http::StatusCode::REQUEST_TIMEOUT
+                        || *status == http::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    TaskFailureClass::Transient
+                }
+                _ => TaskFailureClass::Permanent,
+            };
+        }
+
+        if let Some(err) = cause.downcast_ref::<sqlx::Error>() {
+            return match err {
+                sqlx::Error::Io(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed => TaskFailureClass::Transient,
+                _ => TaskFailureClass::Permanent,
+            };
+        }
+
+        if let Some(IndexerError::TaskJoin(_)) = cause.downcast_ref::<IndexerError>() {
+            return TaskFailureClass::Transient;
+        }
+    }
+
+    TaskFailureClass::Permanent
+}
+
+fn failure_kind(err: &anyhow::Error) -> TaskFailureKind {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<SpeechRecognitionClientError>() {
+            return match err {
+                SpeechRecognitionClientError::JobTimeout(_) => TaskFailureKind::AsrTimeout,
+                SpeechRecognitionClientError::Channel(err) if err.is_timeout() => {
+                    TaskFailureKind::AsrTimeout
+                }
+                SpeechRecognitionClientError::ResponseStatus(status)
+                    if *status == http::StatusCode::REQUEST_TIMEOUT
+                        || *status == http::StatusCode::GATEWAY_TIMEOUT =>
+                {
+                    TaskFailureKind::AsrTimeout
+                }
+                SpeechRecognitionClientError::ResponseStatus(_)
+                | SpeechRecognitionClientError::JobFailed(_, _)
+                | SpeechRecognitionClientError::De(_)
+                | SpeechRecognitionClientError::Recognition(_) => TaskFailureKind::AsrRejected,
+                SpeechRecognitionClientError::Channel(_)
+                | SpeechRecognitionClientError::BaseUrl(_) => TaskFailureKind::Unknown,
+            };
+        }
+
+        if cause.is::<IndexerError>() {
+            return TaskFailureKind::IndexError;
+        }
+
+        if cause.is::<InvalidMetadata>() {
+            return TaskFailureKind::InvalidMetadata;
+        }
+
+        if cause.is::<NoSpeech>() {
+            return TaskFailureKind::NoSpeech;
+        }
+    }
+
+    TaskFailureKind::Unknown
+}
+
+async fn ensure_not_cancelled<C: Context>(task_id: Uuid, cx: &C) -> anyhow::Result<()> {
+    let mut conn = cx.get_db_conn().await?;
+    let task = Task::get(&task_id, &mut conn).await?;
+    if task.status == TaskResultKind::Cancelled {
+        return Err(TaskCancelled(task_id).into());
+    }
+
+    Ok(())
+}
+
+/// Keeps the task from being swept as stuck while the transcription runs, however long
+/// the recognition service takes
+async fn with_heartbeat<C: Context, T>(task_id: Uuid, cx: &C, fut: impl Future<Output = T>) -> T {
+    tokio::pin!(fut);
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+        HEARTBEAT_INTERVAL,
+    );
+    loop {
+        tokio::select! {
+            output = &mut fut => return output,
+            _ = heartbeat.tick() => {
+                let result = match cx.get_db_conn().await {
+                    Ok(mut conn) => Task::heartbeat(&task_id, &mut conn).await.map_err(Into::into),
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    warn!("failed to refresh the start of task {task_id}: {err}");
+                }
+            }
+        }
+    }
+}
+
 async fn process_task<C: Context>(task: &mut Task, cx: &C) -> anyhow::Result<()> {
     let task_id: Uuid = task.id;
 
-    let metadata = {
+    let transcribe_request = {
         let mut conn = cx.get_db_conn().await?;
-        CallMetadata::get_by_task_id(task_id, &mut conn).await?
+        task.attempts = Task::increment_attempts(&task_id, &mut conn).await?;
+        let metadata = CallMetadata::get_by_task_id(task_id, &mut conn)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::RowNotFound
+                | sqlx::Error::ColumnDecode { .. }
+                | sqlx::Error::Decode(_) => InvalidMetadata(task_id, err).into(),
+                err => anyhow::Error::from(err),
+            })?;
+        let emotion_recognition = cx
+            .asr_tasks()
+            .emotion_recognition(task.project_id, &mut conn)
+            .await?;
+        TranscribeRequest::new(&metadata, emotion_recognition)
     };
 
-    let recog_data = cx
-        .speech_recognition()
-        .transcribe((&metadata).into())
-        .await?;
-
-    cx.indexer()
-        .index_speech_recog(task_id, &recog_data)
-        .await?;
+    ensure_not_cancelled(task_id, cx).await?;
+    let mut recog_data = with_heartbeat(
+        task_id,
+        cx,
+        cx.speech_recognition().transcribe(transcribe_request),
+    )
+    .await?;
+    domain::audio_metrics::drop_low_confidence_segments(
+        &mut recog_data,
+        cx.metrics_config().min_confidence,
+    );
+    let has_speech = recog_data
+        .speech_recognition_result
+        .iter()
+        .any(|segment| !segment.text.trim().is_empty());
+    if !has_speech {
+        return Err(NoSpeech(task_id).into());
+    }
 
-    let mut metrics = domain::audio_metrics::process_metrics(&recog_data);
+    ensure_not_cancelled(task_id, cx).await?;
+    let mut metrics = domain::audio_metrics::process_metrics(&recog_data, cx.metrics_config());
     metrics.task_id = task_id;
+    // failed tasks leave no searchable transcript unless configured otherwise
+    let keep_failed_transcripts = cx.search_config().keep_failed_transcripts;
+    if keep_failed_transcripts {
+        cx.indexer()
+            .index_speech_recog(task_id, &recog_data)
+            .await?;
+    }
     let task_to_dicts =
-        domain::keywords::process_metrics(cx, task_id, task.project_id, &mut metrics).await?;
+        domain::keywords::process_metrics(cx, task_id, task.project_id, &recog_data, &mut metrics)
+            .await?;
+
+    // with failed transcripts dropped the transcript is indexed only once the results are
+    // computed and is removed when they can't be stored, so a ready task always has one
+    if !keep_failed_transcripts {
+        cx.indexer()
+            .index_speech_recog(task_id, &recog_data)
+            .await?;
+    }
+    if let Err(err) = store_results(task, metrics, task_to_dicts, cx).await {
+        if !keep_failed_transcripts {
+            if let Err(delete_err) = cx.indexer().delete_transcript(task_id).await {
+                error!("failed to delete transcript of unstored task {task_id}: {delete_err}");
+            }
+        }
+        return Err(err);
+    }
+
+    Ok(())
+}
 
+async fn store_results<C: Context>(
+    task: &mut Task,
+    metrics: CallMetrics,
+    task_to_dicts: Vec<TaskToDict>,
+    cx: &C,
+) -> anyhow::Result<()> {
     let mut conn = cx.get_db_conn().await?;
     let mut txn = conn
         .begin()
@@ -160,10 +358,16 @@ async fn process_task<C: Context>(task: &mut Task, cx: &C) -> anyhow::Result<()>
 
     task.status = TaskResultKind::Ready;
     task.failed_reason = None;
+    task.failure_class = None;
+    task.failure_kind = None;
 
+    // the row lock taken by the conditional update keeps a concurrent cancel from
+    // being overwritten, a task cancelled meanwhile gets no metrics
+    if !task.update_unless_cancelled(&mut txn).await? {
+        return Err(TaskCancelled(task.id).into());
+    }
     CallMetrics::insert(metrics, &mut txn).await?;
     TaskToDict::bulk_insert(task_to_dicts, &mut txn).await?;
-    Task::update(task, &mut txn).await?;
 
     txn.commit().await.context("Transaction failed")?;
 
@@ -172,7 +376,6 @@ async fn process_task<C: Context>(task: &mut Task, cx: &C) -> anyhow::Result<()>
 
 #[cfg(test)]
 mod tests {
-    use chrono::DateTime;
     use protocol::{
         db::{
             dictionary::{Dictionary, Phrase},
@@ -186,7 +389,11 @@ mod tests {
         },
     };
 
-    use crate::test_helpers::context::TestContext;
+    use crate::{
+        config::SearchConfig,
+        indexer::{MockIndexer, TantivyIndexer},
+        test_helpers::{context::TestContext, insert_task},
+    };
 
     use super::*;
 
@@ -194,37 +401,8 @@ mod tests {
     async fn task_processing(pool: sqlx::PgPool) {
         let mut cx = TestContext::new(pool.clone()).await;
         let project_id = Uuid::new_v4();
-        let mut metadata = CallMetadata {
-            metadata_id: Uuid::default(),
-            call_id: 42,
-            performed_at: DateTime::default(),
-            uploaded_at: DateTime::default(),
-            file_hash: "test_hash".to_string(),
-            file_url: "s3://test.mp3".to_string(),
-            file_name: "test.mp3".to_string(),
-            duration: 100.0,
-            left_channel: ParticipantKind::Client,
-            right_channel: ParticipantKind::Employee,
-            client_name: "test_client".to_string(),
-            employee_name: "test_operator".to_string(),
-            inbound: true,
-        };
-
         let mut conn = cx.get_db_conn().await.unwrap();
-        let res = metadata.insert(&mut conn).await.unwrap();
-
-        let mut task = {
-            let task = Task {
-                id: Uuid::default(),
-                call_metadata_id: res.metadata_id,
-                status: TaskResultKind::Processing,
-                failed_reason: None,
-                project_id,
-            };
-
-            task.insert(&mut conn).await.unwrap()
-        };
-        metadata.metadata_id = task.call_metadata_id;
+        let mut task = insert_task(project_id, &mut conn).await;
 
         let dict_to_create = {
             let dict =
@@ -260,12 +438,13 @@ mod tests {
                 name: "dict_test".to_string(),
                 r#type: SettingsItemKind::Dictionary,
                 score_weight: 1,
+                graded_threshold: None,
             },
             &mut conn,
         )
         .await
         .unwrap();
-        let _ = SettingsDictItem::bulk_insert(
+        SettingsDictItem::bulk_insert(
             vec![SettingsDictItem {
                 id: Uuid::default(),
                 settings_item_id: settings_item.id,
@@ -277,9 +456,10 @@ mod tests {
         .await
         .unwrap();
 
+        // the project has no preferences enabling emotion recognition
         cx.speech_recog_client_mock()
             .expect_transcribe()
-            .with(mockall::predicate::always())
+            .withf(|request| request.tasks() == ["speech_recognition".to_string()])
             .returning(|_| {
                 Ok(RecognitionData {
                     call_holds: CallHolds::default(),
@@ -292,19 +472,258 @@ mod tests {
                             end: 10f32,
                         },
                         speaker: ParticipantKind::Employee,
+                        confidence: None,
                     }],
                 })
             });
 
-        let _ = process_task(&mut task, &cx)
+        process_task(&mut task, &cx)
             .await
             .expect("failed to process task");
         let task = Task::get(&task.id, &mut conn).await.unwrap();
         assert_eq!(task.status, TaskResultKind::Ready);
+        assert_eq!(task.attempts, 1);
 
         let metrics = CallMetrics::fetch_by_task_id(task.id, &mut conn)
             .await
             .unwrap();
         assert_eq!(metrics.script_score, 100);
+
+        // reprocessing stores a new task row which carries the attempts over
+        let mut reprocessed_task = task.clone();
+        reprocessed_task.status = TaskResultKind::Processing;
+        let mut reprocessed_task = reprocessed_task.insert(&mut conn).await.unwrap();
+        assert_eq!(reprocessed_task.attempts, 1);
+
+        process_task(&mut reprocessed_task, &cx)
+            .await
+            .expect("failed to reprocess task");
+        let reprocessed_task = Task::get(&reprocessed_task.id, &mut conn).await.unwrap();
+        assert_eq!(reprocessed_task.attempts, 2);
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn cancelled_task_skips_transcribe(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        let mut conn = cx.get_db_conn().await.unwrap();
+        let project_id = Uuid::new_v4();
+        let mut task = insert_task(project_id, &mut conn).await;
+
+        let mut cancelled_task = task.clone();
+        cancelled_task.status = TaskResultKind::Cancelled;
+        cancelled_task.failed_reason = Some("cancelled".to_string());
+        cancelled_task.update(&mut conn).await.unwrap();
+
+        cx.speech_recog_client_mock().expect_transcribe().never();
+
+        let err = process_task(&mut task, &cx)
+            .await
+            .expect_err("cancelled task was processed");
+        assert!(err.is::<TaskCancelled>());
+
+        let task = Task::get(&task.id, &mut conn).await.unwrap();
+        assert_eq!(task.status, TaskResultKind::Cancelled);
+
+        // an outcome computed before the cancel doesn't overwrite it
+        let mut finished = task.clone();
+        finished.status = TaskResultKind::Ready;
+        assert!(!finished.update_unless_cancelled(&mut conn).await.unwrap());
+        finished.status = TaskResultKind::Failed;
+        assert!(!finished.update_unless_cancelled(&mut conn).await.unwrap());
+        let task = Task::get(&task.id, &mut conn).await.unwrap();
+        assert_eq!(task.status, TaskResultKind::Cancelled);
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn failed_task_transcript_by_policy(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        let project_id = Uuid::new_v4();
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        // settings without items fail the metrics calculation after the transcript is ready
+        Settings::insert(
+            Settings {
+                id: Uuid::default(),
+                project_id,
+                r#type: SettingsKind::Script,
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        cx.speech_recog_client_mock()
+            .expect_transcribe()
+            .times(2)
+            .returning(|_| {
+                Ok(RecognitionData {
+                    call_holds: CallHolds::default(),
+                    emotion_recognition_result: vec![],
+                    phrase_timestamps: PhraseTimestamps::default(),
+                    speech_recognition_result: vec![SpeechRecognition {
+                        text: "test phrase".to_string(),
+                        timestamps: Interval {
+                            start: 0f32,
+                            end: 10f32,
+                        },
+                        speaker: ParticipantKind::Employee,
+                        confidence: None,
+                    }],
+                })
+            });
+
+        for keep_failed_transcripts in [false, true] {
+            cx.search_config_mut().keep_failed_transcripts = keep_failed_transcripts;
+            let mut task = insert_task(project_id, &mut conn).await;
+
+            process_task(&mut task, &cx)
+                .await
+                .expect_err("task with broken settings was processed");
+
+            let transcript = cx.indexer().load_transcript_payload(task.id).await;
+            assert_eq!(transcript.is_ok(), keep_failed_transcripts);
+        }
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn indexing_failure_leaves_task_unprocessed(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool.clone()).await;
+        let tantivy = TantivyIndexer::in_memory(&SearchConfig::default()).unwrap();
+        let mut indexer = MockIndexer::new();
+        indexer
+            .expect_transcript_matcher()
+            .returning(move |recog_data| tantivy.transcript_matcher(recog_data));
+        indexer.expect_index_speech_recog().returning(|_, _| {
+            Err(IndexerError::Index(tantivy::TantivyError::SystemError(
+                "index is full".to_string(),
+            )))
+        });
+        indexer.expect_delete_transcript().never();
+        let mut cx = cx.with_indexer(indexer);
+        cx.search_config_mut().keep_failed_transcripts = false;
+        cx.speech_recog_client_mock()
+            .expect_transcribe()
+            .returning(|_| {
+                Ok(RecognitionData {
+                    call_holds: CallHolds::default(),
+                    emotion_recognition_result: vec![],
+                    phrase_timestamps: PhraseTimestamps::default(),
+                    speech_recognition_result: vec![SpeechRecognition {
+                        text: "test phrase".to_string(),
+                        timestamps: Interval {
+                            start: 0f32,
+                            end: 10f32,
+                        },
+                        speaker: ParticipantKind::Employee,
+                        confidence: None,
+                    }],
+                })
+            });
+
+        let mut conn = cx.get_db_conn().await.unwrap();
+        let mut task = insert_task(Uuid::default(), &mut conn).await;
+
+        process_task(&mut task, &cx)
+            .await
+            .expect_err("task was processed without its transcript");
+
+        // the results are stored only once the transcript is indexed
+        let task = Task::get(&task.id, &mut conn).await.unwrap();
+        assert_eq!(task.status, TaskResultKind::Processing);
+        assert!(CallMetrics::fetch_by_task_id(task.id, &mut conn)
+            .await
+            .is_err());
+    }
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn speechless_transcript_fails(pool: sqlx::PgPool) {
+        let mut cx = TestContext::new(pool.clone()).await;
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        cx.speech_recog_client_mock()
+            .expect_transcribe()
+            .returning(|_| {
+                Ok(RecognitionData {
+                    call_holds: CallHolds::default(),
+                    emotion_recognition_result: vec![],
+                    phrase_timestamps: PhraseTimestamps::default(),
+                    speech_recognition_result: vec![SpeechRecognition {
+                        text: " ".to_string(),
+                        timestamps: Interval {
+                            start: 0f32,
+                            end: 10f32,
+                        },
+                        speaker: ParticipantKind::Employee,
+                        confidence: None,
+                    }],
+                })
+            });
+
+        let mut task = insert_task(Uuid::default(), &mut conn).await;
+
+        let err = process_task(&mut task, &cx)
+            .await
+            .expect_err("speechless task was processed");
+        assert_eq!(failure_kind(&err), TaskFailureKind::NoSpeech);
+        assert!(CallMetrics::fetch_by_task_id(task.id, &mut conn)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn failure_classification() {
+        let asr_timeout = anyhow::Error::from(SpeechRecognitionClientError::ResponseStatus(
+            http::StatusCode::GATEWAY_TIMEOUT,
+        ));
+        assert_eq!(classify_failure(&asr_timeout), TaskFailureClass::Transient);
+
+        let asr_rejected = anyhow::Error::from(SpeechRecognitionClientError::ResponseStatus(
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ))
+        .context("failed to transcribe");
+        assert_eq!(classify_failure(&asr_rejected), TaskFailureClass::Permanent);
+
+        let invalid_message =
+            anyhow::Error::from(serde_json::from_slice::<Uuid>(b"not a uuid").unwrap_err());
+        assert_eq!(
+            classify_failure(&invalid_message),
+            TaskFailureClass::Permanent
+        );
+
+        let db_unavailable = anyhow::Error::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(
+            classify_failure(&db_unavailable),
+            TaskFailureClass::Transient
+        );
+    }
+
+    #[test]
+    fn failure_kinds() {
+        let asr_rejected = anyhow::Error::from(SpeechRecognitionClientError::ResponseStatus(
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+        ))
+        .context("failed to transcribe");
+        assert_eq!(failure_kind(&asr_rejected), TaskFailureKind::AsrRejected);
+
+        let asr_timeout =
+            anyhow::Error::from(SpeechRecognitionClientError::JobTimeout("42".to_string()));
+        assert_eq!(failure_kind(&asr_timeout), TaskFailureKind::AsrTimeout);
+
+        let index_error = anyhow::Error::from(IndexerError::Payload(Uuid::new_v4()));
+        assert_eq!(failure_kind(&index_error), TaskFailureKind::IndexError);
+
+        let missing_metadata =
+            anyhow::Error::from(InvalidMetadata(Uuid::new_v4(), sqlx::Error::RowNotFound));
+        assert_eq!(
+            failure_kind(&missing_metadata),
+            TaskFailureKind::InvalidMetadata
+        );
+
+        let no_speech = anyhow::Error::from(NoSpeech(Uuid::new_v4()));
+        assert_eq!(failure_kind(&no_speech), TaskFailureKind::NoSpeech);
+        assert_eq!(classify_failure(&no_speech), TaskFailureClass::Permanent);
+
+        let db_unavailable = anyhow::Error::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(failure_kind(&db_unavailable), TaskFailureKind::Unknown);
     }
 }
@@ -0,0 +1,188 @@
+//! Demo transcripts for the tasks seeded by `api-server seed`, run with `worker seed`
+
+use protocol::{
+    db::{
+        dictionary::{Dictionary, Phrase},
+        metadata::CallMetadata,
+        task::{Task, TaskToDict, DEMO_TASK_CREATOR},
+    },
+    entity::{
+        speech_recog::{CallHolds, Interval, PhraseTimestamps, RecognitionData, SpeechRecognition},
+        ParticipantKind,
+    },
+};
+use tracing::info;
+
+use crate::{
+    context::Context,
+    indexer::{Indexer, IndexerError},
+};
+
+const DEMO_GREETING: &str = "добрый день, чем могу помочь";
+const DEMO_FAREWELL: &str = "спасибо за звонок, всего доброго";
+
+/// Indexes a transcript for every ready demo task which has none, so their transcripts
+/// and intervals can be served, tasks already indexed are left untouched
+pub async fn seed<C: Context>(cx: &C) -> anyhow::Result<usize> {
+    let mut conn = cx.get_db_conn().await?;
+    let tasks = Task::list_ready_by_creator(DEMO_TASK_CREATOR, &mut conn).await?;
+    let dictionaries = Dictionary::list(&mut conn).await?;
+
+    let mut indexed = 0;
+    for task in tasks {
+        match cx.indexer().load_transcript_payload(task.id).await {
+            Ok(_) => continue,
+            Err(IndexerError::TranscriptNotFound(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        // the transcript contains a phrase of every dictionary the task is marked to contain
+        let mut lines = vec![(ParticipantKind::Employee, DEMO_GREETING.to_string())];
+        for task_to_dict in TaskToDict::list_by_task_id(task.id, &mut conn).await? {
+            let dict = dictionaries
+                .iter()
+                .find(|dict| dict.id == task_to_dict.dictionary_id);
+            let Some(dict) = dict.filter(|_| task_to_dict.contains) else {
+                continue;
+            };
+            let phrases = Phrase::list_by_dict_id(dict.id, &mut conn).await?;
+            if let Some(phrase) = phrases.into_iter().next() {
+                lines.push((dict.participant, phrase.text));
+            }
+        }
+        lines.push((ParticipantKind::Employee, DEMO_FAREWELL.to_string()));
+
+        let metadata = CallMetadata::get_by_task_id(task.id, &mut conn).await?;
+        let recog_data = demo_transcript(lines, metadata.duration);
+        cx.indexer()
+            .index_speech_recog(task.id, &recog_data)
+            .await?;
+        indexed += 1;
+    }
+
+    info!("Demo transcripts seeded: {indexed} indexed");
+
+    Ok(indexed)
+}
+
+/// Spreads the lines evenly over the call, each one spoken in the first half of its slot
+fn demo_transcript(lines: Vec<(ParticipantKind, String)>, duration: f32) -> RecognitionData {
+    let slot = duration / lines.len() as f32;
+    let speech_recognition_result = lines
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (speaker, text))| SpeechRecognition {
+            text,
+            timestamps: Interval {
+                start: slot * idx as f32,
+                end: slot * (idx as f32 + 0.5),
+            },
+            speaker,
+            confidence: None,
+        })
+        .collect();
+
+    RecognitionData {
+        call_holds: CallHolds::default(),
+        emotion_recognition_result: vec![],
+        phrase_timestamps: PhraseTimestamps::default(),
+        speech_recognition_result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::db::task::TaskResultKind;
+    use uuid::Uuid;
+
+    use crate::test_helpers::{context::TestContext, insert_task};
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../api-server/migrations")]
+    async fn seed_indexes_demo_transcripts(pool: sqlx::PgPool) {
+        let cx = TestContext::new(pool).await;
+        let mut conn = cx.get_db_conn().await.unwrap();
+
+        let dict = Dictionary::insert(
+            "demo_greetings".to_string(),
+            ParticipantKind::Client,
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        Phrase::bulk_insert(
+            vec![Phrase {
+                id: 0,
+                dictionary_id: dict.id,
+                text: "верните деньги".to_string(),
+            }],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        let mut task_ids = vec![];
+        for created_by in [Some(DEMO_TASK_CREATOR), None] {
+            let mut task = insert_task(Uuid::default(), &mut conn).await;
+            task.status = TaskResultKind::Ready;
+            task.update(&mut conn).await.unwrap();
+            sqlx::query("UPDATE task SET created_by = $2 WHERE id = $1")
+                .bind(task.id)
+                .bind(created_by)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+            TaskToDict::insert(
+                TaskToDict {
+                    task_id: task.id,
+                    dictionary_id: dict.id,
+                    contains: true,
+                },
+                &mut conn,
+            )
+            .await
+            .unwrap();
+            task_ids.push(task.id);
+        }
+        let duration = CallMetadata::get_by_task_id(task_ids[0], &mut conn)
+            .await
+            .unwrap()
+            .duration;
+        drop(conn);
+
+        assert_eq!(seed(&cx).await.expect("failed to seed transcripts"), 1);
+        assert_eq!(seed(&cx).await.expect("failed to reseed transcripts"), 0);
+
+        let payload = cx
+            .indexer()
+            .load_transcript_payload(task_ids[0])
+            .await
+            .expect("demo transcript is indexed");
+        let recog_data: RecognitionData = serde_json::from_slice(&payload).unwrap();
+        let lines = recog_data
+            .speech_recognition_result
+            .iter()
+            .map(|recog| (recog.speaker, recog.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                (ParticipantKind::Employee, DEMO_GREETING),
+                (ParticipantKind::Client, "верните деньги"),
+                (ParticipantKind::Employee, DEMO_FAREWELL),
+            ]
+        );
+        assert!(recog_data
+            .speech_recognition_result
+            .iter()
+            .all(|recog| recog.timestamps.end <= duration));
+
+        let err = cx
+            .indexer()
+            .load_transcript_payload(task_ids[1])
+            .await
+            .expect_err("only demo tasks are seeded");
+        assert!(matches!(err, IndexerError::TranscriptNotFound(_)));
+    }
+}
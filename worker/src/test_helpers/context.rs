@@ -3,23 +3,70 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use sqlx::{pool::PoolConnection, PgPool, Postgres};
 
-use crate::{clients::speech_recognition::MockSpeechRecognitionClient, indexer::TantivyIndexer};
+use crate::{
+    clients::speech_recognition::MockSpeechRecognitionClient,
+    config::{AsrTasksConfig, MetricsConfig, SearchConfig},
+    domain::asr_tasks::AsrTasks,
+    indexer::{Indexer, TantivyIndexer},
+};
 
-#[derive(Clone)]
-pub struct TestContext {
+pub struct TestContext<I = TantivyIndexer> {
     db: PgPool,
-    indexer: TantivyIndexer,
+    indexer: Arc<I>,
     speech_recognition: Arc<MockSpeechRecognitionClient>,
+    metrics_config: MetricsConfig,
+    search_config: SearchConfig,
+    asr_tasks: Arc<AsrTasks>,
+}
+
+impl<I> Clone for TestContext<I> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            indexer: self.indexer.clone(),
+            speech_recognition: self.speech_recognition.clone(),
+            metrics_config: self.metrics_config.clone(),
+            search_config: self.search_config.clone(),
+            asr_tasks: self.asr_tasks.clone(),
+        }
+    }
 }
 
 impl TestContext {
     pub async fn new(db: PgPool) -> Self {
         Self {
             db,
-            indexer: TantivyIndexer::new("").expect("failed to create indexer"),
+            indexer: Arc::new(
+                TantivyIndexer::new("", &SearchConfig::default())
+                    .expect("failed to create indexer"),
+            ),
             speech_recognition: Arc::new(MockSpeechRecognitionClient::new()),
+            metrics_config: MetricsConfig::default(),
+            search_config: SearchConfig::default(),
+            asr_tasks: Arc::new(AsrTasks::new(&AsrTasksConfig::default())),
         }
     }
+}
+
+impl<I> TestContext<I> {
+    pub fn with_indexer<J>(self, indexer: J) -> TestContext<J> {
+        TestContext {
+            db: self.db,
+            indexer: Arc::new(indexer),
+            speech_recognition: self.speech_recognition,
+            metrics_config: self.metrics_config,
+            search_config: self.search_config,
+            asr_tasks: self.asr_tasks,
+        }
+    }
+
+    pub fn metrics_config_mut(&mut self) -> &mut MetricsConfig {
+        &mut self.metrics_config
+    }
+
+    pub fn search_config_mut(&mut self) -> &mut SearchConfig {
+        &mut self.search_config
+    }
 
     pub fn speech_recog_client_mock(&mut self) -> &mut MockSpeechRecognitionClient {
         Arc::get_mut(&mut self.speech_recognition).unwrap()
@@ -27,8 +74,8 @@ impl TestContext {
 }
 
 #[async_trait]
-impl crate::context::Context for TestContext {
-    type Indexer = TantivyIndexer;
+impl<I: Indexer + Send + Sync> crate::context::Context for TestContext<I> {
+    type Indexer = I;
     type SpeechRecognitionClient = MockSpeechRecognitionClient;
 
     fn indexer(&self) -> &Self::Indexer {
@@ -39,6 +86,18 @@ impl crate::context::Context for TestContext {
         &self.speech_recognition
     }
 
+    fn metrics_config(&self) -> &MetricsConfig {
+        &self.metrics_config
+    }
+
+    fn search_config(&self) -> &SearchConfig {
+        &self.search_config
+    }
+
+    fn asr_tasks(&self) -> &AsrTasks {
+        &self.asr_tasks
+    }
+
     async fn get_db_conn(&self) -> anyhow::Result<PoolConnection<Postgres>> {
         let conn = self.db.acquire().await?;
         Ok(conn)
@@ -1 +1,3 @@
 pub mod context;
+
+pub use protocol::test_helpers::insert_task;